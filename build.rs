@@ -0,0 +1,7 @@
+fn main() {
+    // `MockHexchat` needs C for Hexchat's variadic functions.
+    #[cfg(feature = "testing")]
+    cc::Build::new().file("src/mock_variadic.c")
+                    .compile("hexchat_api_mock");
+    println!("cargo:rerun-if-changed=src/mock_variadic.c");
+}
@@ -0,0 +1,195 @@
+//! Procedural macros for the `hexchat-api` crate. These are re-exported by
+//! `hexchat-api` when its `macros` feature is enabled, and shouldn't need to
+//! be depended on directly.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::parse::Parser;
+use syn::{DeriveInput, LitStr};
+
+/// Exports the annotated type as a Hexchat plugin. The type has to implement
+/// `hexchat_api::HexchatPlugin`, which supplies the `Default` constructor and
+/// the `init()` and `deinit()` lifecycle methods. This generates the
+/// `hexchat_plugin_get_info()`, `hexchat_plugin_init()`, and
+/// `hexchat_plugin_deinit()` functions Hexchat looks for when it loads the
+/// plugin's library. The plugin object is created and owned by `hexchat-api`
+/// while the plugin is loaded, and dropped after it's unloaded.
+///
+/// ```ignore
+/// #[hexchat_plugin(name = "My Plugin", version = "0.1",
+///                  description = "Does things.")]
+/// #[derive(Default)]
+/// struct MyPlugin { count: u32 }
+/// ```
+///
+/// # Arguments
+/// * `name`        - The plugin's name. Defaults to the package name.
+/// * `version`     - The plugin's version. Defaults to the package version.
+/// * `description` - The plugin's description. Defaults to the package's
+///   description.
+///
+#[proc_macro_attribute]
+pub fn hexchat_plugin(attr: TokenStream, item: TokenStream) -> TokenStream {
+    expand(attr.into(), item.into())
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// Generates the plugin's exports for `#[hexchat_plugin]`.
+/// # Arguments
+/// * `attr` - The attribute's arguments.
+/// * `item` - The type the attribute is on.
+/// # Returns
+/// * The type followed by the exported functions, or the error to report.
+///
+fn expand(attr: TokenStream2, item: TokenStream2)
+    -> syn::Result<TokenStream2>
+{
+    let mut name        = None;
+    let mut version     = None;
+    let mut description = None;
+
+    let parser = syn::meta::parser(|meta| {
+        let slot = if meta.path.is_ident("name") {
+            &mut name
+        } else if meta.path.is_ident("version") {
+            &mut version
+        } else if meta.path.is_ident("description") {
+            &mut description
+        } else {
+            return Err(meta.error(
+                "expected `name`, `version`, or `description`"));
+        };
+        *slot = Some(meta.value()?.parse::<LitStr>()?);
+        Ok(())
+    });
+    parser.parse2(attr)?;
+
+    let input: DeriveInput = syn::parse2(item)?;
+    let ident = &input.ident;
+
+    let name        = or_env(name, "CARGO_PKG_NAME");
+    let version     = or_env(version, "CARGO_PKG_VERSION");
+    let description = or_env(description, "CARGO_PKG_DESCRIPTION");
+
+    Ok(quote! {
+        #input
+
+        #[no_mangle]
+        pub extern "C"
+        fn hexchat_plugin_get_info(
+            name      : *mut *const ::std::ffi::c_char,
+            desc      : *mut *const ::std::ffi::c_char,
+            version   : *mut *const ::std::ffi::c_char,
+            _reserved : *mut *const ::std::ffi::c_char)
+        {
+            ::hexchat_api::lib_get_info(
+                name,
+                desc,
+                version,
+                ::std::boxed::Box::new(|| {
+                    ::hexchat_api::PluginInfo::new(#name,
+                                                   #version,
+                                                   #description)
+                }));
+        }
+
+        #[no_mangle]
+        pub extern "C"
+        fn hexchat_plugin_init(
+            hexchat : &'static ::hexchat_api::Hexchat,
+            name    : *mut *const ::std::ffi::c_char,
+            desc    : *mut *const ::std::ffi::c_char,
+            version : *mut *const ::std::ffi::c_char)
+            -> ::std::ffi::c_int
+        {
+            ::hexchat_api::lib_hexchat_plugin_init_plugin::<#ident>(
+                hexchat,
+                name,
+                desc,
+                version,
+                ::std::boxed::Box::new(|| {
+                    ::hexchat_api::PluginInfo::new(#name,
+                                                   #version,
+                                                   #description)
+                }))
+        }
+
+        #[no_mangle]
+        pub extern "C"
+        fn hexchat_plugin_deinit(hexchat : &'static ::hexchat_api::Hexchat)
+            -> ::std::ffi::c_int
+        {
+            ::hexchat_api::lib_hexchat_plugin_deinit_plugin::<#ident>(hexchat)
+        }
+    })
+}
+
+/// Returns the given string literal's tokens, or an `env!()` lookup of the
+/// Cargo variable to use in its place.
+///
+fn or_env(value: Option<LitStr>, var: &str) -> TokenStream2 {
+    match value {
+        Some(lit) => quote! { #lit },
+        None      => quote! { ::std::env!(#var) },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use quote::ToTokens;
+
+    /// Expands the attribute and returns the generated functions by name.
+    ///
+    fn exports(attr: TokenStream2) -> Vec<syn::ItemFn> {
+        let item = quote! { #[derive(Default)] struct MyPlugin; };
+        let file: syn::File = syn::parse2(expand(attr, item).unwrap())
+                                  .unwrap();
+        file.items.into_iter()
+            .filter_map(|item| match item {
+                syn::Item::Fn(f) => Some(f),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn generates_the_exports() {
+        let fns = exports(quote! { name = "P", version = "1",
+                                   description = "d" });
+        let names: Vec<_> = fns.iter().map(|f| f.sig.ident.to_string())
+                                      .collect();
+        assert_eq!(names, ["hexchat_plugin_get_info",
+                           "hexchat_plugin_init",
+                           "hexchat_plugin_deinit"]);
+        for f in &fns {
+            assert!(f.attrs.iter().any(|a| a.path().is_ident("no_mangle")));
+            assert!(f.sig.abi.is_some());
+        }
+        let init = fns[1].block.to_token_stream().to_string();
+        assert!(init.contains("init_plugin :: < MyPlugin >"));
+        assert!(init.contains("\"P\" , \"1\" , \"d\""));
+    }
+
+    #[test]
+    fn falls_back_on_cargo_variables() {
+        let fns  = exports(quote! { name = "P" });
+        let info = fns[0].block.to_token_stream().to_string();
+        assert!(info.contains("\"P\""));
+        assert!(info.contains("env ! (\"CARGO_PKG_VERSION\")"));
+        assert!(info.contains("env ! (\"CARGO_PKG_DESCRIPTION\")"));
+
+        let fns  = exports(TokenStream2::new());
+        let info = fns[0].block.to_token_stream().to_string();
+        assert!(info.contains("env ! (\"CARGO_PKG_NAME\")"));
+    }
+
+    #[test]
+    fn rejects_unknown_arguments() {
+        let item  = quote! { struct MyPlugin; };
+        let error = expand(quote! { author = "me" }, item).unwrap_err();
+        assert!(error.to_string().contains("expected `name`"));
+    }
+}
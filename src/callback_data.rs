@@ -6,6 +6,8 @@
 
 use crate::hook::Hook;
 use crate::hexchat::{Hexchat, Eat, EventAttrs, FD};
+#[cfg(feature = "metrics")]
+use crate::hook_metrics::measure;
 use crate::user_data::*;
 
 use core::mem;
@@ -19,6 +21,7 @@ use UCallback::*;
 #[allow(dead_code)]
 enum UCallback {
     Command     (Box< Callback >           ),
+    ServerAttrs (Box< ServerAttrsCallback >),
     Print       (Box< PrintCallback >      ),
     PrintAttrs  (Box< PrintAttrsCallback > ),
     Timer       (Box< TimerCallback >      ),
@@ -61,6 +64,17 @@ impl CallbackData {
         CallbackData { callback, data, hook  }
     }
 
+    /// Creates callback data for a server attrs callback.
+    pub (crate)
+    fn new_server_attrs_data(callback : Box<ServerAttrsCallback>,
+                             data     : UserData,
+                             hook     : Hook)
+        -> Self
+    {
+        let callback = ServerAttrs(callback);
+        CallbackData { callback, data, hook }
+    }
+
     /// Creates callback data for a print callback.
     pub (crate)
     fn new_print_data(callback  : Box<PrintCallback>,
@@ -125,6 +139,13 @@ impl CallbackData {
         &self.data
     }
 
+    /// Returns the hook the callback is registered under.
+    #[inline]
+    pub (crate)
+    fn hook(&self) -> &Hook {
+        &self.hook
+    }
+
     /// Returns the `user_data` held by the `CallbackData` object, passing
     /// ownership to the caller. The data field in the `CallbackData` object is
     /// replaced with `NoData`.
@@ -146,7 +167,26 @@ impl CallbackData {
         -> Eat
     {
         if let Command(callback) = &mut self.callback {
-            (*callback)(hc, word, word_eol, ud)
+            measure(&self.hook, || (*callback)(hc, word, word_eol, ud))
+        } else {
+            panic!("Invoked wrong type in CallbackData.");
+        }
+    }
+
+    /// Invokes the callback held in the `callback` field. This is invoked by
+    /// `c_server_attrs_callback()`.
+    #[inline]
+    pub (crate)
+    unsafe fn server_attrs_cb(&mut self,
+                              hc       : &Hexchat,
+                              word     : &[String],
+                              word_eol : &[String],
+                              attrs    : &EventAttrs,
+                              ud       : &UserData)
+        -> Eat
+    {
+        if let ServerAttrs(callback) = &mut self.callback {
+            measure(&self.hook, || (*callback)(hc, word, word_eol, attrs, ud))
         } else {
             panic!("Invoked wrong type in CallbackData.");
         }
@@ -164,7 +204,7 @@ impl CallbackData {
         -> Eat
     {
         if let Print(callback) = &mut self.callback {
-            (*callback)(hc, word, ud)
+            measure(&self.hook, || (*callback)(hc, word, ud))
         } else {
             panic!("Invoked wrong type in CallbackData.");
         }
@@ -182,7 +222,7 @@ impl CallbackData {
         -> Eat
     {
         if let PrintAttrs(callback) = &mut self.callback {
-            (*callback)(hc, word, attrs, ud)
+            measure(&self.hook, || (*callback)(hc, word, attrs, ud))
         } else {
             panic!("Invoked wrong type in CallbackData.");
         }
@@ -195,7 +235,7 @@ impl CallbackData {
     unsafe fn timer_cb(&mut self, hc: &Hexchat, ud: &UserData) -> i32
     {
         if let Timer(callback) = &mut self.callback {
-            let keep_going = (*callback)(hc, ud);
+            let keep_going = measure(&self.hook, || (*callback)(hc, ud));
             if keep_going == 0 {
                 self.hook.unhook();
                 0
@@ -208,8 +248,8 @@ impl CallbackData {
     }
 
     /// One time use timer callback. This is a special case; it's used for
-    /// invoking callbacks on the main thread from other threads. It will
-    /// unhook itself after one use.
+    /// delayed callbacks on the main thread, including those scheduled from
+    /// other threads. It will unhook itself after one use.
     #[inline]
     pub (crate)
     unsafe fn timer_once_cb(&mut self, hc: &Hexchat, ud: &UserData) -> i32
@@ -217,7 +257,7 @@ impl CallbackData {
         let variant = mem::take(&mut self.callback);
         match variant {
             TimerOnce(callback) => {
-                (callback)(hc, ud);
+                measure(&self.hook, || (callback)(hc, ud));
                 self.hook.unhook();
                 0
             },
@@ -243,16 +283,26 @@ impl CallbackData {
         -> Eat
     {
         if let FD(callback) = &mut self.callback {
-            (*callback)(hc,
-                        fd,
-                        BitFlags::from_bits_truncate(flags as u32),
-                        ud)
+            measure(&self.hook, || {
+                (*callback)(hc,
+                            fd,
+                            BitFlags::from_bits_truncate(flags as u32),
+                            ud)
+            })
         } else {
             panic!("Invoked wrong type in CallbackData.");
         }
     }
 }
 
+/// Invokes a callback. With the "metrics" feature, this records the time it
+/// takes in the hook's metrics instead.
+#[cfg(not(feature = "metrics"))]
+#[inline]
+fn measure<R, F: FnOnce() -> R>(_hook: &Hook, callback: F) -> R {
+    callback()
+}
+
 /// The Rust-facing function signature corresponding to the C-facing
 /// `C_Callback`. Note that, unlike the C API, the Rust-facing callback
 /// signatures include a reference to the Hexchat pointer for
@@ -264,6 +314,19 @@ type Callback = dyn FnMut(&Hexchat,
                           &UserData
                          ) -> Eat;
 
+/// The Rust-facing function signature corresponding to the C-facing
+/// `C_ServerAttrCallback`. Note that, unlike the C API, the Rust-facing
+/// callback signatures include a reference to the Hexchat pointer for
+/// convenience.
+pub (crate)
+type ServerAttrsCallback
+              = dyn FnMut(&Hexchat,
+                          &[String],
+                          &[String],
+                          &EventAttrs,
+                          &UserData
+                         ) -> Eat;
+
 /// The Rust-facing function signature corresponding to the C-facing
 /// `C_PrintCallback`. Note that, unlike the C API, the Rust-facing callback
 /// signatures include a reference to the Hexchat pointer for
@@ -0,0 +1,779 @@
+//! Declarative command definitions. A `CommandSpec` describes a command's
+//! arguments, flags, options, and subcommands. It's registered with
+//! `Hexchat::hook_command_spec()`, which generates the command's `/help` text
+//! from the spec, parses each invocation into a `CommandArgs` before the
+//! callback sees it, and prints usage errors to the current context itself.
+//!
+//! ```no_test
+//! let spec = CommandSpec::new("greet")
+//!                .description("Greets someone in the current channel.")
+//!                .arg(Arg::required("nick").help("Who to greet."))
+//!                .arg(Arg::option("times").short('t')
+//!                                         .of_type::<u32>()
+//!                                         .default("1")
+//!                                         .help("How many times."))
+//!                .arg(Arg::flag("loud").short('l').help("Use caps."))
+//!                .arg(Arg::rest("message").help("What to say."));
+//!
+//! hc.hook_command_spec(spec, Priority::Norm, |hc, args, _ud| {
+//!     let nick  : String = args.get("nick").unwrap();
+//!     let times : u32    = args.get("times").unwrap();
+//!     ...
+//!     Eat::All
+//! }, NoData);
+//! ```
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::str::FromStr;
+
+use crate::errors::HexchatError;
+use crate::errors::HexchatError::InvalidArgument;
+use crate::hexchat::{Eat, Hexchat, Priority};
+use crate::hook::Hook;
+use crate::user_data::UserData;
+
+/// Checks that an argument's value can be parsed as the argument's type.
+type Validator = fn(&str) -> Result<(), String>;
+
+/// The ways an argument can be given.
+///
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum ArgKind {
+    Required,
+    Optional,
+    Rest,
+    Flag,
+    Option,
+}
+
+/// Describes a single argument, flag, or option of a command. Created with
+/// one of `Arg::required()`, `Arg::optional()`, `Arg::rest()`,
+/// `Arg::flag()`, or `Arg::option()`, then refined with the builder methods.
+///
+#[derive(Clone, Debug)]
+pub struct Arg {
+    name       : String,
+    kind       : ArgKind,
+    help       : String,
+    short      : Option<char>,
+    value_name : Option<String>,
+    default    : Option<String>,
+    validator  : Option<Validator>,
+}
+
+impl Arg {
+    fn new(name: &str, kind: ArgKind) -> Self {
+        Arg {
+            name       : name.to_string(),
+            kind,
+            help       : String::new(),
+            short      : None,
+            value_name : None,
+            default    : None,
+            validator  : None,
+        }
+    }
+
+    /// A positional argument that must be given.
+    ///
+    pub fn required(name: &str) -> Self {
+        Self::new(name, ArgKind::Required)
+    }
+
+    /// A positional argument that may be left out. Optional arguments follow
+    /// the required ones.
+    ///
+    pub fn optional(name: &str) -> Self {
+        Self::new(name, ArgKind::Optional)
+    }
+
+    /// Collects the rest of the line, spacing included, from where the
+    /// positional arguments end. It's optional unless given a default.
+    ///
+    pub fn rest(name: &str) -> Self {
+        Self::new(name, ArgKind::Rest)
+    }
+
+    /// A switch given as `--name`, or `-c` if it has a short form.
+    ///
+    pub fn flag(name: &str) -> Self {
+        Self::new(name, ArgKind::Flag)
+    }
+
+    /// A named value given as `--name value`, `--name=value`, or `-c value`
+    /// if it has a short form.
+    ///
+    pub fn option(name: &str) -> Self {
+        Self::new(name, ArgKind::Option)
+    }
+
+    /// Sets the description shown in the command's help.
+    ///
+    pub fn help(mut self, help: &str) -> Self {
+        self.help = help.to_string();
+        self
+    }
+
+    /// Gives a flag or option a single character short form.
+    ///
+    pub fn short(mut self, short: char) -> Self {
+        self.short = Some(short);
+        self
+    }
+
+    /// Sets the placeholder shown for the option's value in the usage line.
+    ///
+    pub fn value_name(mut self, value_name: &str) -> Self {
+        self.value_name = Some(value_name.to_string());
+        self
+    }
+
+    /// Sets the value used when the argument or option isn't given.
+    ///
+    pub fn default(mut self, default: &str) -> Self {
+        self.default = Some(default.to_string());
+        self
+    }
+
+    /// Requires the value to parse as `T`. Values that don't are reported to
+    /// the user as usage errors before the callback is invoked.
+    ///
+    pub fn of_type<T>(mut self) -> Self
+    where
+        T: FromStr,
+        T::Err: Display,
+    {
+        self.validator = Some(|s| s.parse::<T>()
+                                   .map(|_| ())
+                                   .map_err(|e| e.to_string()));
+        self
+    }
+
+    /// Returns the argument as it appears in the usage line.
+    ///
+    fn usage(&self) -> String {
+        let value = self.value_name.clone()
+                        .unwrap_or_else(|| self.name.to_uppercase());
+        let long  = format!("--{}", self.name);
+        match (self.kind, self.short) {
+            (ArgKind::Required, _)    => format!("<{}>", self.name),
+            (ArgKind::Optional, _)    => format!("[{}]", self.name),
+            (ArgKind::Rest, _)        => format!("[{}...]", self.name),
+            (ArgKind::Flag, Some(c))  => format!("[-{}|{}]", c, long),
+            (ArgKind::Flag, None)     => format!("[{}]", long),
+            (ArgKind::Option, Some(c))
+                => format!("[-{}|{} <{}>]", c, long, value),
+            (ArgKind::Option, None)   => format!("[{} <{}>]", long, value),
+        }
+    }
+
+    /// Returns the name of the argument as listed in the help's argument
+    /// table.
+    ///
+    fn label(&self) -> String {
+        match (self.kind, self.short) {
+            (ArgKind::Flag, Some(c)) | (ArgKind::Option, Some(c))
+                => format!("-{}, --{}", c, self.name),
+            (ArgKind::Flag, None) | (ArgKind::Option, None)
+                => format!("--{}", self.name),
+            _   => self.name.clone(),
+        }
+    }
+
+    fn is_named(&self) -> bool {
+        matches!(self.kind, ArgKind::Flag | ArgKind::Option)
+    }
+}
+
+/// Describes a command: its name, description, arguments, and subcommands.
+///
+#[derive(Clone, Debug)]
+pub struct CommandSpec {
+    name        : String,
+    description : String,
+    args        : Vec<Arg>,
+    subcommands : Vec<CommandSpec>,
+}
+
+impl CommandSpec {
+    /// Starts the spec for the named command.
+    /// # Arguments
+    /// * `name` - The command's name, without the leading "/".
+    ///
+    pub fn new(name: &str) -> Self {
+        CommandSpec {
+            name        : name.to_string(),
+            description : String::new(),
+            args        : vec![],
+            subcommands : vec![],
+        }
+    }
+
+    /// Sets the description shown at the top of the command's help.
+    ///
+    pub fn description(mut self, description: &str) -> Self {
+        self.description = description.to_string();
+        self
+    }
+
+    /// Adds an argument, flag, or option. Positional arguments are filled in
+    /// the order they're added.
+    ///
+    pub fn arg(mut self, arg: Arg) -> Self {
+        self.args.push(arg);
+        self
+    }
+
+    /// Adds a subcommand. When the first positional argument given matches a
+    /// subcommand's name, the rest of the line is parsed by the subcommand's
+    /// spec instead.
+    ///
+    pub fn subcommand(mut self, subcommand: CommandSpec) -> Self {
+        self.subcommands.push(subcommand);
+        self
+    }
+
+    /// Returns the name of the command.
+    ///
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Returns the one line usage summary of the command.
+    ///
+    pub fn usage(&self) -> String {
+        self.usage_with_prefix("")
+    }
+
+    /// Returns the full help text of the command, as shown by `/help`.
+    ///
+    pub fn help_text(&self) -> String {
+        self.help_with_prefix("")
+    }
+
+    /// Parses the `word` and `word_eol` slices passed to a command callback.
+    /// # Arguments
+    /// * `word`     - The words of the command line, starting with the
+    ///                command's name.
+    /// * `word_eol` - The matching rest-of-line strings.
+    /// # Returns
+    /// * The parsed arguments, or `HexchatError::InvalidArgument` describing
+    ///   what was wrong with the command line.
+    ///
+    pub fn parse(&self, word: &[String], word_eol: &[String])
+        -> Result<CommandArgs, HexchatError>
+    {
+        self.parse_args(word, word_eol).map_err(InvalidArgument)
+    }
+
+    /// Parses a command line, returning a description of the problem if it
+    /// doesn't match the spec.
+    ///
+    fn parse_args(&self, word: &[String], word_eol: &[String])
+        -> Result<CommandArgs, String>
+    {
+        let mut args = CommandArgs::default();
+
+        for token in self.tokenize(word) {
+            match token {
+                Token::Named(arg, None) => {
+                    args.flags.push(arg.name.clone());
+                },
+                Token::Named(arg, Some(value)) => {
+                    args.values.insert(arg.name.clone(), value.to_string());
+                },
+                Token::Help(i) => {
+                    return Err(format!("Unknown option \"{}\".", word[i]));
+                },
+                Token::Positional(arg, i) => {
+                    args.values.insert(arg.name.clone(), word[i].clone());
+                },
+                Token::Rest(arg, i) => {
+                    let rest = word_eol.get(i).unwrap_or(&word[i]);
+                    args.values.insert(arg.name.clone(), rest.clone());
+                },
+                Token::Subcommand(sub, i) => {
+                    let word_eol = word_eol.get(i..).unwrap_or(&[]);
+                    let parsed   = sub.parse_args(&word[i..], word_eol)?;
+                    args.subcommand = Some((sub.name.clone(),
+                                            Box::new(parsed)));
+                },
+                Token::Invalid(msg) => return Err(msg),
+            }
+        }
+        if args.subcommand.is_none() && !self.subcommands.is_empty()
+            && self.args.iter().all(|a| a.is_named())
+        {
+            let names: Vec<&str> = self.subcommands.iter()
+                                                   .map(|s| s.name.as_str())
+                                                   .collect();
+            return Err(format!("Expected one of: {}.", names.join(", ")));
+        }
+        for arg in &self.args {
+            if arg.kind == ArgKind::Flag {
+                continue;
+            }
+            if !args.values.contains_key(&arg.name) {
+                if let Some(default) = &arg.default {
+                    args.values.insert(arg.name.clone(), default.clone());
+                } else if arg.kind == ArgKind::Required {
+                    return Err(format!("Missing argument <{}>.", arg.name));
+                }
+            }
+            if let (Some(value), Some(validate)) = (args.values.get(&arg.name),
+                                                    arg.validator)
+            {
+                validate(value).map_err(|e| {
+                    format!("Invalid value \"{}\" for {}: {}",
+                            value, arg.label(), e)
+                })?;
+            }
+        }
+        Ok(args)
+    }
+
+    /// Splits a command line into tokens according to the spec. Flags and
+    /// options can come anywhere before the rest argument, unless `--` ends
+    /// them. Tokenizing stops at a subcommand, whose words are left to the
+    /// subcommand's spec, at the rest argument, and at the first word that
+    /// doesn't fit. A word that looks like an unknown option starts the rest
+    /// argument if that's the argument to be filled next.
+    ///
+    fn tokenize<'a>(&'a self, word: &'a [String]) -> Vec<Token<'a>> {
+        let mut tokens       = vec![];
+        let mut positionals  = self.args.iter()
+                                        .filter(|a| !a.is_named())
+                                        .peekable();
+        let mut options_done = false;
+        let mut positional   = false;
+        let mut i = 1;
+
+        while i < word.len() {
+            let token = word[i].as_str();
+
+            if !options_done && token == "--" {
+                options_done = true;
+                i += 1;
+                continue;
+            }
+            if !options_done && is_option_like(token) {
+                let rest_next = positionals.peek()
+                                           .is_some_and(|a| a.kind
+                                                            == ArgKind::Rest);
+                match self.named(word, i) {
+                    Some((named, next)) => {
+                        let invalid = matches!(named, Token::Invalid(_));
+                        tokens.push(named);
+                        if invalid {
+                            break;
+                        }
+                        i = next;
+                        continue;
+                    },
+                    None if rest_next => {},
+                    None => {
+                        let key = token.split('=').next().unwrap_or(token);
+                        tokens.push(Token::Invalid(
+                            format!("Unknown option \"{}\".", key)));
+                        break;
+                    },
+                }
+            }
+            if !positional {
+                if let Some(sub) = self.find_subcommand(token) {
+                    tokens.push(Token::Subcommand(sub, i));
+                    break;
+                }
+            }
+            positional = true;
+            match positionals.next() {
+                Some(arg) if arg.kind == ArgKind::Rest => {
+                    tokens.push(Token::Rest(arg, i));
+                    break;
+                },
+                Some(arg) => {
+                    tokens.push(Token::Positional(arg, i));
+                },
+                None => {
+                    tokens.push(Token::Invalid(
+                        format!("Unexpected argument \"{}\".", token)));
+                    break;
+                },
+            }
+            i += 1;
+        }
+        tokens
+    }
+
+    /// Reads the flag or option at `word[i]`, returning its token and the
+    /// index of the next word. Returns `None` if the spec doesn't define it.
+    ///
+    fn named<'a>(&'a self, word: &'a [String], i: usize)
+        -> Option<(Token<'a>, usize)>
+    {
+        let token = word[i].as_str();
+        let (key, inline) = match token.split_once('=') {
+            Some((key, value)) => (key, Some(value)),
+            None => (token, None),
+        };
+        let arg = if let Some(long) = key.strip_prefix("--") {
+            self.args.iter().find(|a| a.is_named() && a.name == long)
+        } else {
+            let mut chars = key[1..].chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => {
+                    self.args.iter()
+                             .find(|a| a.is_named() && a.short == Some(c))
+                },
+                _ => None,
+            }
+        };
+        let Some(arg) = arg else {
+            let help = inline.is_none() && (key == "-h" || key == "--help");
+            return help.then_some((Token::Help(i), i + 1));
+        };
+        if arg.kind == ArgKind::Flag {
+            if inline.is_some() {
+                return Some((Token::Invalid(
+                    format!("{} doesn't take a value.", arg.label())), i));
+            }
+            return Some((Token::Named(arg, None), i + 1));
+        }
+        match inline {
+            Some(value) => Some((Token::Named(arg, Some(value)), i + 1)),
+            None => match word.get(i + 1) {
+                Some(value) => Some((Token::Named(arg, Some(value)), i + 2)),
+                None => Some((Token::Invalid(
+                    format!("{} needs a value.", arg.label())), i)),
+            },
+        }
+    }
+
+    fn find_subcommand(&self, name: &str) -> Option<&CommandSpec> {
+        self.subcommands.iter().find(|s| s.name.eq_ignore_ascii_case(name))
+    }
+
+    fn usage_with_prefix(&self, prefix: &str) -> String {
+        let mut usage = format!("Usage: /{}{}", prefix, self.name);
+        if !self.subcommands.is_empty() {
+            usage.push_str(" <subcommand>");
+        }
+        for arg in &self.args {
+            usage.push(' ');
+            usage.push_str(&arg.usage());
+        }
+        usage
+    }
+
+    fn help_with_prefix(&self, prefix: &str) -> String {
+        let mut lines = vec![self.usage_with_prefix(prefix)];
+        if !self.description.is_empty() {
+            lines.push(format!("  {}", self.description));
+        }
+        let mut table: Vec<(String, String)> = vec![];
+        for arg in &self.args {
+            let mut help = arg.help.clone();
+            if let Some(default) = &arg.default {
+                help.push_str(&format!(" (default: {})", default));
+            }
+            table.push((arg.label(), help.trim().to_string()));
+        }
+        for sub in &self.subcommands {
+            table.push((sub.name.clone(), sub.description.clone()));
+        }
+        let width = table.iter().map(|(l, _)| l.len()).max().unwrap_or(0);
+        for (label, help) in table {
+            let line = format!("    {:width$}  {}", label, help,
+                               width = width);
+            lines.push(line.trim_end().to_string());
+        }
+        lines.join("\n")
+    }
+
+    /// Finds the subcommand a command line invokes, returning it along with
+    /// the prefix to show before its name in usage text, and whether `-h` or
+    /// `--help` was given to it ahead of its positional arguments.
+    ///
+    fn resolve<'a>(&'a self, word: &'a [String])
+        -> (&'a CommandSpec, String, bool)
+    {
+        let mut spec   = self;
+        let mut word   = word;
+        let mut prefix = String::new();
+        'descend: loop {
+            for token in spec.tokenize(word) {
+                match token {
+                    Token::Named(..) => continue,
+                    Token::Help(_)   => return (spec, prefix, true),
+                    Token::Subcommand(sub, i) => {
+                        prefix.push_str(&spec.name);
+                        prefix.push(' ');
+                        spec = sub;
+                        word = &word[i..];
+                        continue 'descend;
+                    },
+                    _ => break,
+                }
+            }
+            return (spec, prefix, false);
+        }
+    }
+}
+
+/// A word of a command line, or an option and its value, classified by a
+/// `CommandSpec`. The indexes are of the words in the command line.
+///
+enum Token<'a> {
+    /// A flag, or an option and its value.
+    Named(&'a Arg, Option<&'a str>),
+    /// `-h` or `--help`, where the spec doesn't define them itself.
+    Help(usize),
+    /// A positional argument.
+    Positional(&'a Arg, usize),
+    /// The rest argument, which starts at the word.
+    Rest(&'a Arg, usize),
+    /// A subcommand; the words from it on are its own.
+    Subcommand(&'a CommandSpec, usize),
+    /// A word that doesn't fit the spec, and why.
+    Invalid(String),
+}
+
+/// The arguments of a command invocation, parsed according to its
+/// `CommandSpec`.
+///
+#[derive(Clone, Debug, Default)]
+pub struct CommandArgs {
+    values     : HashMap<String, String>,
+    flags      : Vec<String>,
+    subcommand : Option<(String, Box<CommandArgs>)>,
+}
+
+impl CommandArgs {
+    /// Returns the named argument or option converted to `T`, or `None` if
+    /// it wasn't given and has no default, or doesn't parse as `T`.
+    ///
+    pub fn get<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.values.get(name).and_then(|v| v.parse().ok())
+    }
+
+    /// Returns the named argument or option as given.
+    ///
+    pub fn str(&self, name: &str) -> Option<&str> {
+        self.values.get(name).map(|v| v.as_str())
+    }
+
+    /// Returns `true` if the named flag was given.
+    ///
+    pub fn flag(&self, name: &str) -> bool {
+        self.flags.iter().any(|f| f == name)
+    }
+
+    /// Returns the name and arguments of the subcommand invoked, if any.
+    ///
+    pub fn subcommand(&self) -> Option<(&str, &CommandArgs)> {
+        self.subcommand.as_ref().map(|(name, args)| (name.as_str(), &**args))
+    }
+}
+
+impl Hexchat {
+    /// Registers a command described by a `CommandSpec`. The help text shown
+    /// by `/help` is generated from the spec. Each invocation is parsed before
+    /// the callback is invoked; if the arguments don't match the spec, the
+    /// error and usage line are printed to the current context and the
+    /// callback isn't invoked. `-h` or `--help` ahead of the positional
+    /// arguments prints the help text, unless the spec defines those itself.
+    /// The callback needs to be compatible with this signature:
+    /// ``` no_test
+    /// FnMut(&Hexchat, &CommandArgs, &UserData) -> Eat
+    /// ```
+    /// # Arguments
+    /// * `spec`        - The description of the command.
+    /// * `pri`         - The priority of the callback.
+    /// * `callback`    - The callback to invoke with the parsed arguments.
+    /// * `user_data`   - Data passed back to the callback when invoked.
+    /// # Returns
+    /// * A `Hook` object associated with the callback.
+    ///
+    #[track_caller]
+    pub fn hook_command_spec<F>(&self,
+                                spec        : CommandSpec,
+                                pri         : Priority,
+                                mut callback: F,
+                                user_data   : UserData)
+        -> Hook
+    where
+        F: FnMut(&Hexchat, &CommandArgs, &UserData) -> Eat + 'static
+    {
+        let name = spec.name.clone();
+        let help = spec.help_text();
+        self.hook_command(
+            &name,
+            pri,
+            move |hc, word, word_eol, ud| {
+                let (target, prefix, asks_help) = spec.resolve(word);
+                if asks_help {
+                    for line in target.help_with_prefix(&prefix).lines() {
+                        hc.print(line);
+                    }
+                    return Eat::All;
+                }
+                match spec.parse_args(word, word_eol) {
+                    Ok(args) => callback(hc, &args, ud),
+                    Err(msg) => {
+                        hc.print(&format!("/{}{}: {}", prefix, target.name,
+                                          msg));
+                        hc.print(&target.usage_with_prefix(&prefix));
+                        Eat::All
+                    },
+                }
+            },
+            &help,
+            user_data)
+    }
+}
+
+/// Returns `true` if the token should be treated as a flag or option rather
+/// than a positional argument. Negative numbers are positional.
+///
+fn is_option_like(token: &str) -> bool {
+    token.len() > 1 && token.starts_with('-') && token.parse::<f64>().is_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Splits a command line into `word` and `word_eol` the way Hexchat does.
+    fn split(line: &str) -> (Vec<String>, Vec<String>) {
+        let mut word     = vec![];
+        let mut word_eol = vec![];
+        let mut start    = None;
+        for (i, c) in line.char_indices().chain([(line.len(), ' ')]) {
+            match (c == ' ', start) {
+                (false, None) => start = Some(i),
+                (true, Some(s)) => {
+                    word.push(line[s..i].to_string());
+                    word_eol.push(line[s..].to_string());
+                    start = None;
+                },
+                _ => {},
+            }
+        }
+        (word, word_eol)
+    }
+
+    fn greet() -> CommandSpec {
+        CommandSpec::new("greet")
+            .arg(Arg::required("nick"))
+            .arg(Arg::option("times").short('t').of_type::<u32>()
+                                     .default("1"))
+            .arg(Arg::flag("loud").short('l'))
+            .arg(Arg::rest("message"))
+    }
+
+    fn todo() -> CommandSpec {
+        CommandSpec::new("todo")
+            .arg(Arg::option("list").short('L'))
+            .subcommand(CommandSpec::new("add")
+                            .arg(Arg::option("priority").short('p'))
+                            .arg(Arg::rest("item").default("")))
+            .subcommand(CommandSpec::new("done")
+                            .arg(Arg::required("id").of_type::<u32>()))
+    }
+
+    fn parse(spec: &CommandSpec, line: &str) -> Result<CommandArgs, String> {
+        let (word, word_eol) = split(line);
+        spec.parse_args(&word, &word_eol)
+    }
+
+    fn asks_help(spec: &CommandSpec, line: &str) -> Option<String> {
+        let (word, _) = split(line);
+        let (target, prefix, help) = spec.resolve(&word);
+        help.then(|| format!("{}{}", prefix, target.name))
+    }
+
+    #[test]
+    fn parses_arguments_and_options() {
+        let args = parse(&greet(), "greet bob -l --times=3 hello   there")
+                       .unwrap();
+        assert_eq!(args.str("nick"), Some("bob"));
+        assert_eq!(args.get::<u32>("times"), Some(3));
+        assert!(args.flag("loud"));
+        assert_eq!(args.str("message"), Some("hello   there"));
+
+        let args = parse(&greet(), "greet -t 2 bob").unwrap();
+        assert_eq!(args.get::<u32>("times"), Some(2));
+        assert!(!args.flag("loud"));
+        assert_eq!(args.str("message"), None);
+
+        let args = parse(&greet(), "greet bob").unwrap();
+        assert_eq!(args.str("times"), Some("1"));
+    }
+
+    #[test]
+    fn rest_keeps_option_like_words() {
+        let args = parse(&greet(), "greet bob hi -l --there").unwrap();
+        assert_eq!(args.str("message"), Some("hi -l --there"));
+        assert!(!args.flag("loud"));
+
+        let args = parse(&greet(), "greet bob -_- hi").unwrap();
+        assert_eq!(args.str("message"), Some("-_- hi"));
+
+        let args = parse(&greet(), "greet bob -- -l hi").unwrap();
+        assert_eq!(args.str("message"), Some("-l hi"));
+        assert!(!args.flag("loud"));
+    }
+
+    #[test]
+    fn reports_invalid_command_lines() {
+        assert_eq!(parse(&greet(), "greet").unwrap_err(),
+                   "Missing argument <nick>.");
+        assert_eq!(parse(&greet(), "greet -x bob").unwrap_err(),
+                   "Unknown option \"-x\".");
+        assert_eq!(parse(&greet(), "greet bob -t").unwrap_err(),
+                   "-t, --times needs a value.");
+        assert_eq!(parse(&greet(), "greet --loud=yes bob").unwrap_err(),
+                   "-l, --loud doesn't take a value.");
+        assert!(parse(&greet(), "greet bob -t many").unwrap_err()
+                    .starts_with("Invalid value \"many\" for -t, --times"));
+        assert_eq!(parse(&todo(), "todo").unwrap_err(),
+                   "Expected one of: add, done.");
+        assert_eq!(parse(&todo(), "todo done 1 2").unwrap_err(),
+                   "Unexpected argument \"2\".");
+        assert!(matches!(greet().parse(&split("greet").0, &[]),
+                         Err(InvalidArgument(_))));
+    }
+
+    #[test]
+    fn parses_subcommands() {
+        let args = parse(&todo(), "todo -L work add -p 2 buy milk").unwrap();
+        assert_eq!(args.str("list"), Some("work"));
+        let (name, sub) = args.subcommand().unwrap();
+        assert_eq!(name, "add");
+        assert_eq!(sub.str("priority"), Some("2"));
+        assert_eq!(sub.str("item"), Some("buy milk"));
+
+        // An option's value isn't taken for a subcommand.
+        let args = parse(&todo(), "todo -L done done 7").unwrap();
+        assert_eq!(args.str("list"), Some("done"));
+        assert_eq!(args.subcommand().unwrap().1.get::<u32>("id"), Some(7));
+    }
+
+    #[test]
+    fn finds_help_requests() {
+        assert_eq!(asks_help(&greet(), "greet -h").as_deref(), Some("greet"));
+        assert_eq!(asks_help(&greet(), "greet -l --help").as_deref(),
+                   Some("greet"));
+        assert_eq!(asks_help(&greet(), "greet bob hi -h"), None);
+        assert_eq!(asks_help(&greet(), "greet -t -h bob"), None);
+        assert_eq!(asks_help(&todo(), "todo add -h").as_deref(),
+                   Some("todo add"));
+        assert_eq!(asks_help(&todo(), "todo -L add -h").as_deref(),
+                   Some("todo"));
+
+        let spec = greet().arg(Arg::flag("help").short('h'));
+        assert_eq!(asks_help(&spec, "greet -h bob"), None);
+        assert!(parse(&spec, "greet -h bob").unwrap().flag("help"));
+    }
+}
@@ -20,9 +20,11 @@ use std::thread;
 #[cfg(feature = "threadsafe")]
 use crate::MAIN_THREAD_ID;
 use crate::errors::HexchatError;
-use crate::hexchat::{Hexchat, hexchat_context};
+use crate::hexchat::{Hexchat, ModeSign, hexchat_context};
 use crate::hexchat_entry_points::PHEXCHAT;
 use crate::list_iterator::ListIterator;
+use crate::list_records::UserInfo;
+use crate::text_events::TextEvent;
 use crate::utils::*;
 
 //use ContextError::*;
@@ -192,6 +194,14 @@ impl Context {
         }
     }
 
+    /// Issues a text event in the context held by the `Context` object.
+    ///
+    pub fn emit_text_event(&self, event: &TextEvent)
+        -> Result<(), HexchatError>
+    {
+        self.emit_print(event.kind().name(), &event.args())
+    }
+
     /// Issues a command in the context held by the `Context` object.
     ///
     pub fn command(&self, command: &str) -> Result<(), HexchatError> {
@@ -206,6 +216,30 @@ impl Context {
         }
     }
 
+    /// Sends MODE lines for the channel held by the `Context` object, setting
+    /// or unsetting `mode` on each of the targets. See `Hexchat::send_modes()`.
+    ///
+    pub fn send_modes(&self,
+                      targets        : &[&str],
+                      modes_per_line : i32,
+                      sign           : ModeSign,
+                      mode           : char)
+        -> Result<(), HexchatError>
+    {
+        let data = &*self.data;
+        unsafe {
+            let ptr = self.acquire()?;
+            let prior = (data.hc.c_get_context)(data.hc);
+            (data.hc.c_set_context)(data.hc, ptr);
+            let result = data.hc.send_modes(targets,
+                                            modes_per_line,
+                                            sign,
+                                            mode);
+            (data.hc.c_set_context)(data.hc, prior);
+            result
+        }
+    }
+
     /// Gets information from the channel/window that the `Context` object
     /// holds an internal pointer to.
     ///
@@ -239,6 +273,13 @@ impl Context {
         }
     }
 
+    /// Returns the users in the channel held by the `Context` object.
+    ///
+    pub fn users(&self) -> Result<Vec<UserInfo>, HexchatError> {
+        let list = self.list_get("users")?;
+        Ok((&list).map(UserInfo::from).collect())
+    }
+
     /// Returns the network name associated with the `Context` object.
     ///
     pub fn network(&self) -> String {
@@ -267,3 +308,15 @@ impl fmt::Debug for Context {
         write!(f, "Context(\"{}\", \"{}\")", network, channel)
     }
 }
+
+/// Finds the named tab on the current network, opening it as a query window
+/// if it doesn't exist yet.
+///
+pub (crate) fn open_tab(name: &str) -> Option<Context> {
+    let hc      = unsafe { &*PHEXCHAT };
+    let network = hc.get_info("network").unwrap_or_default();
+    Context::find(&network, name).or_else(|| {
+        hc.command(&format!("QUERY -nofocus {}", name));
+        Context::find(&network, name)
+    })
+}
@@ -49,6 +49,17 @@ pub enum HexchatError {
 
     /// The UserData cannot be cast to the specified type.
     UserDataCastError(String),
+
+    /// An argument passed to the API was rejected before calling Hexchat.
+    InvalidArgument(String),
+
+    /// A value couldn't be serialized for storage, or the stored data couldn't
+    /// be deserialized to the requested type.
+    SerializationFailed(String),
+
+    /// Hexchat failed to store a plugin pref, or the data read back for it is
+    /// incomplete or in an unexpected format.
+    PluginPrefFailed(String),
 }
 
 unsafe impl Send for HexchatError {}
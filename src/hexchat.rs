@@ -7,12 +7,12 @@
 
 use libc::{c_int, c_char, c_void, time_t};
 use std::ptr;
-use std::ffi::CString;
 use std::fmt;
 use std::fmt::Debug;
 use std::ops::FnMut;
 use std::ptr::null;
 use std::str;
+use std::time::Duration;
 use enumflags2::{bitflags, BitFlags};
 
 use crate::callback_data::{CallbackData, TimerCallbackOnce};
@@ -20,24 +20,28 @@ use crate::context::Context;
 use crate::{hexchat_callbacks::*, HexchatError};
 #[cfg(feature = "threadsafe")]
 use crate::hexchat_entry_points::PHEXCHAT;
-use crate::hook::Hook;
+use crate::hook::{Hook, HookKind};
+use crate::hook_result::HookResult;
+use crate::irc_message::IrcMessage;
 use crate::list_iterator::ListIterator;
 use crate::plugin::Plugin;
+use crate::text_events::{TextEvent, TextEventKind};
 use crate::user_data::*;
 use crate::utils::*;
 #[cfg(feature = "threadsafe")]
 use crate::threadsafe_hexchat::*;
 
 /// Value used in example from the Hexchat Plugin Interface doc web page.
-const MAX_PREF_VALUE_SIZE: usize =  512;
+pub (crate) const MAX_PREF_VALUE_SIZE: usize =  512;
 
 /// Value specified on the [Hexchat Plugin Interface web page]
 /// (https://hexchat.readthedocs.io/en/latest/plugins.html).
 const MAX_PREF_LIST_SIZE : usize = 4096;
 
-// hexchat_send_modes, hexchat_event_attrs_free, pluginpref_delete,
+// hexchat_event_attrs_free,
 
 /// The priorty for a given callback invoked by Hexchat.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Priority {
     Highest     =  127,
     High        =   64,
@@ -47,6 +51,7 @@ pub enum Priority {
 }
 
 /// The return value for client plugin callbacks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
 pub enum Eat {
     None        =    0,
     Hexchat     =    1,
@@ -54,6 +59,18 @@ pub enum Eat {
     All         =    3,
 }
 
+/// The return value for callbacks registered with
+/// `Hexchat::hook_timer_duration()`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum TimerAction {
+    /// Keep the timer running at its current interval.
+    Continue,
+    /// Stop the timer and unhook it.
+    Stop,
+    /// Keep the timer running at a new interval, starting now.
+    Reschedule(Duration),
+}
+
 /// File descriptor types.
 #[bitflags]
 #[repr(u32)]
@@ -65,6 +82,13 @@ pub enum FD {
     NotSocket   =    8,
 }
 
+/// Whether `send_modes()` sets or unsets the mode on its targets.
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ModeSign {
+    Add         =  b'+' as isize,
+    Remove      =  b'-' as isize,
+}
+
 /// Used by the `hexthat.strip()` function to determine what to strip from the
 /// target string.
 pub enum StripFlags {
@@ -110,6 +134,8 @@ impl Hexchat {
     /// ``` no_test
     ///     FnMut(&Hexchat, &[String], &[String], &UserData) -> Eat
     /// ```
+    /// The callback can also return a `Result<Eat, E>` where `E: Display`;
+    /// see `Hexchat::set_error_format()`.
     /// Note that the callback parameters include a reference to the `Hexchat`
     /// object as a convenience. This differs from the C interface which doesn't
     /// include it.
@@ -124,21 +150,26 @@ impl Hexchat {
     /// # Returns
     /// A `Hook` object associated with the callback.
     ///
-    pub fn hook_command<F>(&self,
-                           name        : &str,
-                           pri         : Priority,
-                           callback    : F,
-                           help        : &str,
-                           user_data   : UserData)
+    #[track_caller]
+    pub fn hook_command<F, R>(&self,
+                              name        : &str,
+                              pri         : Priority,
+                              mut callback: F,
+                              help        : &str,
+                              user_data   : UserData)
         -> Hook
     where
         F: FnMut(&Hexchat, &[String], &[String], &UserData)
-           -> Eat + 'static
+           -> R + 'static,
+        R: HookResult<Eat>,
     {
-        let hook = Hook::new();
+        let hook = Hook::new(HookKind::Command, name, pri as i32);
         let ud   = Box::new(
                     CallbackData::new_command_data(
-                                      Box::new(callback),
+                                      Box::new(move |hc, word, word_eol, ud| {
+                                          callback(hc, word, word_eol, ud)
+                                              .resolve(hc)
+                                      }),
                                       user_data,
                                       hook.clone()));
 
@@ -173,6 +204,8 @@ impl Hexchat {
     ///  ``` no_test
     ///  FnMut(&Hexchat, &[String], &[String], &UserData) -> Eat
     ///  ```
+    /// The callback can also return a `Result<Eat, E>` where `E: Display`;
+    /// see `Hexchat::set_error_format()`.
     /// # Arguments
     /// * `name`        - The name of the event to listen for.
     /// * `pri`         - The priority of the callback.
@@ -183,20 +216,25 @@ impl Hexchat {
     /// * A `Hook` object that can be used to deregister the callback. It
     ///   doesn't need to be retained if not needed.
     ///
-    pub fn hook_server<F>(&self,
-                          name        : &str,
-                          pri         : Priority,
-                          callback    : F,
-                          user_data   : UserData)
+    #[track_caller]
+    pub fn hook_server<F, R>(&self,
+                             name        : &str,
+                             pri         : Priority,
+                             mut callback: F,
+                             user_data   : UserData)
         -> Hook
     where
         F: FnMut(&Hexchat, &[String], &[String], &UserData)
-           -> Eat + 'static
+           -> R + 'static,
+        R: HookResult<Eat>,
     {
-        let hook = Hook::new();
+        let hook = Hook::new(HookKind::Server, name, pri as i32);
         let ud   = Box::new(
                     CallbackData::new_command_data(
-                                      Box::new(callback),
+                                      Box::new(move |hc, word, word_eol, ud| {
+                                          callback(hc, word, word_eol, ud)
+                                              .resolve(hc)
+                                      }),
                                       user_data,
                                       hook.clone()
                                   ));
@@ -214,12 +252,113 @@ impl Hexchat {
         hook
     }
 
+    /// Registers a callback to be called when a certain server event occurs.
+    /// The callback will be invoked with an `EventAttrs` object containing
+    /// the server time of the message, which may differ from the time it was
+    /// received if it was replayed by a bouncer. The callback needs to be
+    /// compatible with this signature:
+    /// ``` no_test
+    /// FnMut(&Hexchat, &[String], &[String], &EventAttrs, &UserData) -> Eat
+    /// ```
+    /// The callback can also return a `Result<Eat, E>` where `E: Display`;
+    /// see `Hexchat::set_error_format()`.
+    /// # Arguments
+    /// * `name`        - The name of the event to listen for.
+    /// * `pri`         - The priority of the callback.
+    /// * `callback`    - The callback to invoke when the event occurs.
+    /// * `user_data`   - The user data that gets passed back to the callback
+    ///                   when it's invoked.
+    /// # Returns
+    /// * A `Hook` object that can be used to deregister the callback. It
+    ///   doesn't need to be retained if not needed.
+    ///
+    #[track_caller]
+    pub fn hook_server_attrs<F, R>(&self,
+                                   name        : &str,
+                                   pri         : Priority,
+                                   mut callback: F,
+                                   user_data   : UserData)
+        -> Hook
+    where
+        F: FnMut(&Hexchat, &[String], &[String], &EventAttrs, &UserData)
+           -> R + 'static,
+        R: HookResult<Eat>,
+    {
+        let hook = Hook::new(HookKind::Server, name, pri as i32);
+        let ud   = Box::new(
+                    CallbackData::new_server_attrs_data(
+                                      Box::new(move |hc, word, word_eol,
+                                                     attrs, ud| {
+                                          callback(hc, word, word_eol,
+                                                   attrs, ud)
+                                              .resolve(hc)
+                                      }),
+                                      user_data,
+                                      hook.clone()
+                                  ));
+        let ud = Box::into_raw(ud) as *mut c_void;
+
+        hook.set_cbd(ud);
+        let name = str2cstring(name);
+        unsafe {
+            hook.set((self.c_hook_server_attrs)(self,
+                                                name.as_ptr(),
+                                                pri as i32,
+                                                c_server_attrs_callback,
+                                                ud));
+        }
+        hook
+    }
+
+    /// Registers a callback to be called when a certain server event occurs.
+    /// Rather than the raw `word` and `word_eol` slices, the callback receives
+    /// the line already parsed into an `IrcMessage` holding its source,
+    /// command, and parameters. The callback needs to be compatible with this
+    /// signature:
+    /// ``` no_test
+    /// FnMut(&Hexchat, &IrcMessage, &UserData) -> Eat
+    /// ```
+    /// # Arguments
+    /// * `name`        - The name of the event to listen for, or "RAW LINE"
+    ///                   for all events.
+    /// * `pri`         - The priority of the callback.
+    /// * `callback`    - The callback to invoke when the event occurs.
+    /// * `user_data`   - The user data that gets passed back to the callback
+    ///                   when it's invoked.
+    /// # Returns
+    /// * A `Hook` object that can be used to deregister the callback. It
+    ///   doesn't need to be retained if not needed.
+    ///
+    #[track_caller]
+    pub fn hook_server_msg<F>(&self,
+                              name        : &str,
+                              pri         : Priority,
+                              mut callback: F,
+                              user_data   : UserData)
+        -> Hook
+    where
+        F: FnMut(&Hexchat, &IrcMessage, &UserData) -> Eat + 'static
+    {
+        self.hook_server_attrs(
+            name,
+            pri,
+            move |hc, _word, word_eol, attrs, ud| {
+                match IrcMessage::from_server_event(word_eol, attrs) {
+                    Some(msg) => callback(hc, &msg, ud),
+                    None      => Eat::None,
+                }
+            },
+            user_data)
+    }
+
     /// Registers a callback to be called when a given print event occurs. This
     /// can be any of the text events listed under Settings > Text Events.
     /// Callback needs to be compatible with this signature:
     /// ``` no_test
     /// FnMut(&Hexchat, &[String], &UserData) -> Eat
     /// ```
+    /// The callback can also return a `Result<Eat, E>` where `E: Display`;
+    /// see `Hexchat::set_error_format()`.
     /// # Arguments
     /// * `name`        - The name of the event to listen for.
     /// * `pri`         - The priority of the callback.
@@ -230,19 +369,23 @@ impl Hexchat {
     /// * A `Hook` object that can be used to deregister the callback. It
     ///   doesn't need to be retained if not needed.
     ///
-    pub fn hook_print<F>(&self,
-                         event_name  : &str,
-                         pri         : Priority,
-                         callback    : F,
-                         user_data   : UserData)
+    #[track_caller]
+    pub fn hook_print<F, R>(&self,
+                            event_name  : &str,
+                            pri         : Priority,
+                            mut callback: F,
+                            user_data   : UserData)
         -> Hook
     where
-        F: FnMut(&Hexchat, &[String], &UserData) -> Eat + 'static
+        F: FnMut(&Hexchat, &[String], &UserData) -> R + 'static,
+        R: HookResult<Eat>,
     {
-        let hook = Hook::new();
+        let hook = Hook::new(HookKind::Print, event_name, pri as i32);
         let ud   = Box::new(
                     CallbackData::new_print_data(
-                                      Box::new(callback),
+                                      Box::new(move |hc, word, ud| {
+                                          callback(hc, word, ud).resolve(hc)
+                                      }),
                                       user_data,
                                       hook.clone()
                                   ));
@@ -277,6 +420,7 @@ impl Hexchat {
     /// * A `Hook` object that can be used to deregister the callback. It
     ///   doesn't need to be retained if not needed.
     ///
+    #[track_caller]
     pub fn hook_print_attrs<F>(&self,
                                name        : &str,
                                pri         : Priority,
@@ -287,7 +431,7 @@ impl Hexchat {
         F: FnMut(&Hexchat, &[String], &EventAttrs, &UserData)
            -> Eat + 'static
     {
-        let hook = Hook::new();
+        let hook = Hook::new(HookKind::Print, name, pri as i32);
         let ud   = Box::new(
                     CallbackData::new_print_attrs_data(
                                       Box::new(callback),
@@ -308,12 +452,67 @@ impl Hexchat {
         hook
     }
 
+    /// Registers a callback to be called when the given text event occurs.
+    /// The callback receives a `TextEvent` with a named field for each of the
+    /// event's arguments, rather than a positional slice of strings. It needs
+    /// to be compatible with this signature:
+    /// ``` no_test
+    /// FnMut(&Hexchat, &TextEvent, &UserData) -> Eat
+    /// ```
+    /// # Arguments
+    /// * `kind`        - The text event to listen for.
+    /// * `pri`         - The priority of the callback.
+    /// * `callback`    - The callback to invoke when the event occurs.
+    /// * `user_data`   - The user data that gets passed back to the callback
+    ///                   when it's invoked.
+    /// # Returns
+    /// * A `Hook` object that can be used to deregister the callback. It
+    ///   doesn't need to be retained if not needed.
+    ///
+    #[track_caller]
+    pub fn hook_text_event<F>(&self,
+                              kind        : TextEventKind,
+                              pri         : Priority,
+                              mut callback: F,
+                              user_data   : UserData)
+        -> Hook
+    where
+        F: FnMut(&Hexchat, &TextEvent, &UserData) -> Eat + 'static
+    {
+        let hook = Hook::new(HookKind::Print, kind.name(), pri as i32);
+        let ud   = Box::new(
+                    CallbackData::new_print_data(
+                                      Box::new(move |hc, word, ud| {
+                                          let event = TextEvent::from_args(
+                                                                    kind,
+                                                                    word);
+                                          callback(hc, &event, ud)
+                                      }),
+                                      user_data,
+                                      hook.clone()
+                                  ));
+        let ud = Box::into_raw(ud) as *mut c_void;
+
+        hook.set_cbd(ud);
+        let event_name = str2cstring(kind.name());
+        unsafe {
+            hook.set((self.c_hook_print)(self,
+                                         event_name.as_ptr(),
+                                         pri as i32,
+                                         c_text_event_callback,
+                                         ud));
+        }
+        hook
+    }
+
 
     /// Sets up a callback to be invoked every `timeout` milliseconds. The
     /// callback needs to be compatible with:
     /// ``` no_test
     /// FnMut(&Hexchat, &UserData) -> i32
     /// ```
+    /// The callback can also return a `Result<i32, E>` where `E: Display`.
+    /// Errors are printed, and the timer keeps running.
     /// # Arguments
     /// * `timeout`     - The timeout in milliseconds.
     /// * `callback`    - The `FnOnce()` callback.
@@ -322,17 +521,21 @@ impl Hexchat {
     /// # Returns
     /// * A `Hook` object that is can be used to deregister the callback.
     ///
-    pub fn hook_timer<F>(&self,
-                         timeout   : i64,
-                         callback  : F,
-                         user_data : UserData)
+    #[track_caller]
+    pub fn hook_timer<F, R>(&self,
+                            timeout      : i64,
+                            mut callback : F,
+                            user_data    : UserData)
         -> Hook
     where
-        F: FnMut(&Hexchat, &UserData) -> i32 + 'static
+        F: FnMut(&Hexchat, &UserData) -> R + 'static,
+        R: HookResult<i32>,
     {
-        let hook = Hook::new();
+        let hook = Hook::new(HookKind::Timer, &format!("{} ms", timeout), 0);
         let ud   = Box::new(CallbackData::new_timer_data(
-                                            Box::new(callback),
+                                            Box::new(move |hc, ud| {
+                                                callback(hc, ud).resolve(hc)
+                                            }),
                                             user_data,
                                             hook.clone()
                                         ));
@@ -349,6 +552,85 @@ impl Hexchat {
         hook
     }
 
+    /// Sets up a callback to be invoked every `interval`. Its return value
+    /// decides whether the timer keeps going, stops, or changes interval. The
+    /// callback needs to be compatible with:
+    /// ``` no_test
+    /// FnMut(&Hexchat, &UserData) -> TimerAction
+    /// ```
+    /// The callback can also return a `Result<TimerAction, E>` where
+    /// `E: Display`. Errors are printed, and the timer keeps running.
+    /// A rescheduled timer stays under the same `Hook`, so the `Hook` can
+    /// still be used to unhook or pause it.
+    /// # Arguments
+    /// * `interval`    - The time between invocations, to the millisecond.
+    /// * `callback`    - The callback to invoke.
+    /// * `user_data`   - User data included with the callback and passed back
+    ///                   to the callback during invocation.
+    /// # Returns
+    /// * A `Hook` object that can be used to deregister the callback.
+    ///
+    #[track_caller]
+    pub fn hook_timer_duration<F, R>(&self,
+                                     interval     : Duration,
+                                     mut callback : F,
+                                     user_data    : UserData)
+        -> Hook
+    where
+        F: FnMut(&Hexchat, &UserData) -> R + 'static,
+        R: HookResult<TimerAction>,
+    {
+        let hook = Hook::new(HookKind::Timer,
+                             &format!("{} ms", interval.as_millis()), 0);
+        let this = hook.clone();
+        let ud   = Box::new(CallbackData::new_timer_data(
+                                            Box::new(move |hc, ud| {
+                                                let action = callback(hc, ud)
+                                                                .resolve(hc);
+                                                this.apply_timer_action(action)
+                                            }),
+                                            user_data,
+                                            hook.clone()
+                                        ));
+        let ud = Box::into_raw(ud) as *mut c_void;
+
+        hook.set_cbd(ud);
+
+        unsafe {
+            hook.set((self.c_hook_timer)(self,
+                                         timer_millis(interval),
+                                         c_timer_callback,
+                                         ud));
+        }
+        hook
+    }
+
+    /// Runs a callback once, after `delay`. Like the hook functions, this has
+    /// to be called from Hexchat's main thread; other threads can use
+    /// `main_thread_after()`. The callback needs to be compatible with:
+    /// ``` no_test
+    /// FnOnce(&Hexchat)
+    /// ```
+    /// # Arguments
+    /// * `delay`       - How long to wait before running the callback.
+    /// * `callback`    - The callback to run.
+    /// # Returns
+    /// * A `Hook` object that can be unhooked to cancel the callback before
+    ///   it runs.
+    ///
+    #[track_caller]
+    pub fn run_after<F>(&self, delay: Duration, callback: F) -> Hook
+    where
+        F: FnOnce(&Hexchat) + 'static
+    {
+        self.hook_timer_once(timer_millis(delay) as i64,
+                             Box::new(move |hc, _ud| {
+                                 callback(hc);
+                                 0
+                             }),
+                             UserData::NoData)
+    }
+
     /// This is a special case feature, used internally to enable other threads
     /// to invoke callbacks on the main thread. This function isn't exported
     /// with the rest of the functions of this class.
@@ -361,7 +643,7 @@ impl Hexchat {
     /// * A `Hook` object that is used to deregister the callback after it's
     ///   invoked.
     ///
-    #[allow(dead_code)]
+    #[track_caller]
     pub (crate)
     fn hook_timer_once(&self,
                        timeout   : i64,
@@ -371,7 +653,7 @@ impl Hexchat {
     {
         // TODO - Put the function signatures somewhere logical (?)
 
-        let hook = Hook::new();
+        let hook = Hook::new(HookKind::Timer, &format!("{} ms", timeout), 0);
         let ud   = Box::new(CallbackData::new_timer_once_data(
                                             callback,
                                             user_data,
@@ -392,6 +674,7 @@ impl Hexchat {
     /// Hooks a socket or file descriptor. WIN32: Passing a pipe from MSVCR71,
     /// MSVCR80 or other variations is not supported at this time.
     ///
+    #[track_caller]
     pub fn hook_fd<F>(&self,
                       fd        : i32,
                       flags     : BitFlags<FD>,
@@ -401,7 +684,7 @@ impl Hexchat {
     where
         F: FnMut(&Hexchat, i32, BitFlags<FD>, &UserData) -> Eat + 'static
     {
-        let hook = Hook::new();
+        let hook = Hook::new(HookKind::Fd, &format!("fd {}", fd), 0);
         let ud   = Box::new(CallbackData::new_fd_data(
                                             Box::new(callback),
                                             user_data,
@@ -470,6 +753,18 @@ impl Hexchat {
         self.emit_print_impl(1, event_attrs, event_name, var_args)
     }
 
+    /// Issues the given text event with its arguments.
+    /// # Arguments
+    /// * `event`   - The text event to send.
+    /// # Returns
+    /// * On success, `Ok(())` is returned; otherwise, `Err(<HexchatError>)`.
+    ///
+    pub fn emit_text_event(&self, event: &TextEvent)
+        -> Result<(), HexchatError>
+    {
+        self.emit_print(event.kind().name(), &event.args())
+    }
+
     /// Issues one of the Hexchat IRC events. Called internally by the public
     /// commands, `emit_print()` and `emit_print_attrs()`. The command works
     /// for any of the events listed in Settings > Text Events dialog.
@@ -540,6 +835,63 @@ impl Hexchat {
         }
     }
 
+    /// Sends MODE lines to the server for the current channel, setting or
+    /// unsetting the same mode for each of the targets. Hexchat batches the
+    /// targets into as few lines as the server allows, e.g. voicing five
+    /// nicks may produce `MODE #chan +vvv a b c` and `MODE #chan +vv d e`.
+    /// # Arguments
+    /// * `targets`         - The nicks, or masks, to apply the mode to.
+    /// * `modes_per_line`  - The maximum number of modes per line, or 0 to
+    ///                       use the limit advertised by the server.
+    /// * `sign`            - Whether to set or unset the mode.
+    /// * `mode`            - The mode character, e.g. 'o', 'v', or 'b'.
+    /// # Returns
+    /// * `Ok(())` if the modes were sent, or a `HexchatError::InvalidArgument`
+    ///   if the mode isn't an ASCII letter, there are no targets, or a target
+    ///   is empty or contains a space.
+    ///
+    pub fn send_modes(&self,
+                      targets        : &[&str],
+                      modes_per_line : i32,
+                      sign           : ModeSign,
+                      mode           : char)
+        -> Result<(), HexchatError>
+    {
+        use HexchatError::InvalidArgument;
+
+        if !mode.is_ascii_alphabetic() {
+            return Err(InvalidArgument(
+                        format!("`.send_modes()`: {:?} isn't a valid mode.",
+                                mode)));
+        }
+        if targets.is_empty() {
+            return Err(InvalidArgument("`.send_modes()`: no targets given."
+                                       .into()));
+        }
+        if let Some(t) = targets.iter().find(|t| t.is_empty()
+                                                 || t.contains(' ')) {
+            return Err(InvalidArgument(
+                        format!("`.send_modes()`: {:?} isn't a valid target.",
+                                t)));
+        }
+        if modes_per_line < 0 {
+            return Err(InvalidArgument(
+                        "`.send_modes()`: `modes_per_line` can't be negative."
+                        .into()));
+        }
+        let targets: Vec<_>  = targets.iter().map(|t| str2cstring(t)).collect();
+        let ptrs: Vec<_>     = targets.iter().map(|t| t.as_ptr()).collect();
+        unsafe {
+            (self.c_send_modes)(self,
+                                ptrs.as_ptr(),
+                                ptrs.len() as c_int,
+                                modes_per_line as c_int,
+                                sign as u8 as c_char,
+                                mode as u8 as c_char);
+        }
+        Ok(())
+    }
+
     /// Compares two nicknames, returning a similar value to `strcmp()`.
     /// If they're equal (0), s1 < s2 (<0 - negative), or s1 > s2 (>0 positive).
     /// # Arguments
@@ -703,25 +1055,38 @@ impl Hexchat {
     /// Writes a variable name and value to a configuration file maintained
     /// by Hexchat for your plugin. These can be accessed later using
     /// `pluginpref_get()`. *A character representing the type of the pref is
-    /// prepended to the value output to the config file. `pluginpref_get()`
-    /// uses this when reading back values from the config file to return the
-    /// correct variant of `PrefValue`.*
+    /// prepended to string and bool values output to the config file.
+    /// `pluginpref_get()` uses this when reading back values from the config
+    /// file to return the correct variant of `PrefValue`.* Integers are
+    /// written without a type character, the same way
+    /// `pluginpref_set_int()` writes them, so C and Python plugins can read
+    /// them as well. Strings containing line breaks, nulls, or backslashes
+    /// are escaped, and are read back intact by `pluginpref_get()`.
     /// # Arguments
     /// * `name`    - The name of the pref to set.
     /// * `value`   - The value to set - an instance of one of the `PrefValue`
     ///               types (`StringVal, IntVal, or BoolVal`).
     /// # Returns
-    /// * `true` if the operation succeeds, `false` otherwise.
+    /// * `true` if the operation succeeds, `false` otherwise. It fails if the
+    ///   value, as written, takes 512 bytes or more.
     ///
     pub fn pluginpref_set(&self, name: &str, value: PrefValue) -> bool {
+        if let IntegerVal(i) = value {
+            return self.pluginpref_set_int(name, i);
+        }
         let sval = value.simple_ser();
-        let namecstr = str2cstring(name);
-        let svalcstr = CString::new(sval.clone()).unwrap();
-        if sval.len() > MAX_PREF_VALUE_SIZE {
-            panic!("`hexchat.pluginpref_set({}, <overflow>)`: the value \
-                    exceeds the max allowable size of {:?} bytes.",
-                   name, MAX_PREF_VALUE_SIZE);
+        // Hexchat's buffer for values includes the terminating null.
+        if sval.len() >= MAX_PREF_VALUE_SIZE {
+            return false;
         }
+        self.pluginpref_set_raw(name, &sval)
+    }
+
+    /// Writes the string to the named pref as-is.
+    ///
+    pub (crate) fn pluginpref_set_raw(&self, name: &str, value: &str) -> bool {
+        let namecstr = str2cstring(name);
+        let svalcstr = str2cstring(value);
         unsafe {
             (self.c_pluginpref_set_str)(self,
                                         namecstr.as_ptr(),
@@ -731,7 +1096,10 @@ impl Hexchat {
 
     /// Retrieves, from a config file that Hexchat manages for your plugin,
     /// the value for the named variable that had been previously created using
-    /// `pluginpref_set()`.
+    /// `pluginpref_set()`. Values written by other means, such as by
+    /// `pluginpref_set_int()` or by a plugin written in another language, are
+    /// returned as an `IntegerVal` if they're integers, and as a `StringVal`
+    /// otherwise.
     /// # Arguments
     /// * `name` - The name of the pref to load.
     /// # Returns
@@ -739,17 +1107,74 @@ impl Hexchat {
     ///   exists, `None` otherwise.
     ///
     pub fn pluginpref_get(&self, name: &str) -> Option<PrefValue> {
-        let mut buf = [0i8; MAX_PREF_VALUE_SIZE];
+        self.pluginpref_get_raw(name).map(|s| PrefValue::simple_deser(&s))
+    }
+
+    /// Reads the string stored for the named pref as-is.
+    ///
+    pub (crate) fn pluginpref_get_raw(&self, name: &str) -> Option<String> {
+        let mut buf = [0 as c_char; MAX_PREF_VALUE_SIZE];
         let name = str2cstring(name);
         if unsafe { (self.c_pluginpref_get_str)(self,
                                                 name.as_ptr(),
                                                 buf.as_mut_ptr()) > 0 }
         {
-            let sval = pchar2string(buf.as_ptr());
-            Some(PrefValue::simple_deser(&sval))
+            Some(pchar2string(buf.as_ptr()))
         } else { None }
     }
 
+    /// Writes an integer to the plugin's config file using Hexchat's native
+    /// integer pref function. The value can be read back with
+    /// `pluginpref_get_int()` or `pluginpref_get()`, and by plugins written
+    /// in other languages.
+    /// # Arguments
+    /// * `name`    - The name of the pref to set.
+    /// * `value`   - The integer to store.
+    /// # Returns
+    /// * `true` if the operation succeeds, `false` otherwise.
+    ///
+    pub fn pluginpref_set_int(&self, name: &str, value: i32) -> bool {
+        let name = str2cstring(name);
+        unsafe {
+            (self.c_pluginpref_set_int)(self, name.as_ptr(), value) > 0
+        }
+    }
+
+    /// Reads an integer pref using Hexchat's native integer pref function.
+    /// # Arguments
+    /// * `name` - The name of the pref to load.
+    /// # Returns
+    /// * `Some(i32)` if the pref exists. Like Hexchat's C function, a value
+    ///   that isn't a number reads as 0. `None` is returned if the pref
+    ///   doesn't exist.
+    ///
+    pub fn pluginpref_get_int(&self, name: &str) -> Option<i32> {
+        let cname = str2cstring(name);
+        let value = unsafe {
+            (self.c_pluginpref_get_int)(self, cname.as_ptr())
+        };
+        // Hexchat returns -1 for missing prefs, which is also a valid value.
+        if value == -1 && self.pluginpref_get_raw(name).is_none() {
+            None
+        } else {
+            Some(value)
+        }
+    }
+
+    /// Deletes the named pref from the plugin's config file.
+    /// # Arguments
+    /// * `name` - The name of the pref to delete.
+    /// # Returns
+    /// * `true` if the operation succeeds, `false` otherwise. Deleting a pref
+    ///   that doesn't exist isn't considered a failure.
+    ///
+    pub fn pluginpref_delete(&self, name: &str) -> bool {
+        let name = str2cstring(name);
+        unsafe {
+            (self.c_pluginpref_delete)(self, name.as_ptr()) > 0
+        }
+    }
+
     /// Returns a list of all the plugin pref variable names your plugin
     /// registered using `pluginpref_set()`. `pluginpref_get()` can be invoked
     /// with each item to get their values.
@@ -758,7 +1183,7 @@ impl Hexchat {
     ///    The vector contains the names of the prefs registered.
     ///
     pub fn pluginpref_list(&self) -> Option<Vec<String>> {
-        let mut buf = [0i8; MAX_PREF_LIST_SIZE];
+        let mut buf = [0 as c_char; MAX_PREF_LIST_SIZE];
         if unsafe { (self.c_pluginpref_list)(self, buf.as_mut_ptr()) > 0 } {
             let s = pchar2string(buf.as_ptr());
             if !s.is_empty() {
@@ -815,7 +1240,7 @@ impl Hexchat {
 /// the `Hexchat` object (`hc.pluginpref_get()`, `hc.pluginpref_get()`, etc.).
 /// The enumeration enables the typing of the values stored and retrieved.
 ///
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum PrefValue {
     StringVal(String),
     IntegerVal(i32),
@@ -853,50 +1278,68 @@ impl PrefValue {
 
     /// Simple config file value serialization into string.
     /// The string produced can be written to the config file Hexchat maintains.
-    /// A type character is prepended ('s', 'i', or 'b').
+    /// A type character is prepended to strings and bools ('s' or 'b').
+    /// Strings that can't be written as-is, because they contain line breaks,
+    /// nulls, or backslashes, are escaped and marked with an 'e' instead.
+    /// Integers are written as-is so other plugins can read them.
     ///
     fn simple_ser(&self) -> String {
         match self {
-            StringVal(s) => {
-                let mut sstr = s.clone();
-                sstr.insert(0, 's');
-                sstr
-            },
-            IntegerVal(i) => {
-                let mut istr = i.to_string();
-                istr.insert(0, 'i');
-                istr
-            },
-            BoolVal(b) => {
-                let mut bstr = b.to_string();
-                bstr.insert(0, 'b');
-                bstr
+            StringVal(s) if s.contains(['\\', '\n', '\r', '\0']) => {
+                let mut esc = String::from("e");
+                for c in s.chars() {
+                    match c {
+                        '\\' => esc.push_str("\\\\"),
+                        '\n' => esc.push_str("\\n"),
+                        '\r' => esc.push_str("\\r"),
+                        '\0' => esc.push_str("\\0"),
+                        c    => esc.push(c),
+                    }
+                }
+                esc
             },
+            StringVal(s)  => { format!("s{}", s) },
+            IntegerVal(i) => { i.to_string() },
+            BoolVal(b)    => { format!("b{}", b) },
         }
     }
     /// Simple config file value deserialization from a string to a `PrefValue`.
     /// Treats the first character of the string read in from the config file
     /// as the type, which it then discards and parses the rest of the string
-    /// to return the correct variant of `PrefValue`.
+    /// to return the correct variant of `PrefValue`. Untyped strings that
+    /// parse as integers were written natively and are returned as
+    /// `IntegerVal`. An 'i' type character, used by earlier versions of this
+    /// crate, is also accepted, and 'e' marks an escaped string.
     ///
     fn simple_deser(s: &str) -> PrefValue {
-        if s.len() > 1 {
-            match &s[0..1] {
-                "s" => {
-                    StringVal(s.to_string())
-                },
-                "i" => {
-                    if let Ok(v) = s[1..].parse::<i32>() {
-                        IntegerVal(v)
-                    } else { StringVal(s.to_string()) }
-                },
-                "b" => {
-                    if let Ok(v) = s[1..].parse::<bool>() {
-                        BoolVal(v)
-                    } else { StringVal(s.to_string()) }
-                },
-                _ => { StringVal(s.to_string()) },
+        if let Some(v) = s.strip_prefix('s') {
+            StringVal(v.to_string())
+        } else if let Some(v) = s.strip_prefix('e')
+                                 .filter(|v| v.contains('\\')) {
+            // Escaped strings always have a backslash, which sets them apart
+            // from untyped strings that happen to start with 'e'.
+            let mut unesc = String::new();
+            let mut chars = v.chars();
+            while let Some(c) = chars.next() {
+                if c != '\\' {
+                    unesc.push(c);
+                    continue;
+                }
+                match chars.next() {
+                    Some('n') => unesc.push('\n'),
+                    Some('r') => unesc.push('\r'),
+                    Some('0') => unesc.push('\0'),
+                    Some(c)   => unesc.push(c),
+                    None      => unesc.push('\\'),
+                }
             }
+            StringVal(unesc)
+        } else if let Some(Ok(v)) = s.strip_prefix('b').map(str::parse) {
+            BoolVal(v)
+        } else if let Some(Ok(v)) = s.strip_prefix('i').map(str::parse) {
+            IntegerVal(v)
+        } else if let Ok(v) = s.parse::<i32>() {
+            IntegerVal(v)
         } else {
             StringVal(s.to_string())
         }
@@ -956,36 +1399,45 @@ pub (crate) type hexchat_context     = c_void;
 pub (crate) type hexchat_event_attrs = c_void;
 
 /// Mirrors the callback function pointer of Hexchat.
-type C_Callback      = extern "C"
-                       fn(word       : *const *const c_char,
-                          word_eol   : *const *const c_char,
-                          user_data  : *mut c_void
-                         ) -> c_int;
+pub (crate) type C_Callback      = extern "C"
+                                   fn(word       : *const *const c_char,
+                                      word_eol   : *const *const c_char,
+                                      user_data  : *mut c_void
+                                     ) -> c_int;
 
 /// Mirrors the print callback function pointer of Hexchat.
-type C_PrintCallback = extern "C"
-                       fn(word       : *const *const c_char,
-                          user_data  : *mut c_void
-                         ) -> c_int;
+pub (crate) type C_PrintCallback = extern "C"
+                                   fn(word       : *const *const c_char,
+                                      user_data  : *mut c_void
+                                     ) -> c_int;
 
 /// Mirrors the timer callback function pointer of Hexchat.
-type C_TimerCallback = extern "C"
-                       fn(user_data  : *mut c_void
-                         ) -> c_int;
+pub (crate) type C_TimerCallback = extern "C"
+                                   fn(user_data  : *mut c_void
+                                     ) -> c_int;
 
 /// Mirrors the print attr callback function pointer of Hexchat.
-type C_AttrCallback  = extern "C"
-                       fn(word       : *const *const c_char,
-                          attrs      : *const EventAttrs,
-                          user_data  : *mut c_void
-                         ) -> c_int;
+pub (crate) type C_AttrCallback  = extern "C"
+                                   fn(word       : *const *const c_char,
+                                      attrs      : *const EventAttrs,
+                                      user_data  : *mut c_void
+                                     ) -> c_int;
+
+/// Mirrors the server attr callback function pointer of Hexchat.
+pub (crate) type C_ServerAttrCallback
+                                 = extern "C"
+                                   fn(word       : *const *const c_char,
+                                      word_eol   : *const *const c_char,
+                                      attrs      : *const EventAttrs,
+                                      user_data  : *mut c_void
+                                     ) -> c_int;
 
 /// Mirrors the FD related callback function pointer of Hexchat.
-type C_FDCallback    = extern "C"
-                       fn(fd         : c_int,
-                          flags      : c_int,
-                          udata      : *mut c_void
-                         ) -> c_int;
+pub (crate) type C_FDCallback    = extern "C"
+                                   fn(fd         : c_int,
+                                      flags      : c_int,
+                                      udata      : *mut c_void
+                                     ) -> c_int;
 }
 
 /// Mirrors the C struct for `hexchat_event_attrs`. It holds the timestamps
@@ -1234,7 +1686,7 @@ pub struct Hexchat {
                            fn(hp     : *const Hexchat,
                               name   : *const c_char,
                               pri    : c_int,
-                              cb     : C_AttrCallback,
+                              cb     : C_ServerAttrCallback,
                               udata  : *const c_void
                              ) -> *const hexchat_hook,
     pub (crate)
@@ -1261,3 +1713,106 @@ pub struct Hexchat {
                               attrs  : *mut EventAttrs
                              ),
 }
+
+/// Converts a timer interval to the milliseconds Hexchat takes, saturating at
+/// the largest interval it supports.
+///
+pub (crate) fn timer_millis(interval: Duration) -> c_int {
+    interval.as_millis().min(c_int::MAX as u128) as c_int
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn round_trip(value: PrefValue) -> PrefValue {
+        PrefValue::simple_deser(&value.simple_ser())
+    }
+
+    #[test]
+    fn pref_values_round_trip() {
+        let values = [IntegerVal(0), IntegerVal(-1), IntegerVal(i32::MIN),
+                      BoolVal(true), BoolVal(false),
+                      StringVal(String::new()), StringVal("42".into()),
+                      StringVal("true".into()), StringVal("plain".into()),
+                      StringVal("two\nlines\r\n".into()),
+                      StringVal("C:\\new\\".into()),
+                      StringVal("nul\0here".into())];
+        for value in values {
+            assert_eq!(round_trip(value.clone()), value);
+        }
+    }
+
+    #[test]
+    fn escaped_strings_stay_on_one_line() {
+        let ser = StringVal("a\nb\rc\0d\\e".into()).simple_ser();
+        assert_eq!(ser, "ea\\nb\\rc\\0d\\\\e");
+        assert_eq!(StringVal("plain".into()).simple_ser(), "splain");
+    }
+
+    #[test]
+    fn reads_untyped_and_legacy_values() {
+        assert_eq!(PrefValue::simple_deser("i42"), IntegerVal(42));
+        assert_eq!(PrefValue::simple_deser("-1"), IntegerVal(-1));
+        assert_eq!(PrefValue::simple_deser("example"),
+                   StringVal("example".into()));
+        assert_eq!(PrefValue::simple_deser("bmaybe"),
+                   StringVal("bmaybe".into()));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn pluginpref_set_and_get() {
+        let mock = crate::testing::MockHexchat::new();
+        let hc   = mock.hexchat();
+        for value in [IntegerVal(-1), BoolVal(true),
+                      StringVal("line one\nline two".into()),
+                      StringVal("back\\slash".into())] {
+            assert!(hc.pluginpref_set("pref", value.clone()));
+            assert_eq!(hc.pluginpref_get("pref"), Some(value));
+        }
+        assert!(hc.pluginpref_set_int("pref", -1));
+        assert_eq!(hc.pluginpref_get_int("pref"), Some(-1));
+        assert_eq!(hc.pluginpref_get_int("missing"), None);
+
+        let long = "x".repeat(MAX_PREF_VALUE_SIZE - 2);
+        assert!(hc.pluginpref_set("long", StringVal(long.clone())));
+        assert!(!hc.pluginpref_set("long", StringVal(long + "x")));
+        assert!(!hc.pluginpref_set("long", StringVal("\n".repeat(300))));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn send_modes_rejects_bad_arguments() {
+        use HexchatError::InvalidArgument;
+        let mock = crate::testing::MockHexchat::new();
+        let hc   = mock.hexchat();
+        let bad  = [(&["amy"][..], 0, '+'),
+                    (&[][..],      0, 'v'),
+                    (&["a b"][..], 0, 'v'),
+                    (&[""][..],    0, 'v'),
+                    (&["amy"][..], -1, 'v')];
+        for (targets, per_line, mode) in bad {
+            let result = hc.send_modes(targets, per_line, ModeSign::Add, mode);
+            assert!(matches!(result, Err(InvalidArgument(_))),
+                    "{:?} {} {:?}", targets, per_line, mode);
+        }
+        assert!(mock.sent().is_empty());
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn send_modes_batches_targets() {
+        let mock = crate::testing::MockHexchat::new();
+        let hc   = mock.hexchat();
+        mock.add_context("net", "#other");
+
+        hc.send_modes(&["a", "b", "c", "d"], 3, ModeSign::Add, 'v').unwrap();
+        let ctx = hc.find_context("net", "#other").unwrap();
+        ctx.send_modes(&["e"], 0, ModeSign::Remove, 'o').unwrap();
+
+        assert_eq!(mock.sent(), vec!["MODE #mock +vvv a b c",
+                                     "MODE #mock +v d",
+                                     "MODE #other -o e"]);
+    }
+}
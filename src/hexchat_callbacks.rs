@@ -2,17 +2,30 @@
 //! This file contains the C-facing functions that are registered directly
 //! with Hexchat when a client plugin registers a Rust-facing callback.
 //! The callbacks in this file wrap the Rust-facing callbacks, marshal
-//! the parameters (word, word_eol, etc) for the Rust callbacks.
+//! the parameters (word, word_eol, etc) for the Rust callbacks. Panics in
+//! the Rust callbacks are caught here and handled according to the hook's
+//! `PanicPolicy`. The callbacks of paused hooks aren't invoked.
 
 use libc::{c_int, c_char, c_void};
 use std::panic::catch_unwind;
 
 use crate::callback_data::CallbackData;
-use crate::hexchat::Eat;
-use crate::hexchat::EventAttrs;
+use crate::hexchat::{Eat, EventAttrs};
 use crate::hexchat_entry_points::PHEXCHAT;
+use crate::hook::Hook;
+use crate::panic_policy::{hook_panicked, RunningHook};
 use crate::utils::*;
 
+/// The number of entries in the `word` arrays Hexchat passes to callbacks,
+/// including the reserved first one.
+const WORD_SIZE: usize = 32;
+
+/// Returns the hook a callback's `user_data` is registered under.
+#[inline]
+fn hook_of(user_data: *mut c_void) -> Hook {
+    unsafe { (*(user_data as *mut CallbackData)).hook().clone() }
+}
+
 /// An actual callback registered with Hexchat, which proxies for client plugin
 /// callbacks. It builds the `String` vectors passed to client callbacks.
 /// See [Hexchat API](https://hexchat.readthedocs.io/en/latest/plugins.html)
@@ -22,6 +35,11 @@ extern "C" fn c_callback(word        : *const *const c_char,
                          user_data   : *mut c_void)
     -> c_int
 {
+    let hook     = hook_of(user_data);
+    if hook.is_paused() {
+        return Eat::None as i32;
+    }
+    let _running = RunningHook::enter(&hook);
     catch_unwind(|| {
         let word     = argv2svec(word, 1);
         let word_eol = argv2svec(word_eol, 1);
@@ -31,7 +49,38 @@ extern "C" fn c_callback(word        : *const *const c_char,
             let hc = &*PHEXCHAT;
             (*cd).command_cb(hc, &word, &word_eol, (*cd).get_user_data()) as _
         }
-    }).unwrap_or(Eat::None as i32)
+    }).unwrap_or_else(|_| hook_panicked(&hook) as i32)
+}
+
+/// An actual callback registered with Hexchat, which proxies for client plugin
+/// server callbacks that take an `EventAttrs` parameter.
+/// See [Hexchat API](https://hexchat.readthedocs.io/en/latest/plugins.html)
+pub (crate)
+extern "C" fn c_server_attrs_callback(word      : *const *const c_char,
+                                      word_eol  : *const *const c_char,
+                                      attrs     : *const EventAttrs,
+                                      user_data : *mut c_void)
+    -> c_int
+{
+    let hook     = hook_of(user_data);
+    if hook.is_paused() {
+        return Eat::None as i32;
+    }
+    let _running = RunningHook::enter(&hook);
+    catch_unwind(|| {
+        let word     = argv2svec(word, 1);
+        let word_eol = argv2svec(word_eol, 1);
+
+        unsafe {
+            let cd = user_data as *mut CallbackData;
+            let hc = &*PHEXCHAT;
+            (*cd).server_attrs_cb(hc,
+                                  &word,
+                                  &word_eol,
+                                  &*attrs,
+                                  (*cd).get_user_data()) as _
+        }
+    }).unwrap_or_else(|_| hook_panicked(&hook) as i32)
 }
 
 /// An actual callback registered with Hexchat, which proxies for client plugin
@@ -43,6 +92,11 @@ extern "C" fn c_print_callback(word      : *const *const c_char,
                                user_data : *mut c_void)
     -> c_int
 {
+    let hook     = hook_of(user_data);
+    if hook.is_paused() {
+        return Eat::None as i32;
+    }
+    let _running = RunningHook::enter(&hook);
     catch_unwind(|| {
         let word = argv2svec(word, 1);
 
@@ -51,7 +105,31 @@ extern "C" fn c_print_callback(word      : *const *const c_char,
             let hc = &*PHEXCHAT;
             (*cd).print_cb(hc, &word, (*cd).get_user_data()) as _
         }
-    }).unwrap_or(Eat::None as i32)
+    }).unwrap_or_else(|_| hook_panicked(&hook) as i32)
+}
+
+/// An actual callback registered with Hexchat for typed text events. Unlike
+/// `c_print_callback()`, it passes on every entry of the `word` array, empty
+/// or not, so each argument keeps its position.
+pub (crate)
+extern "C" fn c_text_event_callback(word      : *const *const c_char,
+                                    user_data : *mut c_void)
+    -> c_int
+{
+    let hook     = hook_of(user_data);
+    if hook.is_paused() {
+        return Eat::None as i32;
+    }
+    let _running = RunningHook::enter(&hook);
+    catch_unwind(|| {
+        let word = argv2svec_count(word, 1, WORD_SIZE - 1);
+
+        unsafe {
+            let cd = user_data as *mut CallbackData;
+            let hc = &*PHEXCHAT;
+            (*cd).print_cb(hc, &word, (*cd).get_user_data()) as _
+        }
+    }).unwrap_or_else(|_| hook_panicked(&hook) as i32)
 }
 
 /// An actual callback registered with Hexchat, which proxies for client plugin
@@ -63,6 +141,11 @@ extern "C" fn c_print_attrs_callback(word      : *const *const c_char,
                                      user_data : *mut c_void)
     -> c_int
 {
+    let hook     = hook_of(user_data);
+    if hook.is_paused() {
+        return Eat::None as i32;
+    }
+    let _running = RunningHook::enter(&hook);
     catch_unwind(|| {
         let word = argv2svec(word, 1);
 
@@ -71,7 +154,7 @@ extern "C" fn c_print_attrs_callback(word      : *const *const c_char,
             let hc = &*PHEXCHAT;
             (*cd).print_attrs_cb(hc, &word, &*attrs, (*cd).get_user_data()) as _
         }
-    }).unwrap_or(Eat::None as i32)
+    }).unwrap_or_else(|_| hook_panicked(&hook) as i32)
 }
 
 
@@ -79,29 +162,49 @@ extern "C" fn c_print_attrs_callback(word      : *const *const c_char,
 /// callbacks.
 pub (crate)
 extern "C" fn c_timer_callback(user_data: *mut c_void) -> c_int {
+    let hook     = hook_of(user_data);
+    if hook.is_paused() {
+        // Keep the timer going; it fires again once resumed.
+        return 1;
+    }
+    let _running = RunningHook::enter(&hook);
     catch_unwind(|| {
         unsafe {
             let cd = user_data as *mut CallbackData;
             let hc = &*PHEXCHAT;
             (*cd).timer_cb(hc, (*cd).get_user_data())
         }
-    }).unwrap_or(0)
+    }).unwrap_or_else(|_| {
+        // The timer keeps running unless its panic policy unhooked it.
+        hook_panicked(&hook);
+        hook.is_hooked() as c_int
+    })
 }
 
-/// A special case callback. This is used by the multi threading support to
-/// put code on the main thread from code running on an independent thread.
-/// The `CallbackData` object will ensure that this callback gets unhooked
-/// after a one-time callback is executed.
-#[allow(dead_code)]
+/// A special case callback. This is used by `Hexchat::run_after()`, and by
+/// `main_thread_after()` to put code on the main thread from code running on
+/// an independent thread. The `CallbackData` object will ensure that this
+/// callback gets unhooked after a one-time callback is executed.
 pub (crate)
 extern "C" fn c_timer_callback_once(user_data: *mut c_void) -> c_int {
+    let hook     = hook_of(user_data);
+    if hook.is_paused() {
+        // Keep the timer going; it fires again once resumed.
+        return 1;
+    }
+    let _running = RunningHook::enter(&hook);
     catch_unwind(|| {
         unsafe {
             let cd = user_data as *mut CallbackData;
             let hc = &*PHEXCHAT;
             (*cd).timer_once_cb(hc, (*cd).get_user_data())
         }
-    }).unwrap_or(0)
+    }).unwrap_or_else(|_| {
+        // The callback was used up, so the timer stops whatever the policy.
+        hook_panicked(&hook);
+        hook.unhook();
+        0
+    })
 }
 
 /// An actual callback registered with Hexchat, which proxies for client plugin
@@ -110,11 +213,16 @@ pub (crate)
 extern "C" fn c_fd_callback(fd: c_int, flags: c_int, user_data: *mut c_void)
     -> c_int
 {
+    let hook     = hook_of(user_data);
+    if hook.is_paused() {
+        return Eat::None as i32;
+    }
+    let _running = RunningHook::enter(&hook);
     catch_unwind(|| {
         unsafe {
             let cd = user_data as *mut CallbackData;
             let hc = &*PHEXCHAT;
             (*cd).fd_cb(hc, fd, flags, (*cd).get_user_data()) as i32
         }
-    }).unwrap_or(Eat::None as i32)
+    }).unwrap_or_else(|_| hook_panicked(&hook) as i32)
 }
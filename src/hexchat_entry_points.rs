@@ -9,13 +9,12 @@
 //! The debug build of the library will include a stack trace in the error
 //! message.
 
-#[cfg(debug_assertions)]
-use backtrace::Backtrace;
 use libc::c_char;
 use send_wrapper::SendWrapper;
+use std::any::Any;
+use std::cell::RefCell;
 use std::ffi::CString;
 use std::marker::PhantomPinned;
-use std::panic;
 use std::panic::{catch_unwind, UnwindSafe};
 use std::pin::Pin;
 use std::ptr::null;
@@ -25,6 +24,10 @@ use std::sync::RwLock;
 //use crate::{plugin_get_info, plugin_init, plugin_deinit};
 use crate::hexchat::Hexchat;
 use crate::hook::*;
+#[cfg(feature = "log")]
+use crate::hexchat_logger::{logger_deinit, logger_init};
+use crate::local_executor::local_executor_deinit;
+use crate::panic_config::{remove_panic_hook, set_panic_hook};
 use crate::utils::*;
 #[cfg(feature = "threadsafe")]
 use crate::thread_facilities::*;
@@ -41,6 +44,68 @@ pub type DeinitFn = dyn FnOnce(&'static Hexchat) -> i32 + UnwindSafe;
 /// `dll_entry_points!()`.
 pub type InfoFn   = dyn FnOnce() -> PluginInfo + UnwindSafe;
 
+/// The signature for the init function of a plugin that keeps its state in
+/// the crate, registered using `dll_entry_points!()` with a state type.
+/// Returning `None` fails the plugin's load.
+pub type StateInitFn<S>   = dyn FnOnce(&'static Hexchat) -> Option<S>
+                                + UnwindSafe;
+
+/// The signature for the deinit function of a plugin that keeps its state in
+/// the crate, registered using `dll_entry_points!()` with a state type.
+pub type StateDeinitFn<S> = dyn FnOnce(&'static Hexchat, &mut S) -> i32
+                                + UnwindSafe;
+
+/// The lifecycle of a plugin exported with the `#[hexchat_plugin]` attribute
+/// (available with the `macros` feature). The plugin object is created with
+/// `Default` when Hexchat loads the plugin, and is owned by this crate until
+/// the plugin is unloaded, so plugins can keep their state in its fields
+/// rather than in statics.
+///
+/// ```no_test
+/// #[hexchat_plugin(name = "Counter", version = "0.1",
+///                  description = "Counts messages.")]
+/// #[derive(Default)]
+/// struct Counter { count: u32 }
+///
+/// impl HexchatPlugin for Counter {
+///     fn init(&mut self, hc: &'static Hexchat) -> bool {
+///         hc.hook_print("Channel Message", Priority::Norm, |hc, _, _| {
+///             // Callbacks look the plugin object up by its type.
+///             hc.try_with_state(|counter: &mut Counter| counter.count += 1);
+///             Eat::None
+///         }, NoData);
+///         true
+///     }
+/// }
+/// ```
+///
+pub trait HexchatPlugin: Default + 'static {
+    /// Called when the plugin is loaded. This is where hooks are typically
+    /// registered.
+    /// # Arguments
+    /// * `hc` - The Hexchat interface.
+    /// # Returns
+    /// * `true` if the plugin loaded successfully; `false` to have Hexchat
+    ///   unload it.
+    ///
+    fn init(&mut self, hc: &'static Hexchat) -> bool;
+
+    /// Called when the plugin is unloaded, before its hooks are removed. The
+    /// plugin object is dropped once the hooks are gone.
+    /// # Arguments
+    /// * `hc` - The Hexchat interface.
+    ///
+    fn deinit(&mut self, _hc: &'static Hexchat) {}
+}
+
+thread_local! {
+    /// The plugin's state, or the plugin object of a `#[hexchat_plugin]`
+    /// plugin, held from its init until its hooks have been removed on
+    /// unload.
+    static PLUGIN_STATE: RefCell<Option<Box<dyn Any>>>
+        = const { RefCell::new(None) };
+}
+
 /// Holds persistent client plugin info strings.
 static PLUGIN_INFO: RwLock<Option<SendWrapper<PluginInfo>>> = RwLock::new(None);
 
@@ -82,16 +147,34 @@ pub(crate) static mut PHEXCHAT: *const Hexchat = null::<Hexchat>();
 /// variables, This is the place to drop their values, for example:
 /// `MY_STATIC_VAR = None;`
 ///
+/// # Plugin state
+///
+/// Instead of statics, the plugin's state can be handed to the crate by
+/// passing its type as a fourth parameter:
+///
+/// ```dll_entry_points!( my_info_func, my_init_func, my_deinit_func, State )```
+///
+/// * `my_init_func  (&'static Hexchat)             -> Option<State>;`
+/// * `my_deinit_func(&'static Hexchat, &mut State) -> i32;`
+///
+/// The state returned by the init function can be looked up from any callback
+/// on the main thread with `Hexchat::try_with_state()`. It's passed to the
+/// deinit function, then dropped once the plugin's hooks have been removed.
+///
+/// With the `macros` feature, the `#[hexchat_plugin]` attribute can be used
+/// instead to export a type implementing `HexchatPlugin`, which keeps the
+/// plugin's state without statics.
+///
 #[macro_export]
 macro_rules! dll_entry_points {
 
     ( $info:ident, $init:ident, $deinit:ident ) => {
         #[no_mangle]
         pub extern "C"
-        fn hexchat_plugin_get_info(name     : *mut *const i8,
-                                   desc     : *mut *const i8,
-                                   version  : *mut *const i8,
-                                   reserved : *mut *const i8)
+        fn hexchat_plugin_get_info(name     : *mut *const std::ffi::c_char,
+                                   desc     : *mut *const std::ffi::c_char,
+                                   version  : *mut *const std::ffi::c_char,
+                                   reserved : *mut *const std::ffi::c_char)
         {
             hexchat_api::lib_get_info(name,
                                       desc,
@@ -101,9 +184,9 @@ macro_rules! dll_entry_points {
         #[no_mangle]
         pub extern "C"
         fn hexchat_plugin_init(hexchat   : &'static Hexchat,
-                               name      : *mut *const i8,
-                               desc      : *mut *const i8,
-                               version   : *mut *const i8
+                               name      : *mut *const std::ffi::c_char,
+                               desc      : *mut *const std::ffi::c_char,
+                               version   : *mut *const std::ffi::c_char
                               ) -> i32
         {
             hexchat_api::lib_hexchat_plugin_init(hexchat,
@@ -120,6 +203,45 @@ macro_rules! dll_entry_points {
             hexchat_api::lib_hexchat_plugin_deinit(hexchat, Box::new($deinit))
         }
     };
+
+    ( $info:ident, $init:ident, $deinit:ident, $state:ty ) => {
+        #[no_mangle]
+        pub extern "C"
+        fn hexchat_plugin_get_info(name     : *mut *const std::ffi::c_char,
+                                   desc     : *mut *const std::ffi::c_char,
+                                   version  : *mut *const std::ffi::c_char,
+                                   reserved : *mut *const std::ffi::c_char)
+        {
+            hexchat_api::lib_get_info(name,
+                                      desc,
+                                      version,
+                                      Box::new($info));
+        }
+        #[no_mangle]
+        pub extern "C"
+        fn hexchat_plugin_init(hexchat   : &'static Hexchat,
+                               name      : *mut *const std::ffi::c_char,
+                               desc      : *mut *const std::ffi::c_char,
+                               version   : *mut *const std::ffi::c_char
+                              ) -> i32
+        {
+            hexchat_api::lib_hexchat_plugin_init_with_state::<$state>(
+                hexchat,
+                name,
+                desc,
+                version,
+                Box::new($init),
+                Box::new($info))
+        }
+        #[no_mangle]
+        pub extern "C"
+        fn hexchat_plugin_deinit(hexchat : &'static Hexchat) -> i32
+        {
+            hexchat_api::lib_hexchat_plugin_deinit_with_state::<$state>(
+                hexchat,
+                Box::new($deinit))
+        }
+    };
 }
 
 /// Holds client plugin information strings.
@@ -189,10 +311,10 @@ impl PluginInfo {
 /// obtain the persistent plugin info strings that it sets the paramters to.
 ///
 #[doc(hidden)]
-pub fn lib_hexchat_plugin_get_info(name      : *mut *const i8,
-                                   desc      : *mut *const i8,
-                                   version   : *mut *const i8,
-                                   _reserved : *mut *const i8,
+pub fn lib_hexchat_plugin_get_info(name      : *mut *const c_char,
+                                   desc      : *mut *const c_char,
+                                   version   : *mut *const c_char,
+                                   _reserved : *mut *const c_char,
                                    callback  : Box<InfoFn>)
 {
     lib_get_info(name, desc, version, callback);
@@ -214,7 +336,7 @@ pub fn lib_hexchat_plugin_init(hexchat   : &'static Hexchat,
     // Store the global Hexchat pointer.
     unsafe { PHEXCHAT = hexchat; }
 
-    set_panic_hook(hexchat);
+    set_panic_hook();
 
     lib_get_info(name, desc, version, info_cb);
 
@@ -223,6 +345,8 @@ pub fn lib_hexchat_plugin_init(hexchat   : &'static Hexchat,
         Hook::init();
         #[cfg(feature = "threadsafe")]
         main_thread_init();
+        #[cfg(feature = "log")]
+        logger_init();
         init_cb(hexchat)
     }).unwrap_or(0)
 }
@@ -246,22 +370,189 @@ pub fn lib_hexchat_plugin_deinit(hexchat  : &'static Hexchat,
         #[cfg(feature = "threadsafe")]
         main_thread_deinit();
 
-        // Call user's deinit().
-        let retval = callback(hexchat);
+        // Call user's deinit(). The clean up below still has to happen if it
+        // panics, or Hexchat would be left holding the hooks.
+        let retval = catch_unwind(|| callback(hexchat)).unwrap_or(0);
+
+        #[cfg(feature = "log")]
+        logger_deinit();
+
+        // Drop any pending `spawn_local()` tasks while their hooks are still
+        // valid.
+        local_executor_deinit();
 
         // Cause the callback_data objects to drop and clean up.
         Hook::deinit();
 
+        // Drop the plugin's state, now that no callbacks can reach it.
+        let state = PLUGIN_STATE.with(|s| s.borrow_mut().take());
+        drop(state);
+
         // Destruct the info struct.
         PLUGIN_INFO.write().unwrap().take();
 
         retval
     }).unwrap_or(0);
     // Final clean up on unload - drop the hook closure.
-    let _ = panic::take_hook();
+    remove_panic_hook();
     result
 }
 
+/// Called indirectly while a plugin with crate-held state is being loaded.
+/// The state returned by `init_cb` is kept until the plugin is unloaded.
+/// Plugin authors shouldn't call this - it's only public because
+/// `dll_entry_points()` generates code that calls it.
+///
+#[doc(hidden)]
+pub fn lib_hexchat_plugin_init_with_state<S: 'static>(
+    hexchat : &'static Hexchat,
+    name    : *mut *const c_char,
+    desc    : *mut *const c_char,
+    version : *mut *const c_char,
+    init_cb : Box<StateInitFn<S>>,
+    info_cb : Box<InfoFn>)
+    -> i32
+{
+    lib_hexchat_plugin_init(hexchat,
+                            name,
+                            desc,
+                            version,
+                            Box::new(|hc| {
+                                match init_cb(hc) {
+                                    Some(state) => {
+                                        PLUGIN_STATE.with(|s| {
+                                            *s.borrow_mut()
+                                                = Some(Box::new(state))
+                                        });
+                                        1
+                                    },
+                                    None => 0,
+                                }
+                            }),
+                            info_cb)
+}
+
+/// Called indirectly while a plugin with crate-held state is being unloaded.
+/// `deinit_cb` is given the state, which is dropped after the hooks are
+/// removed. Plugin authors shouldn't call this - it's only public because
+/// `dll_entry_points()` generates code that calls it.
+///
+#[doc(hidden)]
+pub fn lib_hexchat_plugin_deinit_with_state<S: 'static>(
+    hexchat   : &'static Hexchat,
+    deinit_cb : Box<StateDeinitFn<S>>)
+    -> i32
+{
+    lib_hexchat_plugin_deinit(hexchat, Box::new(|hc| {
+        // Taken out while the deinit function runs so callbacks it triggers
+        // can't find it borrowed. The guard puts it back, even if the deinit
+        // function panics, so it's still dropped after the hooks.
+        let mut state = RestoreState(PLUGIN_STATE.with(|s| {
+                                         s.borrow_mut().take()
+                                     }));
+        match state.0.as_mut().and_then(|s| s.downcast_mut::<S>()) {
+            Some(s) => deinit_cb(hc, s),
+            None    => 0,
+        }
+    }))
+}
+
+/// Puts the plugin's state back in `PLUGIN_STATE` when dropped.
+///
+struct RestoreState(Option<Box<dyn Any>>);
+
+impl Drop for RestoreState {
+    fn drop(&mut self) {
+        let state = self.0.take();
+        PLUGIN_STATE.with(|s| *s.borrow_mut() = state);
+    }
+}
+
+/// Called indirectly while a `#[hexchat_plugin]` plugin is being loaded.
+/// Creates the plugin object and runs its `init()`. The object is kept as
+/// the plugin's state if that succeeds. Plugin authors shouldn't call this -
+/// it's only public because `#[hexchat_plugin]` generates code that calls it.
+///
+#[doc(hidden)]
+pub fn lib_hexchat_plugin_init_plugin<P: HexchatPlugin>(
+    hexchat : &'static Hexchat,
+    name    : *mut *const c_char,
+    desc    : *mut *const c_char,
+    version : *mut *const c_char,
+    info_cb : Box<InfoFn>)
+    -> i32
+{
+    lib_hexchat_plugin_init_with_state::<P>(hexchat,
+                                            name,
+                                            desc,
+                                            version,
+                                            Box::new(|hc| {
+                                                let mut plugin = P::default();
+                                                plugin.init(hc)
+                                                      .then_some(plugin)
+                                            }),
+                                            info_cb)
+}
+
+/// Called indirectly while a `#[hexchat_plugin]` plugin is being unloaded.
+/// Runs the plugin object's `deinit()`; the object itself is dropped after
+/// the hooks are removed. Plugin authors shouldn't call this - it's only
+/// public because `#[hexchat_plugin]` generates code that calls it.
+///
+#[doc(hidden)]
+pub fn lib_hexchat_plugin_deinit_plugin<P: HexchatPlugin>(
+    hexchat: &'static Hexchat)
+    -> i32
+{
+    lib_hexchat_plugin_deinit_with_state::<P>(hexchat, Box::new(|hc, plugin| {
+        plugin.deinit(hc);
+        1
+    }))
+}
+
+impl Hexchat {
+    /// Looks up the plugin's state - the value returned by the init function
+    /// registered with `dll_entry_points!()`, or the `#[hexchat_plugin]`
+    /// plugin object - and passes it to the callback. Hook callbacks aren't
+    /// given the state, so this is how they reach it. The state's type has to
+    /// be named, either on the callback's parameter or with a turbofish:
+    ///
+    /// ```no_test
+    /// hc.try_with_state(|counter: &mut Counter| counter.count += 1);
+    /// let count = hc.try_with_state::<Counter, _, _>(|c| c.count);
+    /// ```
+    ///
+    /// The state is only available on the main thread, between the plugin's
+    /// init and deinit.
+    ///
+    /// # Arguments
+    /// * `callback` - Called with the plugin's state.
+    ///
+    /// # Returns
+    /// * The callback's return value, or `None` if there's no state of type
+    ///   `S`, or it's already borrowed by a `try_with_state()` call further
+    ///   up the stack.
+    ///
+    pub fn try_with_state<S, R, F>(&self, callback: F) -> Option<R>
+    where
+        S: 'static,
+        F: FnOnce(&mut S) -> R,
+    {
+        PLUGIN_STATE.with(|s| {
+            let mut state = s.try_borrow_mut().ok()?;
+            state.as_mut()?.downcast_mut::<S>().map(callback)
+        })
+    }
+}
+
+/// Returns the name the plugin registered with Hexchat, if it's available.
+///
+pub (crate) fn plugin_name() -> Option<String> {
+    let info = PLUGIN_INFO.read().unwrap();
+    // The info can only be read on the thread it was created on.
+    let info = info.as_ref().filter(|pi| pi.valid())?;
+    Some(info.data.name.to_string_lossy().into_owned())
+}
 
 /// This function sets Hexchat's character pointer pointer's to point at the
 /// pinned buffers holding info about a plugin. Not to be called by plugin
@@ -289,57 +580,73 @@ pub fn lib_get_info(name     : *mut *const c_char,
     }
 }
 
-/// Sets the panic hook so panic info will be printed to the active Hexchat
-/// window. The debug build includes a stack trace using
-/// [Backtrace](https://crates.io/crates/backtrace)
-fn set_panic_hook(hexchat: &'static Hexchat) {
-    panic::set_hook(Box::new(move |panic_info| {
-        #[cfg(debug_assertions)]
-        let mut loc = String::new();
-        let plugin_name;
-        if let Some(pi) = PLUGIN_INFO.read().unwrap().as_ref() {
-            plugin_name = pi.data.name.to_str().unwrap().to_string();
-        } else {
-            plugin_name = "a Rust plugin".to_string();
-        }
-        if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
-            hexchat.print(&format!("\x0304<<Panicked!>>\t{:?}", s));
-        } else {
-            hexchat.print(&format!("\x0304<<Panicked!>>\t{:?}", panic_info));
-        }
-        if let Some(location) = panic_info.location() {
-            hexchat.print(
-                &format!("\x0313Panic occured in {} in file '{}' at line {:?}.",
-                         plugin_name,
-                         location.file(),
-                         location.line()));
-
-            #[cfg(debug_assertions)]
-            { loc = format!("{}:{:?}", location.file(), location.line()); }
-        }
-        // For the debug build, include a stack trace.
-        #[cfg(debug_assertions)]
-        {
-            let mut trace = vec![];
-            let mut begin = 0;
-            let mut end   = 0;
-            let     bt    = Backtrace::new();
-            let     btstr = format!("{:?}", bt);
-
-            for line in btstr.lines() {
-                let line  = String::from(line);
-                if begin == 0 && !loc.is_empty() && line.contains(&loc) {
-                    // Underlined and magenta.
-                    trace.push(format!("\x1F\x0313{}", line));
-                    begin = end;
-                } else {
-                    trace.push(format!("\x0304{}", line));
-                }
-                end += 1;
-            }
-            // Start the trace where the panic actually occurred.
-            begin = if begin == 0 { 0 } else { begin - 1 };
-            hexchat.print(&trace[begin..end].join("\n"));
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use std::cell::Cell;
+    use std::ptr::null;
+
+    use super::*;
+    use crate::hexchat::{Eat, Priority};
+    use crate::testing::MockHexchat;
+    use crate::user_data::UserData::NoData;
+
+    thread_local! {
+        /// The number of hooks still active when the state was dropped.
+        static HOOKS_AT_DROP: Cell<Option<usize>> = const { Cell::new(None) };
+    }
+
+    struct State;
+
+    impl Drop for State {
+        fn drop(&mut self) {
+            HOOKS_AT_DROP.with(|h| h.set(Some(Hook::active().len())));
         }
-    }));
+    }
+
+    #[test]
+    fn callbacks_look_up_the_state() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        let (mut name, mut desc, mut version) = (null(), null(), null());
+        lib_hexchat_plugin_init_with_state(
+            hc, &mut name, &mut desc, &mut version,
+            Box::new(|hc| {
+                hc.hook_command("COUNT", Priority::Norm, |hc, _, _, _| {
+                    hc.try_with_state(|count: &mut u32| *count += 1);
+                    Eat::All
+                }, "", NoData);
+                Some(0u32)
+            }),
+            Box::new(|| PluginInfo::new("count", "1", "")));
+
+        mock.inject_command("COUNT");
+        mock.inject_command("COUNT");
+        assert_eq!(hc.try_with_state(|count: &mut u32| *count), Some(2));
+        assert_eq!(hc.try_with_state(|_: &mut State| ()), None);
+
+        lib_hexchat_plugin_deinit_with_state::<u32>(hc, Box::new(|_, _| 1));
+        assert_eq!(hc.try_with_state(|count: &mut u32| *count), None);
+    }
+
+    #[test]
+    fn state_outlives_hooks_when_deinit_panics() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        let (mut name, mut desc, mut version) = (null(), null(), null());
+        let init = lib_hexchat_plugin_init_with_state(
+                       hc, &mut name, &mut desc, &mut version,
+                       Box::new(|hc| {
+                           hc.hook_command("S", Priority::Norm,
+                                           |_, _, _, _| Eat::All, "", NoData);
+                           Some(State)
+                       }),
+                       Box::new(|| PluginInfo::new("state", "1", "")));
+        assert_eq!(init, 1);
+
+        let deinit = lib_hexchat_plugin_deinit_with_state::<State>(
+                         hc, Box::new(|_, _| panic!("deinit panicked")));
+        assert_eq!(deinit, 0);
+        assert_eq!(HOOKS_AT_DROP.with(Cell::get), Some(0));
+        assert_eq!(mock.inject_command("S"), Eat::None);
+    }
 }
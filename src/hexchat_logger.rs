@@ -0,0 +1,231 @@
+#![cfg(feature = "log")]
+
+//! A backend for the [log](https://crates.io/crates/log) facade that prints
+//! records into Hexchat. It's installed when the plugin is loaded and
+//! silenced when it's unloaded, so `log::info!()` and friends can be used
+//! from the plugin and the libraries it depends on without any setup.
+//! Records can be logged from any thread; they're printed on Hexchat's main
+//! thread using `main_thread()`.
+//!
+//! By default, records at `Info` and above are printed to the active window.
+//! `Hexchat::set_log_config()` can change the level, and send each level to a
+//! given channel or a query window of the plugin's own.
+//!
+//! ```no_test
+//! hc.set_log_config(LogConfig::new()
+//!                       .level(LevelFilter::Debug)
+//!                       .target(LogTarget::Tab("myplugin".into()))
+//!                       .level_target(Level::Error, LogTarget::Active));
+//! ```
+
+use std::sync::RwLock;
+
+use log::{Level, LevelFilter, Log, Metadata, Record};
+
+use crate::consts::*;
+use crate::context::{open_tab, Context};
+use crate::hexchat::Hexchat;
+use crate::thread_facilities::main_thread;
+
+/// The logger's configuration. `None` while the plugin isn't loaded.
+///
+static LOG_CONFIG: RwLock<Option<LogConfig>> = RwLock::new(None);
+
+/// The logger registered with the `log` crate.
+///
+static LOGGER: HexchatLogger = HexchatLogger;
+
+/// Where log records are printed.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum LogTarget {
+    /// The window active when the record is printed.
+    Active,
+    /// A channel or query, given by network and channel name.
+    Context(String, String),
+    /// A query window of the plugin's own, opened if needed.
+    Tab(String),
+}
+
+/// Which log records are printed, and where.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LogConfig {
+    level   : LevelFilter,
+    targets : [LogTarget; 5],
+}
+
+impl LogConfig {
+    /// Creates the default configuration: records at `Info` and above are
+    /// printed to the active window.
+    ///
+    pub fn new() -> Self {
+        LogConfig {
+            level   : LevelFilter::Info,
+            targets : [LogTarget::Active, LogTarget::Active, LogTarget::Active,
+                       LogTarget::Active, LogTarget::Active],
+        }
+    }
+
+    /// Sets the most verbose level that's printed.
+    /// # Arguments
+    /// * `level` - The level filter.
+    ///
+    pub fn level(mut self, level: LevelFilter) -> Self {
+        self.level = level;
+        self
+    }
+
+    /// Sends records of every level to `target`.
+    /// # Arguments
+    /// * `target` - Where records are printed.
+    ///
+    pub fn target(mut self, target: LogTarget) -> Self {
+        self.targets = [target.clone(), target.clone(), target.clone(),
+                        target.clone(), target];
+        self
+    }
+
+    /// Sends records of one level to `target`.
+    /// # Arguments
+    /// * `level`  - The level of the records.
+    /// * `target` - Where they're printed.
+    ///
+    pub fn level_target(mut self, level: Level, target: LogTarget) -> Self {
+        self.targets[level as usize - 1] = target;
+        self
+    }
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig::new()
+    }
+}
+
+impl Hexchat {
+    /// Replaces the configuration of the logger that prints `log` records
+    /// into Hexchat.
+    /// # Arguments
+    /// * `config` - Which records are printed, and where.
+    ///
+    pub fn set_log_config(&self, config: LogConfig) {
+        log::set_max_level(config.level);
+        *LOG_CONFIG.write().unwrap() = Some(config);
+    }
+}
+
+/// Prints `log` records into Hexchat.
+///
+struct HexchatLogger;
+
+impl Log for HexchatLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        LOG_CONFIG.read().unwrap()
+                  .as_ref()
+                  .is_some_and(|config| metadata.level() <= config.level)
+    }
+
+    fn log(&self, record: &Record) {
+        let target = match LOG_CONFIG.read().unwrap().as_ref() {
+            Some(config) if record.level() <= config.level => {
+                config.targets[record.level() as usize - 1].clone()
+            },
+            _ => return,
+        };
+        let color = match record.level() {
+            Level::Error => IRC_RED,
+            Level::Warn  => IRC_OLIVE,
+            Level::Info  => IRC_GREEN,
+            Level::Debug => IRC_TEAL,
+            Level::Trace => IRC_GRAY,
+        };
+        let line = format!("{}{}\t{}: {}",
+                           color, record.level(), record.target(),
+                           record.args());
+
+        main_thread(move |hc| {
+            let ctx = match &target {
+                LogTarget::Active        => None,
+                LogTarget::Context(n, c) => Context::find(n, c),
+                LogTarget::Tab(name)     => open_tab(name),
+            };
+            match ctx {
+                Some(ctx) => { let _ = ctx.print(&line); },
+                None      => hc.print(&line),
+            }
+        });
+    }
+
+    fn flush(&self) {}
+}
+
+/// Installs the logger with the default configuration. Called when the
+/// plugin is loaded. If the plugin has already set a logger of its own, that
+/// one is left in place.
+///
+pub (crate) fn logger_init() {
+    *LOG_CONFIG.write().unwrap() = Some(LogConfig::new());
+    if log::set_logger(&LOGGER).is_ok() {
+        log::set_max_level(LevelFilter::Info);
+    }
+}
+
+/// Silences the logger. Called when the plugin is unloaded. The `log` crate
+/// doesn't allow a logger to be removed, so it's turned off instead.
+///
+pub (crate) fn logger_deinit() {
+    log::set_max_level(LevelFilter::Off);
+    LOG_CONFIG.write().unwrap().take();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_targets_override_the_default() {
+        let tab    = LogTarget::Tab("log".into());
+        let config = LogConfig::new().target(tab.clone())
+                                     .level_target(Level::Error,
+                                                   LogTarget::Active);
+        assert_eq!(config.targets[Level::Error as usize - 1],
+                   LogTarget::Active);
+        assert!(config.targets[1..].iter().all(|t| *t == tab));
+        assert_eq!(LogConfig::default().level, LevelFilter::Info);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn records_are_filtered_and_routed() {
+        use crate::testing::MockHexchat;
+
+        let mock = MockHexchat::new();
+        mock.add_context("MockNet", "#other");
+        mock.hexchat().set_log_config(
+            LogConfig::new().level_target(Level::Warn,
+                                          LogTarget::Context(
+                                              "MockNet".into(),
+                                              "#other".into())));
+        let log = |level, text| {
+            LOGGER.log(&Record::builder().level(level)
+                                         .target("plugin")
+                                         .args(format_args!("{}", text))
+                                         .build());
+        };
+        log(Level::Info, "started");
+        log(Level::Warn, "careful");
+        log(Level::Debug, "hidden");
+        logger_deinit();
+        log(Level::Error, "unloaded");
+
+        let printed: Vec<_> = mock.printed().into_iter()
+                                  .map(|p| (p.channel, p.text))
+                                  .collect();
+        assert_eq!(printed,
+                   vec![("#mock".to_string(),
+                         format!("{}INFO\tplugin: started", IRC_GREEN)),
+                        ("#other".to_string(),
+                         format!("{}WARN\tplugin: careful", IRC_OLIVE))]);
+    }
+}
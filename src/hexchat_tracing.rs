@@ -0,0 +1,438 @@
+#![cfg(feature = "tracing")]
+
+//! A [tracing-subscriber](https://crates.io/crates/tracing-subscriber) layer
+//! that prints [tracing](https://crates.io/crates/tracing) events into
+//! Hexchat. Each event is printed with the spans it occurred in and their
+//! fields, followed by its own message and fields. Events can be recorded on
+//! any thread; they're printed on Hexchat's main thread using
+//! `main_thread()`.
+//!
+//! Which events are printed is decided per target, by the most specific
+//! target prefix that has a level set, falling back on a default level. The
+//! levels can be changed while the plugin runs with a command registered by
+//! `Hexchat::hook_trace_command()`.
+//!
+//! ```no_test
+//! use tracing_subscriber::prelude::*;
+//!
+//! let layer = HexchatLayer::new().level(LevelFilter::WARN)
+//!                                .target_level("myplugin", LevelFilter::DEBUG)
+//!                                .tab("myplugin-trace");
+//!
+//! hc.hook_trace_command("MYTRACE", layer.filter());
+//! tracing_subscriber::registry().with(layer).init();
+//! ```
+//! With that in place, `/MYTRACE myplugin::net trace` prints everything from
+//! the `myplugin::net` module, and `/MYTRACE` lists the levels.
+
+use std::fmt::{self, Write};
+use std::sync::{Arc, RwLock};
+
+use tracing::field::{Field, Visit};
+use tracing::level_filters::LevelFilter;
+use tracing::span::{Attributes, Id, Record};
+use tracing::{Event, Level, Subscriber};
+use tracing_subscriber::layer::{Context as LayerContext, Layer};
+use tracing_subscriber::registry::LookupSpan;
+
+use crate::command_spec::{Arg, CommandSpec};
+use crate::consts::*;
+use crate::context::open_tab;
+use crate::hexchat::{Eat, Hexchat, Priority};
+use crate::hook::Hook;
+use crate::thread_facilities::main_thread;
+use crate::user_data::UserData::NoData;
+
+/// A `tracing-subscriber` layer that prints events into Hexchat, to the
+/// active window or a tab of its own.
+///
+pub struct HexchatLayer {
+    filter : TraceFilter,
+    tab    : Option<String>,
+}
+
+impl HexchatLayer {
+    /// Creates a layer that prints events at `INFO` and above, from any
+    /// target, to the active window.
+    ///
+    pub fn new() -> Self {
+        HexchatLayer {
+            filter : TraceFilter::new(LevelFilter::INFO),
+            tab    : None,
+        }
+    }
+
+    /// Sets the default level, used for targets without a level of their own.
+    /// # Arguments
+    /// * `level` - The most verbose level that's printed.
+    ///
+    pub fn level(self, level: LevelFilter) -> Self {
+        self.filter.set_default(level);
+        self
+    }
+
+    /// Sets the level of a target and the targets under it.
+    /// # Arguments
+    /// * `target` - The target, such as a module path, e.g. `myplugin::net`.
+    /// * `level`  - The most verbose level that's printed for it.
+    ///
+    pub fn target_level(self, target: &str, level: LevelFilter) -> Self {
+        self.filter.set_target(target, level);
+        self
+    }
+
+    /// Prints events to a tab of their own, which is opened if needed,
+    /// instead of the active window.
+    /// # Arguments
+    /// * `name` - The name of the tab.
+    ///
+    pub fn tab(mut self, name: &str) -> Self {
+        self.tab = Some(name.to_string());
+        self
+    }
+
+    /// Returns a handle to the layer's levels, which can be changed after the
+    /// layer has been installed.
+    ///
+    pub fn filter(&self) -> TraceFilter {
+        self.filter.clone()
+    }
+}
+
+impl Default for HexchatLayer {
+    fn default() -> Self {
+        HexchatLayer::new()
+    }
+}
+
+impl<S> Layer<S> for HexchatLayer
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    fn on_new_span(&self, attrs: &Attributes<'_>, id: &Id,
+                   ctx: LayerContext<'_, S>)
+    {
+        if let Some(span) = ctx.span(id) {
+            let mut visitor = FieldVisitor::default();
+            attrs.record(&mut visitor);
+            span.extensions_mut().insert(SpanFields(visitor.fields));
+        }
+    }
+
+    fn on_record(&self, id: &Id, values: &Record<'_>,
+                 ctx: LayerContext<'_, S>)
+    {
+        if let Some(span) = ctx.span(id) {
+            let mut visitor = FieldVisitor::default();
+            values.record(&mut visitor);
+            let mut extensions = span.extensions_mut();
+            match extensions.get_mut::<SpanFields>() {
+                Some(SpanFields(fields)) => fields.push_str(&visitor.fields),
+                None => extensions.insert(SpanFields(visitor.fields)),
+            }
+        }
+    }
+
+    fn on_event(&self, event: &Event<'_>, ctx: LayerContext<'_, S>) {
+        let meta = event.metadata();
+        if *meta.level() > self.filter.level_for(meta.target()) {
+            return;
+        }
+        let mut scope = String::new();
+        if let Some(spans) = ctx.event_scope(event) {
+            for span in spans.from_root() {
+                let extensions = span.extensions();
+                let fields     = extensions.get::<SpanFields>()
+                                           .map_or("", |f| f.0.trim_start());
+                if fields.is_empty() {
+                    let _ = write!(scope, "{}:", span.name());
+                } else {
+                    let _ = write!(scope, "{}{{{}}}:", span.name(), fields);
+                }
+            }
+            scope.push(' ');
+        }
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
+
+        let color = match *meta.level() {
+            Level::ERROR => IRC_RED,
+            Level::WARN  => IRC_OLIVE,
+            Level::INFO  => IRC_GREEN,
+            Level::DEBUG => IRC_TEAL,
+            Level::TRACE => IRC_GRAY,
+        };
+        let body = format!("{}{}", visitor.message, visitor.fields);
+        let line = format!("{}{}\t{}: {}{}",
+                           color, meta.level(), meta.target(), scope,
+                           body.trim_start());
+        let tab  = self.tab.clone();
+
+        main_thread(move |hc| {
+            match tab.as_deref().and_then(open_tab) {
+                Some(ctx) => { let _ = ctx.print(&line); },
+                None      => hc.print(&line),
+            }
+        });
+    }
+}
+
+/// The levels of a `HexchatLayer`, shared with the layer so they can be
+/// changed while it's installed. Clones share the same levels.
+///
+#[derive(Clone, Debug)]
+pub struct TraceFilter {
+    levels: Arc<RwLock<TraceLevels>>,
+}
+
+#[derive(Debug)]
+struct TraceLevels {
+    default : LevelFilter,
+    targets : Vec<(String, LevelFilter)>,
+}
+
+impl TraceFilter {
+    fn new(default: LevelFilter) -> Self {
+        TraceFilter {
+            levels: Arc::new(RwLock::new(TraceLevels {
+                        default,
+                        targets : vec![],
+                    })),
+        }
+    }
+
+    /// Sets the default level, used for targets without a level of their own.
+    /// # Arguments
+    /// * `level` - The most verbose level that's printed.
+    ///
+    pub fn set_default(&self, level: LevelFilter) {
+        self.levels.write().unwrap().default = level;
+    }
+
+    /// Sets the level of a target and the targets under it, replacing the
+    /// level it had.
+    /// # Arguments
+    /// * `target` - The target, such as a module path, e.g. `myplugin::net`.
+    /// * `level`  - The most verbose level that's printed for it.
+    ///
+    pub fn set_target(&self, target: &str, level: LevelFilter) {
+        let mut levels = self.levels.write().unwrap();
+        match levels.targets.iter_mut().find(|(t, _)| t == target) {
+            Some(entry) => entry.1 = level,
+            None        => levels.targets.push((target.to_string(), level)),
+        }
+    }
+
+    /// Removes a target's own level, so it falls back on the level of the
+    /// target above it, or the default.
+    /// # Arguments
+    /// * `target` - The target whose level is removed.
+    /// # Returns
+    /// * `true` if the target had a level of its own.
+    ///
+    pub fn remove_target(&self, target: &str) -> bool {
+        let mut levels = self.levels.write().unwrap();
+        let     len    = levels.targets.len();
+        levels.targets.retain(|(t, _)| t != target);
+        levels.targets.len() != len
+    }
+
+    /// Returns the level that applies to a target: the level of the most
+    /// specific target prefix that has one, or else the default.
+    /// # Arguments
+    /// * `target` - The target of an event, e.g. `myplugin::net::irc`.
+    ///
+    pub fn level_for(&self, target: &str) -> LevelFilter {
+        let levels = self.levels.read().unwrap();
+        levels.targets.iter()
+              .filter(|(t, _)| {
+                  target.strip_prefix(t.as_str())
+                        .is_some_and(|rest| rest.is_empty()
+                                            || rest.starts_with("::"))
+              })
+              .max_by_key(|(t, _)| t.len())
+              .map_or(levels.default, |(_, level)| *level)
+    }
+
+    /// Returns the default level and the levels set for targets, sorted by
+    /// target.
+    ///
+    pub fn levels(&self) -> (LevelFilter, Vec<(String, LevelFilter)>) {
+        let levels      = self.levels.read().unwrap();
+        let mut targets = levels.targets.clone();
+        targets.sort();
+        (levels.default, targets)
+    }
+}
+
+impl Hexchat {
+    /// Registers a command that shows and changes the levels of a
+    /// `HexchatLayer` while the plugin runs. With no arguments it lists the
+    /// levels. Given only a level, it sets the default level. Given a target
+    /// and a level, it sets the target's level; the level `reset` removes the
+    /// target's own level. Levels are `off`, `error`, `warn`, `info`,
+    /// `debug`, and `trace`.
+    /// # Arguments
+    /// * `name`   - The name of the command.
+    /// * `filter` - The layer's levels, from `HexchatLayer::filter()`.
+    /// # Returns
+    /// * A `Hook` object associated with the command.
+    ///
+    #[track_caller]
+    pub fn hook_trace_command(&self, name: &str, filter: TraceFilter) -> Hook {
+        let spec = CommandSpec::new(name)
+                       .description("Shows or sets which tracing events are \
+                                     printed.")
+                       .arg(Arg::optional("target")
+                                .help("A target, or a level to set the \
+                                       default level."))
+                       .arg(Arg::optional("level")
+                                .help("The target's level, or `reset`."));
+
+        self.hook_command_spec(spec, Priority::Norm, move |hc, args, _ud| {
+            match (args.str("target"), args.str("level")) {
+                (None, _) => {
+                    let (default, targets) = filter.levels();
+                    hc.print(&format!("Default level: {}", default));
+                    for (target, level) in targets {
+                        hc.print(&format!("  {}: {}", target, level));
+                    }
+                },
+                (Some(target), None) => match target.parse() {
+                    Ok(level) => {
+                        filter.set_default(level);
+                        hc.print(&format!("Default level set to {}.", level));
+                    },
+                    Err(_) => {
+                        hc.print(&format!("{}: {}",
+                                          target, filter.level_for(target)));
+                    },
+                },
+                (Some(target), Some("reset")) => {
+                    if filter.remove_target(target) {
+                        hc.print(&format!("{} now uses level {}.",
+                                          target, filter.level_for(target)));
+                    } else {
+                        hc.print(&format!("{} has no level of its own.",
+                                          target));
+                    }
+                },
+                (Some(target), Some(level)) => match level.parse() {
+                    Ok(level) => {
+                        filter.set_target(target, level);
+                        hc.print(&format!("{} level set to {}.",
+                                          target, level));
+                    },
+                    Err(_) => {
+                        hc.print(&format!("\x0304Unknown level: {}", level));
+                    },
+                },
+            }
+            Eat::All
+        }, NoData)
+    }
+}
+
+/// The fields recorded for a span, formatted as ` name=value` pairs.
+///
+struct SpanFields(String);
+
+/// Formats the fields of a span or event. An event's `message` field is kept
+/// apart, so it can be printed ahead of the other fields.
+///
+#[derive(Default)]
+struct FieldVisitor {
+    message : String,
+    fields  : String,
+}
+
+impl Visit for FieldVisitor {
+    fn record_str(&mut self, field: &Field, value: &str) {
+        if field.name() == "message" {
+            self.message = value.to_string();
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+
+    fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+        if field.name() == "message" {
+            self.message = format!("{:?}", value);
+        } else {
+            let _ = write!(self.fields, " {}={:?}", field.name(), value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn level_for_uses_the_most_specific_target() {
+        let filter = HexchatLayer::new().level(LevelFilter::WARN)
+                                        .target_level("app", LevelFilter::INFO)
+                                        .target_level("app::net",
+                                                      LevelFilter::TRACE)
+                                        .filter();
+        assert_eq!(filter.level_for("app"), LevelFilter::INFO);
+        assert_eq!(filter.level_for("app::ui"), LevelFilter::INFO);
+        assert_eq!(filter.level_for("app::net::irc"), LevelFilter::TRACE);
+        assert_eq!(filter.level_for("apple"), LevelFilter::WARN);
+        assert_eq!(filter.level_for("other"), LevelFilter::WARN);
+
+        filter.set_target("app", LevelFilter::OFF);
+        assert!(filter.remove_target("app::net"));
+        assert!(!filter.remove_target("app::net"));
+        assert_eq!(filter.level_for("app::net::irc"), LevelFilter::OFF);
+        assert_eq!(filter.levels(),
+                   (LevelFilter::WARN,
+                    vec![("app".to_string(), LevelFilter::OFF)]));
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn events_print_with_their_spans() {
+        use tracing_subscriber::prelude::*;
+        use crate::testing::MockHexchat;
+
+        let mock  = MockHexchat::new();
+        let layer = HexchatLayer::new().target_level("quiet",
+                                                     LevelFilter::ERROR);
+        let subscriber = tracing_subscriber::registry().with(layer);
+        tracing::subscriber::with_default(subscriber, || {
+            let span = tracing::info_span!("conn", id = 7);
+            let _in  = span.enter();
+            tracing::info!(target: "app", nick = "amy", "joined");
+            tracing::warn!(target: "quiet", "dropped");
+            tracing::debug!(target: "app", "dropped");
+        });
+        assert_eq!(mock.printed_text(),
+                   vec![format!("{}INFO\tapp: conn{{id=7}}: joined \
+                                 nick=\"amy\"", IRC_GREEN)]);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn trace_command_changes_levels() {
+        use crate::testing::MockHexchat;
+
+        let mock   = MockHexchat::new();
+        let filter = HexchatLayer::new().filter();
+        let _hook  = mock.hexchat().hook_trace_command("TRACE",
+                                                       filter.clone());
+        mock.inject_command("TRACE debug");
+        mock.inject_command("TRACE app::net trace");
+        mock.inject_command("TRACE app::net bogus");
+        assert_eq!(filter.level_for("app"), LevelFilter::DEBUG);
+        assert_eq!(filter.level_for("app::net"), LevelFilter::TRACE);
+
+        mock.inject_command("TRACE app::net reset");
+        assert_eq!(filter.level_for("app::net"), LevelFilter::DEBUG);
+        assert_eq!(mock.printed_text(),
+                   vec!["Default level set to debug.",
+                        "app::net level set to trace.",
+                        "\x0304Unknown level: bogus",
+                        "app::net now uses level debug."]);
+    }
+}
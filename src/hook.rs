@@ -12,17 +12,36 @@
 //! pointer. When hooks go out of scope, they do not remove their associated
 //! commands. Hooks can be ignored by the plugin if there is no need to
 //! unhook commands. The most relevant use of a hook could be to cancel
-//! timer callbacks.
+//! timer callbacks. For callbacks that should be removed when they go out of
+//! scope, `Hook::into_guard()` returns a `ScopedHook`, which does.
+//!
+//! Hooks can also be paused with `pause()` and picked up again with
+//! `resume()`, which keeps the callback and its user data in place.
 
 use libc::c_void;
+use std::fmt;
+use std::ops::Deref;
+use std::panic::Location;
 use std::ptr::null;
-use std::sync::RwLock;
+use std::sync::{Mutex, RwLock};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::Duration;
+#[cfg(feature = "threadsafe")]
+use std::thread;
 
 use send_wrapper::SendWrapper;
 
 use crate::callback_data::*;
+use crate::hexchat::{timer_millis, TimerAction};
+use crate::hexchat_callbacks::c_timer_callback;
 use crate::hexchat_entry_points::PHEXCHAT;
+use crate::hook_group::add_to_collecting_groups;
+#[cfg(feature = "metrics")]
+use crate::hook_metrics::HookMetrics;
+use crate::panic_policy::PanicPolicy;
+#[cfg(feature = "threadsafe")]
+use crate::thread_facilities::{main_thread, MAIN_THREAD_ID};
 use crate::user_data::{*, UserData::*};
 
 /// A synchronized global list of the hooks. This gets initialized when a
@@ -37,6 +56,73 @@ struct HookData {
     cbd_box_ptr : *const c_void,
 }
 
+/// The kinds of callbacks that can be hooked.
+///
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HookKind {
+    Command,
+    Print,
+    Server,
+    Timer,
+    Fd,
+}
+
+impl fmt::Display for HookKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        use HookKind::*;
+        let name = match self {
+            Command => "command",
+            Print   => "print",
+            Server  => "server",
+            Timer   => "timer",
+            Fd      => "fd",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// What a hook was registered for. Unlike the hook pointers, this can be read
+/// from any thread.
+///
+pub (crate) struct HookInfo {
+    kind     : HookKind,
+    name     : String,
+    priority : i32,
+    location : &'static Location<'static>,
+    panics   : Mutex<PanicCount>,
+    paused   : AtomicBool,
+    groups   : AtomicU32,
+    #[cfg(feature = "metrics")]
+    metrics  : Mutex<HookMetrics>,
+}
+
+/// The hook's own panic policy, if it has one, and how many times its
+/// callback has panicked.
+///
+struct PanicCount {
+    policy : Option<PanicPolicy>,
+    count  : u32,
+}
+
+impl HookInfo {
+    /// Returns the metrics recorded for the hook's callback.
+    ///
+    #[cfg(feature = "metrics")]
+    pub (crate) fn metrics(&self) -> &Mutex<HookMetrics> {
+        &self.metrics
+    }
+}
+
+impl fmt::Display for HookInfo {
+    /// Describes the hook for panic reports, e.g.
+    /// `command hook "FOO" (priority 0)`.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} hook \"{}\" (priority {})",
+               self.kind, self.name, self.priority)
+    }
+}
+
 /// A wrapper for Hexchat callback hooks. These hooks are returned when
 /// registering callbacks and can be used to unregister (unhook) them.
 /// `Hook`s can be cloned to share a reference to the same callback hook.
@@ -44,15 +130,22 @@ struct HookData {
 #[derive(Clone)]
 pub struct Hook {
     data: Arc<RwLock<Option<SendWrapper<HookData>>>>,
+    info: Arc<HookInfo>,
 }
 
 unsafe impl Send for Hook {}
 
 impl Hook {
-    /// Constructor. `hook` is a hook returned by Hexchat when registering a
-    /// C-facing callback.
+    /// Constructor. The hook pointer Hexchat returns when the C-facing
+    /// callback is registered is set afterward with `set()`.
+    /// # Arguments
+    /// * `kind`     - The kind of callback being hooked.
+    /// * `name`     - The command or event name, or a description of the
+    ///   timer or fd.
+    /// * `priority` - The priority the callback is registered with.
     ///
-    pub (crate) fn new() -> Self {
+    #[track_caller]
+    pub (crate) fn new(kind: HookKind, name: &str, priority: i32) -> Self {
 
         let hook = Hook {
             data: Arc::new(
@@ -63,6 +156,18 @@ impl Hook {
                                     hook_ptr    : null::<c_void>(),
                                     cbd_box_ptr : null::<c_void>(),
                         })))),
+            info: Arc::new(HookInfo {
+                    kind,
+                    name     : name.to_string(),
+                    priority,
+                    location : Location::caller(),
+                    panics   : Mutex::new(PanicCount { policy : None,
+                                                       count  : 0 }),
+                    paused   : AtomicBool::new(false),
+                    groups   : AtomicU32::new(0),
+                    #[cfg(feature = "metrics")]
+                    metrics  : Mutex::new(HookMetrics::default()),
+                  }),
         };
 
         if let Some(hook_list) = HOOK_LIST.write().unwrap().as_mut() {
@@ -74,6 +179,7 @@ impl Hook {
             // Store newly created hook in global list.
             hook_list.push(hook.clone());
         }
+        add_to_collecting_groups(&hook);
 
         hook
     }
@@ -110,7 +216,7 @@ impl Hook {
     ///   hexchat hook functions.
     ///
     pub fn unhook(&self) -> UserData {
-        unsafe {
+        let cd = unsafe {
             if let Some(_hook_list) = HOOK_LIST.read().unwrap().as_ref() {
                 let ptr_data = &mut self.data.write().unwrap();
 
@@ -131,15 +237,195 @@ impl Hook {
 
                     // Reconstitute the CallbackData Box.
                     let cd = ptr_data.as_ref().unwrap().cbd_box_ptr;
-                    let cd = &mut (*(cd as *mut CallbackData));
-                    let cd = &mut Box::from_raw(cd);
+                    Some(Box::from_raw(cd as *mut CallbackData))
+                } else {
+                    None
+                }
+            } else {
+                None
+            }
+        };
+        // The locks are released before the CallbackData is dropped. Its
+        // callback or user data may hold the last `ScopedHook` of this hook,
+        // which unhooks it again when dropped.
+        match cd {
+            // Give the caller the `user_data` the plugin registered with the
+            // callback.
+            Some(mut cd) => cd.take_data(),
+            None         => NoData,
+        }
+    }
 
-                    // Give the caller the `user_data` the plugin registered
-                    // with the callback.
-                    return cd.take_data();
+    /// Carries out the `TimerAction` returned by the callback of a timer
+    /// registered with `Hexchat::hook_timer_duration()`.
+    /// # Returns
+    /// * The value for the callback to return to Hexchat: 0 to stop the
+    ///   timer, or 1 to keep it.
+    ///
+    pub (crate) fn apply_timer_action(&self, action: TimerAction) -> i32 {
+        match action {
+            TimerAction::Continue => 1,
+            TimerAction::Stop     => 0,
+            TimerAction::Reschedule(interval) => {
+                self.rehook_timer(interval);
+                1
+            },
+        }
+    }
+
+    /// Registers the timer again with a new interval, under the same
+    /// `CallbackData`, and replaces the old registration with it. This is
+    /// done from within the timer's own callback; Hexchat allows a callback
+    /// to unhook itself.
+    /// # Arguments
+    /// * `interval` - The timer's new interval.
+    ///
+    fn rehook_timer(&self, interval: Duration) {
+        if let Some(_hook_list) = HOOK_LIST.read().unwrap().as_ref() {
+            let ptr_data = &mut self.data.write().unwrap();
+            let data     = ptr_data.as_mut().unwrap();
+
+            if !data.hook_ptr.is_null() {
+                unsafe {
+                    let hc = &*PHEXCHAT;
+                    let _  = (hc.c_unhook)(hc, data.hook_ptr);
+                    data.hook_ptr = (hc.c_hook_timer)(
+                                        hc,
+                                        timer_millis(interval),
+                                        c_timer_callback,
+                                        data.cbd_box_ptr as *mut c_void);
                 }
             }
-            NoData
+        }
+    }
+
+    /// Turns the hook into a `ScopedHook`, which unhooks the callback when
+    /// the last clone of the `ScopedHook` is dropped.
+    ///
+    pub fn into_guard(self) -> ScopedHook {
+        ScopedHook { guard: Arc::new(UnhookOnDrop(self)) }
+    }
+
+    /// Sets the policy applied when this hook's callback panics, in place of
+    /// the default set by `Hexchat::set_panic_policy()`.
+    /// # Arguments
+    /// * `policy` - The hook's panic policy.
+    ///
+    pub fn set_panic_policy(&self, policy: PanicPolicy) -> &Self {
+        self.info.panics.lock().unwrap().policy = Some(policy);
+        self
+    }
+
+    /// Pauses the hook. While it's paused, its callback isn't invoked;
+    /// Hexchat is given `Eat::None` in its place, and timers keep running
+    /// without calling it. Unlike `unhook()`, the callback stays registered
+    /// with its priority, and keeps its user data and any state it holds.
+    /// This can be called from any thread.
+    ///
+    pub fn pause(&self) {
+        self.info.paused.store(true, Ordering::Relaxed);
+    }
+
+    /// Resumes the hook after `pause()`. A hook that's also in a paused
+    /// `HookGroup` stays paused until the group is resumed.
+    ///
+    pub fn resume(&self) {
+        self.info.paused.store(false, Ordering::Relaxed);
+    }
+
+    /// Counts a paused `HookGroup` the hook belongs to. The hook is paused
+    /// while any of its groups are.
+    ///
+    pub (crate) fn group_paused(&self) {
+        self.info.groups.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Counts a group of the hook's being resumed.
+    ///
+    pub (crate) fn group_resumed(&self) {
+        let _ = self.info.groups.fetch_update(Ordering::Relaxed,
+                                              Ordering::Relaxed,
+                                              |n| n.checked_sub(1));
+    }
+
+    /// Returns `true` if the hook is paused, by `pause()` or by a paused
+    /// `HookGroup` it belongs to.
+    ///
+    pub fn is_paused(&self) -> bool {
+        self.info.paused.load(Ordering::Relaxed)
+        || self.info.groups.load(Ordering::Relaxed) > 0
+    }
+
+    /// Returns the kind of callback the hook is for.
+    ///
+    pub fn kind(&self) -> HookKind {
+        self.info.kind
+    }
+
+    /// Returns the command or event name the hook was registered for. For
+    /// timers and fds, this describes the timeout or the fd instead.
+    ///
+    pub fn name(&self) -> &str {
+        &self.info.name
+    }
+
+    /// Returns the priority the hook was registered with. Timers and fds
+    /// don't have one, and report 0.
+    ///
+    pub fn priority(&self) -> i32 {
+        self.info.priority
+    }
+
+    /// Returns where in the plugin's source the hook was created.
+    ///
+    pub fn location(&self) -> &'static Location<'static> {
+        self.info.location
+    }
+
+    /// Returns `true` until the hook has been unhooked.
+    ///
+    pub fn is_hooked(&self) -> bool {
+        self.data.read().unwrap()
+                 .as_ref()
+                 .is_some_and(|data| !data.hook_ptr.is_null())
+    }
+
+    /// Returns what the hook was registered for.
+    ///
+    pub (crate) fn info(&self) -> &Arc<HookInfo> {
+        &self.info
+    }
+
+    /// Counts a panic of the hook's callback.
+    /// # Returns
+    /// * The hook's own panic policy, if it has one, and the number of times
+    ///   its callback has now panicked.
+    ///
+    pub (crate) fn count_panic(&self) -> (Option<PanicPolicy>, u32) {
+        let mut panics = self.info.panics.lock().unwrap();
+        panics.count += 1;
+        (panics.policy, panics.count)
+    }
+
+    /// Returns the hooks that are still hooked, in the order they were
+    /// created.
+    ///
+    pub (crate) fn active() -> Vec<Hook> {
+        HOOK_LIST.read().unwrap()
+                 .as_ref()
+                 .map(|hooks| {
+                     hooks.iter().filter(|h| h.is_hooked()).cloned().collect()
+                 })
+                 .unwrap_or_default()
+    }
+
+    /// Unhooks every hook the plugin has registered, leaving the hook list in
+    /// place so hooks can still be created afterward.
+    ///
+    pub (crate) fn unhook_all() {
+        let hooks = HOOK_LIST.read().unwrap().clone().unwrap_or_default();
+        for hook in hooks {
+            hook.unhook();
         }
     }
 
@@ -161,11 +447,9 @@ impl Hook {
     /// are called.
     ///
     pub (crate) fn deinit() {
-        if let Some(hook_list) = HOOK_LIST.read().unwrap().as_ref() {
-            for hook in hook_list {
-                hook.unhook();
-            }
-        }
+        // The list is copied so it isn't locked while the callback data of
+        // the hooks is dropped.
+        Hook::unhook_all();
         // This causes the `RwLock` and hook vector to be dropped.
         // plugin authors need to ensure that no threads are running when
         // their plugins are unloading - or one may try to access the lock
@@ -176,5 +460,161 @@ impl Hook {
     }
 }
 
+/// A hook that unhooks its callback when the last clone of it is dropped,
+/// for callbacks that are only needed for a while, such as waiting for a
+/// single reply from the server. It derefs to the `Hook`, so it can also be
+/// unhooked or paused early. Only clones of the `ScopedHook` keep the
+/// callback hooked; plain `Hook`s cloned from it don't.
+///
+/// ```no_test
+/// let whois = hc.hook_server("311", Priority::Norm, on_whois, NoData)
+///               .into_guard();
+/// ```
+///
+#[derive(Clone)]
+pub struct ScopedHook {
+    guard: Arc<UnhookOnDrop>,
+}
+
+impl Deref for ScopedHook {
+    type Target = Hook;
+
+    fn deref(&self) -> &Hook {
+        &self.guard.0
+    }
+}
+
+/// Unhooks the hook it holds when dropped. Unhooking an already unhooked
+/// hook does nothing, so the hook can safely be unhooked early, or by
+/// `Hook::deinit()` when the plugin is unloaded. If it's dropped off the main
+/// thread, the unhooking is handed to the main thread.
+///
+struct UnhookOnDrop(Hook);
+
+impl Drop for UnhookOnDrop {
+    fn drop(&mut self) {
+        #[cfg(feature = "threadsafe")]
+        if Some(thread::current().id()) != unsafe { MAIN_THREAD_ID } {
+            let hook = self.0.clone();
+            main_thread(move |_hc| { hook.unhook(); });
+            return;
+        }
+        self.0.unhook();
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use super::*;
+    use crate::hexchat::{Eat, Priority, TimerAction};
+    use crate::hook_group::HookGroup;
+    use crate::testing::MockHexchat;
 
+    #[test]
+    fn scoped_hook_held_by_its_own_callback() {
+        let mock  = MockHexchat::new();
+        let hc    = mock.hexchat();
+        let slot  = Rc::new(RefCell::new(None::<ScopedHook>));
+        let held  = slot.clone();
+        let hook  = hc.hook_command("ONCE", Priority::Norm, move |_, _, _, _| {
+                        let _ = &held;
+                        Eat::All
+                    }, "", NoData);
+        *slot.borrow_mut() = Some(hook.clone().into_guard());
+        drop(slot);
 
+        assert_eq!(mock.inject_command("ONCE"), Eat::All);
+        hook.unhook();
+        assert!(!hook.is_hooked());
+        assert_eq!(mock.inject_command("ONCE"), Eat::None);
+    }
+
+    #[test]
+    fn scoped_hook_held_by_its_user_data() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        let slot = Rc::new(RefCell::new(None::<ScopedHook>));
+        let hook = hc.hook_command("DATA", Priority::Norm,
+                                   |_, _, _, _| Eat::All, "",
+                                   UserData::shared(slot.clone()));
+        *slot.borrow_mut() = Some(hook.clone().into_guard());
+        drop(slot);
+
+        // Unloading the plugin drops the user data, and the guard with it.
+        drop(mock);
+        assert!(!hook.is_hooked());
+    }
+
+    #[test]
+    fn own_pause_and_group_pauses_are_separate() {
+        let mock   = MockHexchat::new();
+        let hc     = mock.hexchat();
+        let hook   = hc.hook_command("P", Priority::Norm,
+                                     |_, _, _, _| Eat::All, "", NoData);
+        let (a, b) = (HookGroup::new(), HookGroup::new());
+        a.add(&hook);
+        b.add(&hook);
+
+        hook.pause();
+        a.pause();
+        hook.resume();
+        assert!(hook.is_paused());
+        assert_eq!(mock.inject_command("P"), Eat::None);
+
+        b.pause();
+        b.pause();
+        a.resume();
+        assert!(hook.is_paused());
+        b.resume();
+        assert!(!hook.is_paused());
+        assert_eq!(mock.inject_command("P"), Eat::All);
+
+        a.pause();
+        hook.pause();
+        a.resume();
+        assert!(hook.is_paused());
+        hook.resume();
+        assert_eq!(mock.inject_command("P"), Eat::All);
+    }
+
+    #[test]
+    fn timer_actions_reschedule_and_stop() {
+        let mock  = MockHexchat::new();
+        let hc    = mock.hexchat();
+        let ticks = Rc::new(RefCell::new(0));
+        let t     = ticks.clone();
+        let hook  = hc.hook_timer_duration(
+                        Duration::from_millis(10),
+                        move |_, _| {
+                            *t.borrow_mut() += 1;
+                            match *t.borrow() {
+                                1 => TimerAction::Reschedule(
+                                         Duration::from_millis(50)),
+                                2 => TimerAction::Continue,
+                                _ => TimerAction::Stop,
+                            }
+                        }, NoData);
+
+        mock.advance(Duration::from_millis(10));
+        assert_eq!(*ticks.borrow(), 1);
+        mock.advance(Duration::from_millis(49));
+        assert_eq!(*ticks.borrow(), 1);
+        mock.advance(Duration::from_millis(1));
+        assert_eq!(*ticks.borrow(), 2);
+        assert!(hook.is_hooked());
+
+        // The rescheduled timer still answers to the same hook.
+        hook.pause();
+        mock.advance(Duration::from_millis(50));
+        assert_eq!(*ticks.borrow(), 2);
+        hook.resume();
+        mock.advance(Duration::from_millis(50));
+        assert_eq!(*ticks.borrow(), 3);
+        assert!(!hook.is_hooked());
+        mock.advance(Duration::from_millis(100));
+        assert_eq!(*ticks.borrow(), 3);
+    }
+}
@@ -0,0 +1,144 @@
+//! Introspection of the plugin's hooks. `Hexchat::active_hooks()` describes
+//! every callback the plugin currently has hooked, including where in the
+//! plugin's source each was hooked, and `Hexchat::hook_diagnostic_command()`
+//! registers a `/<plugin>_hooks` command that prints the same list.
+
+use std::fmt;
+use std::panic::Location;
+
+use crate::hexchat::{Eat, Hexchat, Priority};
+use crate::hexchat_entry_points::plugin_name;
+use crate::hook::{Hook, HookKind};
+#[cfg(feature = "metrics")]
+use crate::hook_metrics::HookMetrics;
+use crate::user_data::UserData::NoData;
+
+/// A description of a hook, taken when `Hexchat::active_hooks()` is called.
+///
+#[derive(Clone, Debug)]
+pub struct HookDescriptor {
+    /// The kind of callback.
+    pub kind     : HookKind,
+    /// The command or event name, or a description of the timer or fd.
+    pub name     : String,
+    /// The priority the callback was registered with; 0 for timers and fds.
+    pub priority : i32,
+    /// Where in the source the hook was created.
+    pub location : &'static Location<'static>,
+    /// Whether the hook is paused.
+    pub paused   : bool,
+    /// How the hook's callback has performed.
+    #[cfg(feature = "metrics")]
+    pub metrics  : HookMetrics,
+}
+
+impl HookDescriptor {
+    fn from_hook(hook: &Hook) -> Self {
+        HookDescriptor {
+            kind     : hook.kind(),
+            name     : hook.name().to_string(),
+            priority : hook.priority(),
+            location : hook.location(),
+            paused   : hook.is_paused(),
+            #[cfg(feature = "metrics")]
+            metrics  : hook.metrics(),
+        }
+    }
+}
+
+impl fmt::Display for HookDescriptor {
+    /// Describes the hook on one line, e.g.
+    /// `command "FOO" (priority 0) at src/lib.rs:42:5`.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} \"{}\" (priority {}) at {}",
+               self.kind, self.name, self.priority, self.location)?;
+        if self.paused {
+            write!(f, " [paused]")?;
+        }
+        Ok(())
+    }
+}
+
+impl Hexchat {
+    /// Describes the callbacks the plugin currently has hooked, in the order
+    /// they were hooked. This includes hooks the library creates for the
+    /// plugin, such as the timer behind `main_thread()`.
+    ///
+    pub fn active_hooks(&self) -> Vec<HookDescriptor> {
+        Hook::active().iter().map(HookDescriptor::from_hook).collect()
+    }
+
+    /// Registers a `/<plugin>_hooks` command that prints the plugin's active
+    /// hooks. The plugin's name is lowercased, with anything other than
+    /// letters and digits replaced by `_`, e.g. `/my_plugin_hooks`.
+    /// # Returns
+    /// * A `Hook` object associated with the command.
+    ///
+    #[track_caller]
+    pub fn hook_diagnostic_command(&self) -> Hook {
+        let plugin = plugin_name().unwrap_or("plugin".to_string());
+        let name   = format!("{}_hooks", command_name(&plugin));
+        let help   = format!("Usage: {}, lists the hooks of {}.",
+                             name.to_uppercase(), plugin);
+
+        self.hook_command(&name, Priority::Norm, move |hc, _, _, _| {
+            let hooks = hc.active_hooks();
+            hc.print(&format!("{} has {} active hooks:", plugin, hooks.len()));
+            for hook in hooks {
+                hc.print(&format!("  {}", hook));
+            }
+            Eat::All
+        }, &help, NoData)
+    }
+}
+
+/// Turns a plugin's name into a command name of lowercase ASCII letters,
+/// digits, and underscores.
+///
+pub (crate) fn command_name(plugin: &str) -> String {
+    plugin.chars()
+          .map(|c| if c.is_ascii_alphanumeric() { c.to_ascii_lowercase() }
+                   else                         { '_' })
+          .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_names_are_lowercase_words() {
+        assert_eq!(command_name("My Plugin-2"), "my_plugin_2");
+        assert_eq!(command_name("plain"), "plain");
+        assert_eq!(command_name("Café Ünïcode"), "caf___n_code");
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn describes_active_hooks() {
+        use crate::testing::MockHexchat;
+
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        let line = line!() + 1;
+        let hook = hc.hook_command("FOO", Priority::High,
+                                   |_, _, _, _| Eat::None, "", NoData);
+        hook.pause();
+
+        let hooks = hc.active_hooks();
+        let foo   = hooks.iter().find(|h| h.name == "FOO").unwrap();
+        assert_eq!(foo.kind, HookKind::Command);
+        assert_eq!(foo.location.line(), line);
+        assert_eq!(foo.to_string(),
+                   format!("command \"FOO\" (priority {}) at {} [paused]",
+                           Priority::High as i32, foo.location));
+
+        let _diag = hc.hook_diagnostic_command();
+        mock.inject_command("plugin_hooks");
+        let printed = mock.printed_text();
+        assert_eq!(printed[0],
+                   format!("plugin has {} active hooks:", hooks.len() + 1));
+        assert!(printed.contains(&format!("  {}", foo)));
+    }
+}
@@ -0,0 +1,285 @@
+
+//! Groups of hooks that are managed together. A `HookGroup` collects the
+//! hooks created while a closure passed to `HookGroup::collect()` runs, so a
+//! whole feature of a plugin can be paused, resumed, or unhooked at once
+//! without keeping a `Hook` for each of its callbacks.
+//!
+//! ```no_test
+//! let logging = HookGroup::new();
+//! logging.collect(|| {
+//!     hc.hook_print("Channel Message", Priority::Norm, on_message, NoData);
+//!     hc.hook_print("Private Message", Priority::Norm, on_message, NoData);
+//!     hc.hook_server("JOIN", Priority::Norm, on_join, NoData);
+//! });
+//!
+//! hc.hook_command("LOGGING", Priority::Norm, move |hc, word, _, _| {
+//!     match word.get(1).map(|w| w.as_str()) {
+//!         Some("off") => logging.pause(),
+//!         Some("on")  => logging.resume(),
+//!         _           => hc.print("Usage: LOGGING on|off"),
+//!     }
+//!     Eat::All
+//! }, "", NoData);
+//! ```
+
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex};
+
+use crate::hook::Hook;
+
+thread_local! {
+    /// The groups whose `collect()` closures are running on this thread,
+    /// innermost last.
+    static COLLECTING: RefCell<Vec<HookGroup>> = const { RefCell::new(vec![]) };
+}
+
+/// A set of hooks that can be paused, resumed, and unhooked together.
+/// `HookGroup`s can be cloned; clones share the same hooks. Dropping the last
+/// clone of a paused group takes its pause off the hooks, leaving them
+/// hooked.
+///
+#[derive(Clone, Default)]
+pub struct HookGroup {
+    members: Arc<Mutex<Members>>,
+}
+
+#[derive(Default)]
+struct Members {
+    hooks  : Vec<Hook>,
+    paused : bool,
+}
+
+impl Members {
+    /// Drops the hooks that have been unhooked since they were added.
+    ///
+    fn prune(&mut self) {
+        self.hooks.retain(Hook::is_hooked);
+    }
+}
+
+impl Drop for Members {
+    /// Takes back the pause of a group that's dropped while paused, so its
+    /// hooks aren't left paused with no group to resume them.
+    ///
+    fn drop(&mut self) {
+        if self.paused {
+            for hook in &self.hooks {
+                hook.group_resumed();
+            }
+        }
+    }
+}
+
+impl HookGroup {
+    /// Creates an empty group.
+    ///
+    pub fn new() -> Self {
+        HookGroup::default()
+    }
+
+    /// Runs `f`, adding every hook created while it runs on this thread to
+    /// the group. When calls to `collect()` are nested, the hooks are added
+    /// to each of the groups. Hooks created while the group is paused start
+    /// out paused.
+    /// # Arguments
+    /// * `f` - The code that creates the hooks.
+    /// # Returns
+    /// * The value `f` returns.
+    ///
+    pub fn collect<F, R>(&self, f: F) -> R
+    where
+        F: FnOnce() -> R
+    {
+        COLLECTING.with(|groups| groups.borrow_mut().push(self.clone()));
+        let _collecting = Collecting;
+        f()
+    }
+
+    /// Adds a hook that was created outside of `collect()` to the group.
+    /// # Arguments
+    /// * `hook` - The hook to add.
+    ///
+    pub fn add(&self, hook: &Hook) {
+        let mut members = self.members.lock().unwrap();
+        members.prune();
+        if members.paused {
+            hook.group_paused();
+        }
+        members.hooks.push(hook.clone());
+    }
+
+    /// Returns the number of hooks in the group that are still hooked.
+    ///
+    pub fn len(&self) -> usize {
+        let mut members = self.members.lock().unwrap();
+        members.prune();
+        members.hooks.len()
+    }
+
+    /// Returns `true` if the group has no hooks.
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Pauses every hook in the group. While paused, their callbacks aren't
+    /// invoked, but they stay registered with Hexchat and keep their user
+    /// data.
+    ///
+    pub fn pause(&self) {
+        let mut members = self.members.lock().unwrap();
+        members.prune();
+        if !members.paused {
+            members.paused = true;
+            for hook in &members.hooks {
+                hook.group_paused();
+            }
+        }
+    }
+
+    /// Resumes the hooks in the group. Hooks paused with `Hook::pause()`, or
+    /// by another group they're in, stay paused.
+    ///
+    pub fn resume(&self) {
+        let mut members = self.members.lock().unwrap();
+        members.prune();
+        if members.paused {
+            members.paused = false;
+            for hook in &members.hooks {
+                hook.group_resumed();
+            }
+        }
+    }
+
+    /// Returns `true` if the group is paused.
+    ///
+    pub fn is_paused(&self) -> bool {
+        self.members.lock().unwrap().paused
+    }
+
+    /// Unhooks every hook in the group and empties it. The user data of the
+    /// hooks is dropped. This must be called from Hexchat's main thread.
+    ///
+    pub fn unhook_all(&self) {
+        let hooks = std::mem::take(&mut self.members.lock().unwrap().hooks);
+        for hook in hooks {
+            hook.unhook();
+        }
+    }
+}
+
+/// Removes the innermost collecting group when `collect()` returns, or its
+/// closure panics.
+///
+struct Collecting;
+
+impl Drop for Collecting {
+    fn drop(&mut self) {
+        COLLECTING.with(|groups| groups.borrow_mut().pop());
+    }
+}
+
+/// Puts the collecting groups back when `outside_groups()` returns, or its
+/// closure panics.
+///
+struct Suspended(Vec<HookGroup>);
+
+impl Drop for Suspended {
+    fn drop(&mut self) {
+        let groups = std::mem::take(&mut self.0);
+        COLLECTING.with(|collecting| *collecting.borrow_mut() = groups);
+    }
+}
+
+/// Adds a newly created hook to the groups collecting on this thread. Called
+/// by `Hook::new()`.
+///
+pub (crate) fn add_to_collecting_groups(hook: &Hook) {
+    let groups = COLLECTING.with(|groups| groups.borrow().clone());
+    for group in groups {
+        group.add(hook);
+    }
+}
+
+/// Runs `f` without adding the hooks it creates to the groups collecting on
+/// this thread. The crate's own hooks, such as the timer behind
+/// `main_thread()`, are created this way, so pausing or unhooking a group
+/// can't break them.
+/// # Arguments
+/// * `f` - The code that creates the hooks.
+/// # Returns
+/// * The value `f` returns.
+///
+pub (crate) fn outside_groups<F, R>(f: F) -> R
+where
+    F: FnOnce() -> R
+{
+    let _suspended = Suspended(COLLECTING.with(|groups| groups.take()));
+    f()
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use crate::hexchat::{Eat, Priority};
+    use crate::local_executor::spawn_local;
+    use crate::testing::MockHexchat;
+    use crate::user_data::UserData::NoData;
+
+    #[test]
+    fn collects_only_the_plugins_hooks() {
+        let mock  = MockHexchat::new();
+        let hc    = mock.hexchat();
+        let group = HookGroup::new();
+        group.collect(|| {
+            hc.hook_command("A", Priority::Norm, |_, _, _, _| Eat::All,
+                            "", NoData);
+            spawn_local(async {});
+        });
+        assert_eq!(group.len(), 1);
+
+        group.pause();
+        assert_eq!(mock.inject_command("A"), Eat::None);
+        group.resume();
+        assert_eq!(mock.inject_command("A"), Eat::All);
+    }
+
+    #[test]
+    fn dropping_a_paused_group_resumes_its_hooks() {
+        let mock  = MockHexchat::new();
+        let hc    = mock.hexchat();
+        let group = HookGroup::new();
+        let hook  = group.collect(|| {
+            hc.hook_command("D", Priority::Norm, |_, _, _, _| Eat::All,
+                            "", NoData)
+        });
+        group.pause();
+        let clone = group.clone();
+        drop(group);
+        assert_eq!(mock.inject_command("D"), Eat::None);
+
+        drop(clone);
+        assert!(!hook.is_paused());
+        assert_eq!(mock.inject_command("D"), Eat::All);
+    }
+
+    #[test]
+    fn drops_unhooked_members() {
+        let mock  = MockHexchat::new();
+        let hc    = mock.hexchat();
+        let group = HookGroup::new();
+        let hook  = group.collect(|| {
+            hc.hook_command("B", Priority::Norm, |_, _, _, _| Eat::All,
+                            "", NoData)
+        });
+        group.add(&hc.hook_command("C", Priority::Norm,
+                                   |_, _, _, _| Eat::All, "", NoData));
+        assert_eq!(group.len(), 2);
+
+        hook.unhook();
+        assert_eq!(group.len(), 1);
+        group.unhook_all();
+        assert!(group.is_empty());
+        assert_eq!(mock.inject_command("C"), Eat::None);
+    }
+}
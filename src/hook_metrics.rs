@@ -0,0 +1,245 @@
+#![cfg(feature = "metrics")]
+
+//! Per-hook metrics, for finding the callbacks that slow Hexchat down. Each
+//! time a hook's callback is invoked, the time it took and the `Eat` value
+//! it returned are recorded on the hook. They can be read with
+//! `Hook::metrics()` or `Hexchat::active_hooks()`, or printed with the
+//! command registered by `Hexchat::hook_metrics_command()`.
+//!
+//! ```no_test
+//! hc.hook_metrics_command();
+//! ```
+//! `/myplugin_metrics` then lists the hooks, slowest first, and
+//! `/myplugin_metrics reset` starts the counts over.
+
+use std::cmp::Reverse;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::hexchat::{Eat, Hexchat, Priority};
+use crate::hexchat_entry_points::plugin_name;
+use crate::hook::Hook;
+use crate::hook_diagnostics::command_name;
+use crate::user_data::UserData::NoData;
+
+/// How a hook's callback has performed since it was hooked, or since its
+/// metrics were last reset.
+///
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct HookMetrics {
+    /// The number of times the callback was invoked.
+    pub calls      : u64,
+    /// The time spent in the callback, in total.
+    pub total_time : Duration,
+    /// The longest time a single invocation took.
+    pub max_time   : Duration,
+    /// How many times the callback returned each `Eat` value. Always zero for
+    /// timers.
+    pub eats       : EatCounts,
+}
+
+/// The number of times a callback returned each `Eat` value.
+///
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct EatCounts {
+    pub none    : u64,
+    pub hexchat : u64,
+    pub plugin  : u64,
+    pub all     : u64,
+}
+
+impl HookMetrics {
+    /// Returns the average time an invocation took.
+    ///
+    pub fn mean_time(&self) -> Duration {
+        match self.calls {
+            0     => Duration::ZERO,
+            calls => {
+                let nanos = self.total_time.as_nanos() / calls as u128;
+                Duration::from_nanos(nanos as u64)
+            },
+        }
+    }
+
+    fn record(&mut self, elapsed: Duration, eat: Option<Eat>) {
+        self.calls      += 1;
+        self.total_time += elapsed;
+        self.max_time    = self.max_time.max(elapsed);
+        match eat {
+            Some(Eat::None)    => self.eats.none    += 1,
+            Some(Eat::Hexchat) => self.eats.hexchat += 1,
+            Some(Eat::Plugin)  => self.eats.plugin  += 1,
+            Some(Eat::All)     => self.eats.all     += 1,
+            None               => {},
+        }
+    }
+}
+
+impl fmt::Display for HookMetrics {
+    /// Summarizes the metrics on one line, e.g.
+    /// `12 calls, 3.1ms total, 258µs mean, 1.2ms max, eat none 10 all 2`.
+    ///
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} calls, {:?} total, {:?} mean, {:?} max",
+               self.calls, self.total_time, self.mean_time(), self.max_time)?;
+        let eats = [("none",    self.eats.none),
+                    ("hexchat", self.eats.hexchat),
+                    ("plugin",  self.eats.plugin),
+                    ("all",     self.eats.all)];
+        if eats.iter().any(|(_, n)| *n > 0) {
+            write!(f, ", eat")?;
+            for (name, n) in eats.iter().filter(|(_, n)| *n > 0) {
+                write!(f, " {} {}", name, n)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Hook {
+    /// Returns the metrics recorded for the hook's callback.
+    ///
+    pub fn metrics(&self) -> HookMetrics {
+        *self.info().metrics().lock().unwrap()
+    }
+
+    /// Starts the hook's metrics over.
+    ///
+    pub fn reset_metrics(&self) {
+        *self.info().metrics().lock().unwrap() = HookMetrics::default();
+    }
+}
+
+impl Hexchat {
+    /// Starts the metrics of all the plugin's hooks over.
+    ///
+    pub fn reset_hook_metrics(&self) {
+        for hook in Hook::active() {
+            hook.reset_metrics();
+        }
+    }
+
+    /// Registers a `/<plugin>_metrics` command that prints the metrics of the
+    /// plugin's active hooks, those that took the most time in total first.
+    /// `/<plugin>_metrics reset` starts them over. The command is named like
+    /// the one registered by `hook_diagnostic_command()`.
+    /// # Returns
+    /// * A `Hook` object associated with the command.
+    ///
+    #[track_caller]
+    pub fn hook_metrics_command(&self) -> Hook {
+        let plugin = plugin_name().unwrap_or("plugin".to_string());
+        let name   = format!("{}_metrics", command_name(&plugin));
+        let help   = format!("Usage: {} [reset], shows or resets how long \
+                              the callbacks of {} take.",
+                             name.to_uppercase(), plugin);
+
+        self.hook_command(&name, Priority::Norm, move |hc, word, _, _| {
+            if word.get(1).is_some_and(|w| w.eq_ignore_ascii_case("reset")) {
+                hc.reset_hook_metrics();
+                hc.print(&format!("Reset the hook metrics of {}.", plugin));
+                return Eat::All;
+            }
+            let mut hooks = hc.active_hooks();
+            hooks.sort_by_key(|h| Reverse(h.metrics.total_time));
+            hc.print(&format!("Hook metrics of {}:", plugin));
+            for hook in hooks {
+                hc.print(&format!("  {}", hook));
+                hc.print(&format!("    {}", hook.metrics));
+            }
+            Eat::All
+        }, &help, NoData)
+    }
+}
+
+/// The outcome of a callback that's recorded in the metrics.
+///
+pub (crate) trait CallOutcome {
+    fn eat(&self) -> Option<Eat>;
+}
+
+impl CallOutcome for Eat {
+    fn eat(&self) -> Option<Eat> {
+        Some(*self)
+    }
+}
+
+impl CallOutcome for i32 {
+    fn eat(&self) -> Option<Eat> {
+        None
+    }
+}
+
+/// Invokes a hook's callback, recording the time it takes and its outcome
+/// in the hook's metrics. Used by the dispatch functions of `CallbackData`.
+/// # Arguments
+/// * `hook`     - The hook the callback is registered under.
+/// * `callback` - Invokes the callback.
+/// # Returns
+/// * The callback's return value.
+///
+#[inline]
+pub (crate) fn measure<R, F>(hook: &Hook, callback: F) -> R
+where
+    R: CallOutcome,
+    F: FnOnce() -> R,
+{
+    let start   = Instant::now();
+    let result  = callback();
+    let elapsed = start.elapsed();
+    hook.info().metrics().lock().unwrap().record(elapsed, result.eat());
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn record_accumulates() {
+        let mut metrics = HookMetrics::default();
+        assert_eq!(metrics.mean_time(), Duration::ZERO);
+        assert_eq!(metrics.to_string(),
+                   "0 calls, 0ns total, 0ns mean, 0ns max");
+
+        metrics.record(Duration::from_millis(1), Some(Eat::None));
+        metrics.record(Duration::from_millis(5), Some(Eat::All));
+        metrics.record(Duration::from_millis(3), Some(Eat::None));
+        metrics.record(Duration::from_millis(3), None);
+        assert_eq!(metrics.calls, 4);
+        assert_eq!(metrics.total_time, Duration::from_millis(12));
+        assert_eq!(metrics.max_time, Duration::from_millis(5));
+        assert_eq!(metrics.mean_time(), Duration::from_millis(3));
+        assert_eq!(metrics.eats, EatCounts { none: 2, all: 1,
+                                             ..EatCounts::default() });
+        assert_eq!(metrics.to_string(),
+                   "4 calls, 12ms total, 3ms mean, 5ms max, eat none 2 all 1");
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn callbacks_are_measured() {
+        use crate::testing::MockHexchat;
+
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        let hook = hc.hook_command("FOO", Priority::Norm,
+                                   |_, word, _, _| {
+                                       if word.len() > 1 { Eat::All }
+                                       else              { Eat::None }
+                                   }, "", NoData);
+        mock.inject_command("FOO");
+        mock.inject_command("FOO bar");
+        mock.inject_command("FOO bar");
+        let metrics = hook.metrics();
+        assert_eq!(metrics.calls, 3);
+        assert_eq!((metrics.eats.none, metrics.eats.all), (1, 2));
+        assert!(metrics.max_time <= metrics.total_time);
+
+        let _cmd = hc.hook_metrics_command();
+        mock.inject_command("plugin_metrics reset");
+        assert_eq!(hook.metrics(), HookMetrics::default());
+        assert_eq!(mock.printed_text(),
+                   vec!["Reset the hook metrics of plugin."]);
+    }
+}
@@ -0,0 +1,236 @@
+//! Return types for hook callbacks. Besides `Eat` (or `i32` and
+//! `TimerAction` for timers), callbacks registered with `hook_command()`,
+//! `hook_print()`, `hook_server()`, `hook_timer()`, and
+//! `hook_timer_duration()` can return a `Result`, which lets them use `?` on
+//! `HexchatError`s and other errors. When a callback returns an error, it's
+//! printed to the context the callback was invoked in, even if the callback
+//! switched to another, and a fallback value is returned to Hexchat in its
+//! place.
+//!
+//! ```no_test
+//! hc.hook_command("TOPIC2", Priority::Norm,
+//!                 |hc, word, _, _| -> Result<Eat, HexchatError> {
+//!                     let ctx = hc.find_context(&word[1], &word[2])
+//!                                 .ok_or(ContextAcquisitionFailed(
+//!                                         word[2].clone()))?;
+//!                     hc.print(&ctx.get_info("topic")?);
+//!                     Ok(Eat::All)
+//!                 },
+//!                 "", NoData);
+//! ```
+
+use std::fmt::Display;
+use std::sync::RwLock;
+
+use crate::hexchat::{Eat, Hexchat, TimerAction};
+use crate::panic_policy::current_context;
+
+/// The format used for callback errors when none has been set.
+const DEFAULT_ERROR_FORMAT: &str = "\x0304<<Error>>\t{error}";
+
+/// The format errors are printed in, and the value returned to Hexchat in
+/// place of a failed callback's.
+///
+struct ErrorReporting {
+    format   : Option<String>,
+    fallback : Eat,
+}
+
+static ERROR_REPORTING: RwLock<ErrorReporting>
+    = RwLock::new(ErrorReporting { format: None, fallback: Eat::None });
+
+/// Implemented by the types hook callbacks can return. `T` is the type the
+/// callback's value is turned into: `Eat` for commands, prints, and server
+/// events, `i32` for timers, or `TimerAction` for timers registered with
+/// `hook_timer_duration()`.
+///
+pub trait HookResult<T> {
+    /// Converts the callback's return value into the value passed back to
+    /// Hexchat, reporting it first if it's an error.
+    /// # Arguments
+    /// * `hc` - The Hexchat interface, used to print errors.
+    ///
+    fn resolve(self, hc: &Hexchat) -> T;
+}
+
+impl HookResult<Eat> for Eat {
+    fn resolve(self, _hc: &Hexchat) -> Eat {
+        self
+    }
+}
+
+impl<E: Display> HookResult<Eat> for Result<Eat, E> {
+    /// Returns the `Eat` value on success. On error, the error is printed and
+    /// the fallback set by `Hexchat::set_error_fallback()` is returned.
+    ///
+    fn resolve(self, hc: &Hexchat) -> Eat {
+        self.unwrap_or_else(|error| {
+            report_error(hc, &error);
+            ERROR_REPORTING.read().unwrap().fallback
+        })
+    }
+}
+
+impl HookResult<i32> for i32 {
+    fn resolve(self, _hc: &Hexchat) -> i32 {
+        self
+    }
+}
+
+impl<E: Display> HookResult<i32> for Result<i32, E> {
+    /// Returns the timer's value on success. On error, the error is printed
+    /// and the timer is kept running.
+    ///
+    fn resolve(self, hc: &Hexchat) -> i32 {
+        self.unwrap_or_else(|error| {
+            report_error(hc, &error);
+            1
+        })
+    }
+}
+
+impl HookResult<TimerAction> for TimerAction {
+    fn resolve(self, _hc: &Hexchat) -> TimerAction {
+        self
+    }
+}
+
+impl<E: Display> HookResult<TimerAction> for Result<TimerAction, E> {
+    /// Returns the timer's action on success. On error, the error is printed
+    /// and the timer is kept running.
+    ///
+    fn resolve(self, hc: &Hexchat) -> TimerAction {
+        self.unwrap_or_else(|error| {
+            report_error(hc, &error);
+            TimerAction::Continue
+        })
+    }
+}
+
+/// Prints a callback's error using the configured format, in the context the
+/// callback was invoked in. If that context has been closed, or the callback
+/// isn't running from a hook, the error goes to the current context.
+///
+fn report_error(hc: &Hexchat, error: &dyn Display) {
+    let message = {
+        let reporting = ERROR_REPORTING.read().unwrap();
+        let format    = reporting.format.as_deref()
+                                        .unwrap_or(DEFAULT_ERROR_FORMAT);
+        format.replace("{error}", &error.to_string())
+    };
+    let context = current_context();
+    unsafe {
+        let prior    = (hc.c_get_context)(hc);
+        let switched = !context.is_null()
+                       && (hc.c_set_context)(hc, context) > 0;
+        hc.print(&message);
+        if switched {
+            (hc.c_set_context)(hc, prior);
+        }
+    }
+}
+
+impl Hexchat {
+    /// Sets the format callback errors are printed in. `{error}` in the
+    /// format is replaced with the error's `Display` text. The default is
+    /// `"\x0304<<Error>>\t{error}"`.
+    /// # Arguments
+    /// * `format` - The format for callback errors.
+    ///
+    pub fn set_error_format(&self, format: &str) {
+        ERROR_REPORTING.write().unwrap().format = Some(format.to_string());
+    }
+
+    /// Sets the `Eat` value returned to Hexchat when a command, print, or
+    /// server callback returns an error. The default is `Eat::None`, which
+    /// lets Hexchat and other plugins carry on handling the event.
+    /// # Arguments
+    /// * `fallback` - The value to return in place of a failed callback's.
+    ///
+    pub fn set_error_fallback(&self, fallback: Eat) {
+        ERROR_REPORTING.write().unwrap().fallback = fallback;
+    }
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::errors::HexchatError;
+    use crate::hexchat::Priority;
+    use crate::testing::MockHexchat;
+    use crate::user_data::UserData::NoData;
+
+    fn failing(word: &[String]) -> Result<Eat, HexchatError> {
+        match word.get(1).map(|w| w.as_str()) {
+            Some("ok") => Ok(Eat::All),
+            _ => Err(HexchatError::CommandFailed("bad arg".into())),
+        }
+    }
+
+    #[test]
+    fn errors_are_printed_and_replaced() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        let _hook = hc.hook_command("TRY", Priority::Norm,
+                                    |_hc, word, _eol, _ud| failing(word),
+                                    "", NoData);
+
+        assert_eq!(mock.inject_command("TRY ok"), Eat::All);
+        assert!(mock.printed_text().is_empty());
+        assert_eq!(mock.inject_command("TRY no"), Eat::None);
+        assert_eq!(mock.printed_text(),
+                   vec![format!("\x0304<<Error>>\t{}",
+                                HexchatError::CommandFailed(
+                                    "bad arg".into()))]);
+
+        hc.set_error_format("oops: {error}");
+        hc.set_error_fallback(Eat::All);
+        mock.clear_output();
+        let eat = mock.inject_command("TRY no");
+        let printed = mock.printed_text();
+
+        *ERROR_REPORTING.write().unwrap()
+            = ErrorReporting { format: None, fallback: Eat::None };
+        assert_eq!(eat, Eat::All);
+        assert!(printed[0].starts_with("oops: "), "{:?}", printed);
+    }
+
+    #[test]
+    fn errors_go_to_the_invoking_context() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        mock.add_context("MockNet", "#other");
+        let _hook = hc.hook_command("MOVE", Priority::Norm,
+                                    |hc, _, _, _| -> Result<Eat, String> {
+            hc.find_context("MockNet", "#other").unwrap().set().unwrap();
+            Err("moved".into())
+        }, "", NoData);
+
+        mock.inject_command("MOVE");
+        let printed = mock.printed();
+        assert_eq!(printed[0].channel, "#mock");
+        assert_eq!(printed[0].text, "\x0304<<Error>>\tmoved");
+        assert_eq!(hc.get_info("channel").as_deref(), Some("#other"));
+    }
+
+    #[test]
+    fn failing_timers_keep_running() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        let mut ticks = 0;
+        let hook = hc.hook_timer_duration(
+            Duration::from_millis(10),
+            move |_hc, _ud| -> Result<TimerAction, String> {
+                ticks += 1;
+                if ticks < 3 { Err(format!("tick {}", ticks)) }
+                else         { Ok(TimerAction::Stop) }
+            }, NoData);
+
+        mock.advance(Duration::from_millis(100));
+        assert_eq!(mock.printed_text(), vec!["\x0304<<Error>>\ttick 1",
+                                             "\x0304<<Error>>\ttick 2"]);
+        assert!(!hook.is_hooked());
+    }
+}
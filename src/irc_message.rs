@@ -0,0 +1,293 @@
+//! A parsed IRC protocol message, as passed to callbacks registered with
+//! `Hexchat::hook_server_msg()`. Parsing follows RFC 1459 plus the IRCv3
+//! message tags extension.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::hexchat::EventAttrs;
+
+/// A single IRC message, broken into its parts.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct IrcMessage {
+    /// The IRCv3 tags sent with the message, already unescaped. Tags sent
+    /// without a value map to an empty string. Hexchat consumes tags before
+    /// passing messages on to plugins, so this is usually empty.
+    pub tags        : BTreeMap<String, String>,
+
+    /// Who sent the message. `None` if the line had no prefix.
+    pub source      : Option<IrcSource>,
+
+    /// The command, or numeric reply.
+    pub command     : IrcCommand,
+
+    /// The command's parameters. A trailing parameter (the one following
+    /// `:`) is the last item, and may contain spaces or be empty.
+    pub params      : Vec<String>,
+
+    /// The server time of the message, if the server provided one. This can
+    /// differ from when the message was received if it was replayed by a
+    /// bouncer.
+    pub server_time : Option<SystemTime>,
+}
+
+/// The sender of an IRC message, parsed from its prefix.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct IrcSource {
+    /// The nickname of the sender. For messages from the server itself, this
+    /// holds the server's name.
+    pub nick : String,
+
+    /// The username (ident) of the sender, if given.
+    pub user : Option<String>,
+
+    /// The hostname of the sender, if given.
+    pub host : Option<String>,
+}
+
+/// The command of an IRC message.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum IrcCommand {
+    /// A named command, such as `PRIVMSG`. Always uppercase.
+    Named(String),
+
+    /// A three digit numeric reply, such as `001`.
+    Numeric(u16),
+}
+
+impl IrcMessage {
+    /// Parses a raw IRC line.
+    /// # Arguments
+    /// * `line` - The line to parse, with or without tags and a prefix.
+    ///            A trailing CR/LF is ignored.
+    /// # Returns
+    /// * `Some(IrcMessage)` if the line holds a command, `None` otherwise.
+    ///
+    pub fn parse(line: &str) -> Option<Self> {
+        let mut rest = line.trim_end_matches(['\r', '\n'])
+                           .trim_start_matches(' ');
+        let mut tags = BTreeMap::new();
+
+        if let Some(tagstr) = rest.strip_prefix('@') {
+            let (tagstr, tail) = split_word(tagstr);
+            for tag in tagstr.split(';').filter(|t| !t.is_empty()) {
+                let (key, value) = tag.split_once('=').unwrap_or((tag, ""));
+                tags.insert(key.to_string(), unescape_tag_value(value));
+            }
+            rest = tail;
+        }
+        let mut source = None;
+        if let Some(prefix) = rest.strip_prefix(':') {
+            let (prefix, tail) = split_word(prefix);
+            source = Some(IrcSource::parse(prefix));
+            rest = tail;
+        }
+        let (command, mut rest) = split_word(rest);
+        if command.is_empty() {
+            return None;
+        }
+        let mut params = vec![];
+        while !rest.is_empty() {
+            if let Some(trailing) = rest.strip_prefix(':') {
+                params.push(trailing.to_string());
+                break;
+            }
+            let (param, tail) = split_word(rest);
+            params.push(param.to_string());
+            rest = tail;
+        }
+        Some(IrcMessage {
+            tags,
+            source,
+            command     : IrcCommand::parse(command),
+            params,
+            server_time : None,
+        })
+    }
+
+    /// Builds a message from the `word_eol` and `EventAttrs` Hexchat passes
+    /// to server hook callbacks.
+    ///
+    pub (crate) fn from_server_event(word_eol : &[String],
+                                     attrs    : &EventAttrs)
+        -> Option<Self>
+    {
+        let mut msg = Self::parse(word_eol.first()?)?;
+        if attrs.server_time_utc > 0 {
+            let secs = Duration::from_secs(attrs.server_time_utc as u64);
+            msg.server_time = Some(UNIX_EPOCH + secs);
+        }
+        Some(msg)
+    }
+
+    /// Returns the nickname of the sender, if the message had a prefix.
+    ///
+    pub fn nick(&self) -> Option<&str> {
+        self.source.as_ref().map(|s| s.nick.as_str())
+    }
+
+    /// Returns the parameter at `index`, if there is one.
+    ///
+    pub fn param(&self, index: usize) -> Option<&str> {
+        self.params.get(index).map(|p| p.as_str())
+    }
+
+    /// Returns the last parameter, which for most commands is the text of the
+    /// message - the message body of a `PRIVMSG` for instance.
+    ///
+    pub fn trailing(&self) -> Option<&str> {
+        self.params.last().map(|p| p.as_str())
+    }
+
+    /// Returns `true` if the message's command is the named command. The
+    /// comparison is case insensitive.
+    ///
+    pub fn is(&self, command: &str) -> bool {
+        matches!(&self.command,
+                 IrcCommand::Named(c) if c.eq_ignore_ascii_case(command))
+    }
+}
+
+impl IrcSource {
+    /// Splits a prefix of the form `nick!user@host` into its parts.
+    ///
+    fn parse(prefix: &str) -> Self {
+        let (rest, host) = match prefix.split_once('@') {
+            Some((rest, host)) => (rest, Some(host.to_string())),
+            None => (prefix, None),
+        };
+        let (nick, user) = match rest.split_once('!') {
+            Some((nick, user)) => (nick, Some(user.to_string())),
+            None => (rest, None),
+        };
+        IrcSource { nick: nick.to_string(), user, host }
+    }
+}
+
+impl IrcCommand {
+    /// Converts the command word of a message into an `IrcCommand`.
+    ///
+    fn parse(command: &str) -> Self {
+        if command.len() == 3 && command.bytes().all(|b| b.is_ascii_digit()) {
+            IrcCommand::Numeric(command.parse().unwrap())
+        } else {
+            IrcCommand::Named(command.to_ascii_uppercase())
+        }
+    }
+}
+
+impl fmt::Display for IrcCommand {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            IrcCommand::Named(name) => write!(f, "{}", name),
+            IrcCommand::Numeric(num) => write!(f, "{:03}", num),
+        }
+    }
+}
+
+/// Splits off the first space delimited word of `s`, returning it and the
+/// remainder with leading spaces removed.
+///
+fn split_word(s: &str) -> (&str, &str) {
+    match s.split_once(' ') {
+        Some((word, rest)) => (word, rest.trim_start_matches(' ')),
+        None => (s, ""),
+    }
+}
+
+/// Reverses the escaping applied to IRCv3 tag values.
+///
+fn unescape_tag_value(value: &str) -> String {
+    let mut out   = String::with_capacity(value.len());
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some(':')   => out.push(';'),
+            Some('s')   => out.push(' '),
+            Some('r')   => out.push('\r'),
+            Some('n')   => out.push('\n'),
+            Some(other) => out.push(other),
+            None        => {},
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_a_full_message() {
+        let msg = IrcMessage::parse(
+            "@time=now;flag;msg=a\\sb\\:c\\\\ :amy!ident@host.net \
+             privmsg #rust :hi  there\r\n").unwrap();
+        assert_eq!(msg.tags.get("msg").map(|s| s.as_str()), Some("a b;c\\"));
+        assert_eq!(msg.tags.get("flag").map(|s| s.as_str()), Some(""));
+        assert_eq!(msg.source, Some(IrcSource {
+            nick : "amy".into(),
+            user : Some("ident".into()),
+            host : Some("host.net".into()),
+        }));
+        assert_eq!(msg.command, IrcCommand::Named("PRIVMSG".into()));
+        assert!(msg.is("PrivMsg"));
+        assert_eq!(msg.params, vec!["#rust", "hi  there"]);
+        assert_eq!(msg.nick(), Some("amy"));
+        assert_eq!(msg.trailing(), Some("hi  there"));
+        assert_eq!(msg.server_time, None);
+    }
+
+    #[test]
+    fn parses_bare_and_numeric_messages() {
+        let msg = IrcMessage::parse("PING   server.net").unwrap();
+        assert_eq!(msg.source, None);
+        assert_eq!(msg.params, vec!["server.net"]);
+
+        let msg = IrcMessage::parse(":irc.net 001 amy :").unwrap();
+        assert_eq!(msg.command, IrcCommand::Numeric(1));
+        assert_eq!(msg.command.to_string(), "001");
+        assert_eq!(msg.nick(), Some("irc.net"));
+        assert_eq!(msg.source.as_ref().unwrap().user, None);
+        assert_eq!(msg.params, vec!["amy", ""]);
+        assert_eq!(msg.param(2), None);
+        assert!(!msg.is("001"));
+
+        assert_eq!(IrcMessage::parse(""), None);
+        assert_eq!(IrcMessage::parse("@a=b :nick!u@h"), None);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn server_hooks_get_the_server_time() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        use crate::{Eat, Priority, UserData::NoData};
+
+        let mock = crate::testing::MockHexchat::new();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let s    = seen.clone();
+        let _hook = mock.hexchat().hook_server_msg(
+            "PRIVMSG", Priority::Norm,
+            move |_hc, msg, _ud| {
+                s.borrow_mut().push(msg.clone());
+                Eat::None
+            }, NoData);
+
+        mock.inject_server_attrs(":amy!i@h PRIVMSG #x :hello", 1000);
+        mock.inject_server(":amy!i@h PRIVMSG #x :again");
+        let seen = seen.borrow();
+        assert_eq!(seen.len(), 2);
+        assert_eq!(seen[0].server_time,
+                   Some(UNIX_EPOCH + Duration::from_secs(1000)));
+        assert_eq!(seen[0].trailing(), Some("hello"));
+        assert_eq!(seen[1].server_time, None);
+    }
+}
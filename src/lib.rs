@@ -8,16 +8,31 @@
 //! listed on the Hexchat docs page linked above.
 
 mod hook;
+mod hook_diagnostics;
+mod hook_group;
+mod hook_metrics;
+mod hook_result;
 mod callback_data;
+mod command_spec;
 mod consts;
 mod context;
 mod errors;
 mod hexchat;
 mod hexchat_callbacks;
 mod hexchat_entry_points;
+mod hexchat_logger;
+mod hexchat_tracing;
+mod irc_message;
 mod list_item;
 mod list_iterator;
+mod list_records;
+mod local_executor;
+mod panic_config;
+mod panic_policy;
 mod plugin;
+mod pluginpref_serde;
+mod testing;
+mod text_events;
 mod thread_facilities;
 mod threadsafe_context;
 mod threadsafe_hexchat;
@@ -26,16 +41,36 @@ mod user_data;
 mod utils;
 
 pub use hook::*;
+pub use hook_diagnostics::*;
+pub use hook_group::*;
+#[cfg(feature = "metrics")]
+pub use hook_metrics::*;
+pub use hook_result::*;
 //pub use callback_data::*;
+pub use command_spec::*;
 pub use consts::*;
 pub use context::*;
 pub use errors::*;
 pub use hexchat::*;
 //pub use hexchat_callbacks::*;
 pub use hexchat_entry_points::*;
+#[cfg(feature = "log")]
+pub use hexchat_logger::*;
+#[cfg(feature = "tracing")]
+pub use hexchat_tracing::*;
+#[cfg(feature = "macros")]
+pub use hexchat_api_macros::hexchat_plugin;
+pub use irc_message::*;
 pub use list_item::*;
 pub use list_iterator::*;
+pub use list_records::*;
+pub use local_executor::*;
+pub use panic_config::*;
+pub use panic_policy::*;
 pub use plugin::*;
+#[cfg(feature = "testing")]
+pub use testing::*;
+pub use text_events::*;
 #[cfg(feature = "threadsafe")]
 pub use thread_facilities::*;
 #[cfg(feature = "threadsafe")]
@@ -0,0 +1,423 @@
+//! Typed records for the lists Hexchat provides. Each struct here holds the
+//! fields of one item of a Hexchat list, already converted to Rust types:
+//! flags are decoded to `BitFlags`, numeric codes to their enums, and times to
+//! `SystemTime`. They're obtained through `Hexchat::channels()`,
+//! `Hexchat::users()`, `Hexchat::dcc_transfers()`, `Hexchat::ignore_list()`,
+//! and `Hexchat::notify_list()`, or can be built from a `ListIterator` at its
+//! current position using `From`.
+
+use std::net::Ipv4Addr;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use enumflags2::BitFlags;
+
+use crate::consts::*;
+use crate::context::Context;
+use crate::hexchat::Hexchat;
+use crate::list_iterator::{FieldValue, ListIterator};
+
+/// An item of the "channels" list - one for each channel, query, and server
+/// tab open in Hexchat.
+///
+#[derive(Clone, Debug)]
+pub struct ChannelInfo {
+    /// The channel or query name, or the server name for server tabs.
+    pub channel       : String,
+    /// The channel key, if one is set.
+    pub channel_key   : String,
+    /// The channel modes the server supports.
+    pub chan_modes    : String,
+    /// The channel prefixes the server supports, e.g. "#&".
+    pub chan_types    : String,
+    /// A `Context` for the channel, if one could be acquired.
+    pub context       : Option<Context>,
+    /// The server and tab flags.
+    pub flags         : BitFlags<ChanFlag>,
+    /// A unique ID for the server the channel is on.
+    pub id            : i32,
+    /// The lag to the server, in milliseconds.
+    pub lag           : i32,
+    /// The maximum number of modes that can be sent in one line.
+    pub max_modes     : i32,
+    /// The network the channel is on.
+    pub network       : String,
+    /// The user mode characters the server supports, e.g. "ov".
+    pub nick_modes    : String,
+    /// The user mode prefixes the server supports, e.g. "@+".
+    pub nick_prefixes : String,
+    /// The number of bytes waiting in the server's send queue.
+    pub queue         : i32,
+    /// The name of the server the channel is on.
+    pub server        : String,
+    /// The kind of tab. `None` if Hexchat reports an unknown type.
+    pub chan_type     : Option<ChanType>,
+    /// The number of users in the channel.
+    pub users         : i32,
+}
+
+/// An item of the "users" list - one for each user in the current channel.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct UserInfo {
+    /// The user's account name, if they're logged in.
+    pub account   : String,
+    /// `true` if the user is marked as away.
+    pub away      : bool,
+    /// When the user last spoke in the channel, if they have.
+    pub last_talk : Option<SystemTime>,
+    /// The user's nickname.
+    pub nick      : String,
+    /// The user's `user@host`, if known.
+    pub host      : String,
+    /// The user's mode prefix in the channel, e.g. "@".
+    pub prefix    : String,
+    /// The user's real name, if known.
+    pub real_name : String,
+    /// `true` if the user is selected in the user list.
+    pub selected  : bool,
+}
+
+/// An item of the "dcc" list - one for each DCC transfer or chat.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct DccTransfer {
+    /// The IPv4 address of the remote user.
+    pub address   : Ipv4Addr,
+    /// The transfer rate in bytes per second.
+    pub cps       : i32,
+    /// The destination path of the file.
+    pub dest_file : String,
+    /// The name of the file.
+    pub file      : String,
+    /// The nickname of the user sending or receiving.
+    pub nick      : String,
+    /// The TCP port of the connection.
+    pub port      : i32,
+    /// The number of bytes transferred so far.
+    pub pos       : u64,
+    /// The offset the transfer resumed from.
+    pub resume    : u64,
+    /// The size of the file in bytes.
+    pub size      : u64,
+    /// The state of the transfer. `None` if Hexchat reports an unknown
+    /// status.
+    pub status    : Option<DccStatus>,
+    /// The kind of transfer. `None` if Hexchat reports an unknown type.
+    pub dcc_type  : Option<DccType>,
+}
+
+/// An item of the "ignore" list.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct IgnoreEntry {
+    /// The hostmask being ignored.
+    pub mask  : String,
+    /// What's being ignored from the hostmask.
+    pub flags : BitFlags<IgnFlag>,
+}
+
+/// An item of the "notify" list.
+///
+#[derive(Clone, Debug, PartialEq)]
+pub struct NotifyEntry {
+    /// The networks the notify applies to, comma separated. Empty for all
+    /// networks.
+    pub networks : String,
+    /// The nickname being watched.
+    pub nick     : String,
+    /// Whether the user is online, per Hexchat.
+    pub flags    : i32,
+    /// When the user last came online, if seen.
+    pub on       : Option<SystemTime>,
+    /// When the user last went offline, if seen.
+    pub off      : Option<SystemTime>,
+    /// When the user was last seen.
+    pub seen     : Option<SystemTime>,
+}
+
+impl Hexchat {
+    /// Returns the channels, queries, and server tabs open in Hexchat.
+    ///
+    pub fn channels(&self) -> Vec<ChannelInfo> {
+        collect_list("channels")
+    }
+
+    /// Returns the users in the current channel.
+    ///
+    pub fn users(&self) -> Vec<UserInfo> {
+        collect_list("users")
+    }
+
+    /// Returns the DCC transfers and chats, active or not.
+    ///
+    pub fn dcc_transfers(&self) -> Vec<DccTransfer> {
+        collect_list("dcc")
+    }
+
+    /// Returns the entries of the ignore list.
+    ///
+    pub fn ignore_list(&self) -> Vec<IgnoreEntry> {
+        collect_list("ignore")
+    }
+
+    /// Returns the entries of the notify list for the current server.
+    ///
+    pub fn notify_list(&self) -> Vec<NotifyEntry> {
+        collect_list("notify")
+    }
+}
+
+impl From<&ListIterator> for ChannelInfo {
+    /// Builds a `ChannelInfo` from the "channels" list iterator at its
+    /// current position.
+    ///
+    fn from(list: &ListIterator) -> Self {
+        ChannelInfo {
+            channel       : str_field(list, "channel"),
+            channel_key   : str_field(list, "channelkey"),
+            chan_modes    : str_field(list, "chanmodes"),
+            chan_types    : str_field(list, "chantypes"),
+            context       : match list.get_field("context") {
+                                Ok(FieldValue::ContextVal(c)) => Some(c),
+                                _ => None,
+                            },
+            flags         : BitFlags::from_bits_truncate(
+                                int_field(list, "flags") as u32),
+            id            : int_field(list, "id"),
+            lag           : int_field(list, "lag"),
+            max_modes     : int_field(list, "maxmodes"),
+            network       : str_field(list, "network"),
+            nick_modes    : str_field(list, "nickmodes"),
+            nick_prefixes : str_field(list, "nickprefixes"),
+            queue         : int_field(list, "queue"),
+            server        : str_field(list, "server"),
+            chan_type     : chan_type(int_field(list, "type")),
+            users         : int_field(list, "users"),
+        }
+    }
+}
+
+impl From<&ListIterator> for UserInfo {
+    /// Builds a `UserInfo` from the "users" list iterator at its current
+    /// position.
+    ///
+    fn from(list: &ListIterator) -> Self {
+        UserInfo {
+            account   : str_field(list, "account"),
+            away      : int_field(list, "away") != 0,
+            last_talk : time_field(list, "lasttalk"),
+            nick      : str_field(list, "nick"),
+            host      : str_field(list, "host"),
+            prefix    : str_field(list, "prefix"),
+            real_name : str_field(list, "realname"),
+            selected  : int_field(list, "selected") != 0,
+        }
+    }
+}
+
+impl From<&ListIterator> for DccTransfer {
+    /// Builds a `DccTransfer` from the "dcc" list iterator at its current
+    /// position.
+    ///
+    fn from(list: &ListIterator) -> Self {
+        DccTransfer {
+            address   : Ipv4Addr::from(int_field(list, "address32") as u32),
+            cps       : int_field(list, "cps"),
+            dest_file : str_field(list, "destfile"),
+            file      : str_field(list, "file"),
+            nick      : str_field(list, "nick"),
+            port      : int_field(list, "port"),
+            pos       : u64_field(list, "pos", "poshigh"),
+            resume    : u64_field(list, "resume", "resumehigh"),
+            size      : u64_field(list, "size", "sizehigh"),
+            status    : dcc_status(int_field(list, "status")),
+            dcc_type  : dcc_type(int_field(list, "type")),
+        }
+    }
+}
+
+impl From<&ListIterator> for IgnoreEntry {
+    /// Builds an `IgnoreEntry` from the "ignore" list iterator at its current
+    /// position.
+    ///
+    fn from(list: &ListIterator) -> Self {
+        IgnoreEntry {
+            mask  : str_field(list, "mask"),
+            flags : BitFlags::from_bits_truncate(
+                        int_field(list, "flags") as u32),
+        }
+    }
+}
+
+impl From<&ListIterator> for NotifyEntry {
+    /// Builds a `NotifyEntry` from the "notify" list iterator at its current
+    /// position.
+    ///
+    fn from(list: &ListIterator) -> Self {
+        NotifyEntry {
+            networks : str_field(list, "networks"),
+            nick     : str_field(list, "nick"),
+            flags    : int_field(list, "flags"),
+            on       : time_field(list, "on"),
+            off      : time_field(list, "off"),
+            seen     : time_field(list, "seen"),
+        }
+    }
+}
+
+/// Walks the named list and converts each of its items into a record.
+///
+fn collect_list<T>(name: &str) -> Vec<T>
+where
+    T: for<'a> From<&'a ListIterator>
+{
+    match ListIterator::new(name) {
+        Some(list) => (&list).map(T::from).collect(),
+        None => vec![],
+    }
+}
+
+/// Reads a string field, or an empty string if the field is missing.
+///
+fn str_field(list: &ListIterator, name: &str) -> String {
+    match list.get_field(name) {
+        Ok(FieldValue::StringVal(s)) => s,
+        _ => String::new(),
+    }
+}
+
+/// Reads an integer field, or 0 if the field is missing.
+///
+fn int_field(list: &ListIterator, name: &str) -> i32 {
+    match list.get_field(name) {
+        Ok(FieldValue::IntVal(i)) => i,
+        _ => 0,
+    }
+}
+
+/// Combines the low and high 32 bits Hexchat gives for file sizes and
+/// offsets into one value.
+///
+fn u64_field(list: &ListIterator, low: &str, high: &str) -> u64 {
+    let low  = int_field(list, low) as u32 as u64;
+    let high = int_field(list, high) as u32 as u64;
+    (high << 32) | low
+}
+
+/// Reads a time field. Hexchat uses 0 for times that haven't happened, which
+/// are returned as `None`, as are missing fields.
+///
+fn time_field(list: &ListIterator, name: &str) -> Option<SystemTime> {
+    match list.get_field(name) {
+        Ok(FieldValue::TimeVal(t)) if t > 0 => {
+            Some(UNIX_EPOCH + Duration::from_secs(t as u64))
+        },
+        _ => None,
+    }
+}
+
+/// Converts the "type" field of the channels list.
+///
+fn chan_type(value: i32) -> Option<ChanType> {
+    use ChanType::*;
+    match value {
+        1 => Some(SERVER),
+        2 => Some(CHANNEL),
+        3 => Some(DIALOG),
+        4 => Some(NOTICE),
+        5 => Some(SNOTICE),
+        _ => None,
+    }
+}
+
+/// Converts the "status" field of the dcc list.
+///
+fn dcc_status(value: i32) -> Option<DccStatus> {
+    use DccStatus::*;
+    match value {
+        0 => Some(QUEUED),
+        1 => Some(ACTIVE),
+        2 => Some(FAILED),
+        3 => Some(DONE),
+        4 => Some(CONNECTING),
+        5 => Some(ABORTED),
+        _ => None,
+    }
+}
+
+/// Converts the "type" field of the dcc list.
+///
+fn dcc_type(value: i32) -> Option<DccType> {
+    use DccType::*;
+    match value {
+        0 => Some(SEND),
+        1 => Some(RECIEVE),
+        2 => Some(CHATRECV),
+        3 => Some(CHATSEND),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decodes_numeric_codes() {
+        assert_eq!(chan_type(2), Some(ChanType::CHANNEL));
+        assert_eq!(chan_type(0), None);
+        assert_eq!(dcc_status(5), Some(DccStatus::ABORTED));
+        assert_eq!(dcc_status(-1), None);
+        assert_eq!(dcc_type(1), Some(DccType::RECIEVE));
+        assert_eq!(dcc_type(4), None);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn builds_records_from_lists() {
+        use crate::testing::MockField::{Int, Str, Time};
+        use crate::testing::MockHexchat;
+
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        mock.add_list_item("users", &[("nick",     Str("amy".into())),
+                                      ("away",     Int(1)),
+                                      ("lasttalk", Time(60)),
+                                      ("selected", Int(0))])
+            .add_list_item("users", &[("nick",     Str("bob".into())),
+                                      ("away",     Int(0)),
+                                      ("lasttalk", Time(0)),
+                                      ("selected", Int(1))])
+            .add_list_item("dcc", &[("address32",  Int(0x7f000001)),
+                                    ("size",       Int(-1)),
+                                    ("sizehigh",   Int(1)),
+                                    ("pos",        Int(10)),
+                                    ("poshigh",    Int(0)),
+                                    ("status",     Int(3)),
+                                    ("type",       Int(9))])
+            .add_list_item("ignore", &[("mask",  Str("*!*@spam".into())),
+                                       ("flags", Int(0x05))]);
+
+        let users = hc.users();
+        assert_eq!(users.len(), 2);
+        assert_eq!(users[0].nick, "amy");
+        assert!(users[0].away && !users[0].selected);
+        assert_eq!(users[0].last_talk,
+                   Some(UNIX_EPOCH + Duration::from_secs(60)));
+        assert_eq!(users[1].last_talk, None);
+        assert!(users[1].selected);
+
+        let dcc = &hc.dcc_transfers()[0];
+        assert_eq!(dcc.address, Ipv4Addr::new(127, 0, 0, 1));
+        assert_eq!(dcc.size, 0x1_ffff_ffff);
+        assert_eq!(dcc.pos, 10);
+        assert_eq!(dcc.status, Some(DccStatus::DONE));
+        assert_eq!(dcc.dcc_type, None);
+
+        let ignore = &hc.ignore_list()[0];
+        assert_eq!(ignore.mask, "*!*@spam");
+        assert_eq!(ignore.flags, IgnFlag::PRIVATE | IgnFlag::CHANNEL);
+
+        assert!(hc.notify_list().is_empty());
+    }
+}
@@ -0,0 +1,418 @@
+//! A small single-threaded executor for running `async` code on Hexchat's
+//! main thread. Futures passed to `spawn_local()` are polled from a one-shot
+//! Hexchat timer that's hooked whenever a task is woken, so they're free to
+//! use the Hexchat API directly, and nothing runs while every task waits.
+//! The futures returned by `sleep()`, `next_print()`, `next_text_event()`,
+//! and `next_server_msg()` let plugin logic that spans several events read
+//! top to bottom rather than as nested callbacks.
+//!
+//! ```no_test
+//! spawn_local(async {
+//!     hc.command("JOIN #rust");
+//!     let args = next_print("You Join").await;
+//!     sleep(Duration::from_secs(2)).await;
+//!     hc.command(&format!("SAY hello {}", args[1]));
+//! });
+//! ```
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::future::Future;
+use std::mem;
+use std::pin::Pin;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::rc::Rc;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::task::{Context, Poll, Wake, Waker};
+#[cfg(feature = "threadsafe")]
+use std::thread;
+use std::time::Duration;
+
+#[cfg(feature = "threadsafe")]
+use crate::MAIN_THREAD_ID;
+use crate::hexchat::{Eat, Priority, timer_millis};
+use crate::hexchat_entry_points::PHEXCHAT;
+use crate::hook::Hook;
+use crate::hook_group::outside_groups;
+use crate::irc_message::IrcMessage;
+use crate::text_events::{TextEvent, TextEventKind};
+#[cfg(feature = "threadsafe")]
+use crate::thread_facilities::main_thread;
+use crate::user_data::UserData::*;
+
+/// How soon, in milliseconds, woken tasks are polled.
+const POLL_MSECS: i64 = 2;
+
+type LocalTask = Pin<Box<dyn Future<Output = ()>>>;
+
+thread_local! {
+    /// The executor's tasks. Only the main thread has one.
+    static EXECUTOR: RefCell<Option<Executor>> = const { RefCell::new(None) };
+}
+
+/// The IDs of tasks that have been woken and need polling. Wakers can be
+/// invoked from any thread, so this is kept apart from the tasks themselves.
+///
+static READY: Mutex<Vec<u64>> = Mutex::new(Vec::new());
+
+/// Whether a timer has been hooked to poll the tasks in `READY`.
+///
+static SCHEDULED: AtomicBool = AtomicBool::new(false);
+
+/// Holds the spawned tasks. The executor only exists while there are tasks.
+///
+struct Executor {
+    tasks   : HashMap<u64, LocalTask>,
+    next_id : u64,
+}
+
+/// Wakes a task by queueing its ID to be polled on the next timer tick.
+///
+struct TaskWaker(u64);
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        wake_task(self.0);
+    }
+}
+
+/// Queues a task to be polled, and hooks the timer that polls it unless one
+/// is already waiting to fire.
+///
+fn wake_task(id: u64) {
+    {
+        let mut ready = READY.lock().unwrap();
+        if !ready.contains(&id) {
+            ready.push(id);
+        }
+    }
+    if !SCHEDULED.swap(true, Ordering::SeqCst) {
+        schedule_tick();
+    }
+}
+
+/// Hooks a one-shot timer that polls the woken tasks. Wakers can be invoked
+/// from any thread, so off the main thread the timer is hooked through
+/// `main_thread()`.
+///
+fn schedule_tick() {
+    #[cfg(feature = "threadsafe")]
+    if Some(thread::current().id()) != unsafe { MAIN_THREAD_ID } {
+        main_thread(|_hc| schedule_tick());
+        return;
+    }
+    let hc = unsafe { &*PHEXCHAT };
+    outside_groups(|| {
+        hc.hook_timer(POLL_MSECS, |_hc, _ud| run_ready_tasks(), NoData)
+    });
+}
+
+/// Runs a future to completion on Hexchat's main thread. The future is first
+/// polled on the next tick of the executor's timer, and is polled again
+/// whenever it's woken. This must be called from the main thread.
+/// # Arguments
+/// * `future` - The future to run. It doesn't need to be `Send`.
+///
+pub fn spawn_local<F>(future: F)
+where
+    F: Future<Output = ()> + 'static
+{
+    #[cfg(feature = "threadsafe")]
+    assert!(thread::current().id() == unsafe { MAIN_THREAD_ID.unwrap() },
+            "spawn_local() must be called from the Hexchat main thread.");
+
+    let id = EXECUTOR.with(|ex| {
+        let mut ex = ex.borrow_mut();
+        let ex = ex.get_or_insert_with(|| {
+            Executor { tasks: HashMap::new(), next_id: 0 }
+        });
+        let id = ex.next_id;
+        ex.next_id += 1;
+        ex.tasks.insert(id, Box::pin(future));
+        id
+    });
+    wake_task(id);
+}
+
+/// The executor's timer callback. Polls each task that's been woken since the
+/// timer was hooked. A task that panics is dropped, and the others carry on.
+/// When no tasks remain the executor is torn down.
+/// # Returns
+/// * 0, so the timer is unhooked. It's hooked again when a task is woken.
+///
+fn run_ready_tasks() -> i32 {
+    SCHEDULED.store(false, Ordering::SeqCst);
+    let ready = mem::take(&mut *READY.lock().unwrap());
+    for id in ready {
+        // The task is taken out while it's polled so it can spawn others.
+        let task = EXECUTOR.with(|ex| {
+            ex.borrow_mut().as_mut().and_then(|ex| ex.tasks.remove(&id))
+        });
+        if let Some(mut task) = task {
+            let waker  = Waker::from(Arc::new(TaskWaker(id)));
+            let mut cx = Context::from_waker(&waker);
+            let poll   = catch_unwind(AssertUnwindSafe(|| {
+                             task.as_mut().poll(&mut cx)
+                         }));
+            if matches!(poll, Ok(Poll::Pending)) {
+                EXECUTOR.with(|ex| {
+                    if let Some(ex) = ex.borrow_mut().as_mut() {
+                        ex.tasks.insert(id, task);
+                    }
+                });
+            }
+        }
+    }
+    EXECUTOR.with(|ex| {
+        let mut ex = ex.borrow_mut();
+        if ex.as_ref().is_some_and(|ex| ex.tasks.is_empty()) {
+            ex.take();
+        }
+    });
+    0
+}
+
+/// Drops any tasks still pending. Called when the plugin is unloading, before
+/// the hooks are removed, so hooks held by the tasks' futures are released
+/// first.
+///
+pub (crate) fn local_executor_deinit() {
+    let executor = EXECUTOR.with(|ex| ex.borrow_mut().take());
+    drop(executor);
+    READY.lock().unwrap().clear();
+    SCHEDULED.store(false, Ordering::SeqCst);
+}
+
+/// The value an event future is waiting on, and the waker of the task
+/// waiting on it.
+///
+struct Slot<T> {
+    value : Option<T>,
+    waker : Option<Waker>,
+}
+
+/// Fills the slot with the first value it receives and wakes its task.
+///
+fn fill_slot<T>(slot: &RefCell<Slot<T>>, value: T) {
+    let mut slot = slot.borrow_mut();
+    if slot.value.is_none() {
+        slot.value = Some(value);
+        if let Some(waker) = slot.waker.take() {
+            waker.wake();
+        }
+    }
+}
+
+type Register<T> = Box<dyn FnOnce(Rc<RefCell<Slot<T>>>) -> Hook>;
+
+/// A future that resolves the first time a Hexchat hook fires. The hook is
+/// registered when the future is first polled, and unhooked when the future
+/// completes or is dropped.
+///
+pub struct HookFuture<T> {
+    register : Option<Register<T>>,
+    hook     : Option<Hook>,
+    slot     : Rc<RefCell<Slot<T>>>,
+}
+
+impl<T> HookFuture<T> {
+    fn new(register: Register<T>) -> Self {
+        HookFuture {
+            register : Some(register),
+            hook     : None,
+            slot     : Rc::new(RefCell::new(Slot { value: None,
+                                                   waker: None })),
+        }
+    }
+}
+
+impl<T> Future for HookFuture<T> {
+    type Output = T;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<T> {
+        let me = self.get_mut();
+        if let Some(register) = me.register.take() {
+            me.hook = Some(outside_groups(|| register(me.slot.clone())));
+        }
+        let mut slot = me.slot.borrow_mut();
+        if let Some(value) = slot.value.take() {
+            if let Some(hook) = me.hook.take() {
+                hook.unhook();
+            }
+            Poll::Ready(value)
+        } else {
+            slot.waker = Some(cx.waker().clone());
+            Poll::Pending
+        }
+    }
+}
+
+impl<T> Drop for HookFuture<T> {
+    fn drop(&mut self) {
+        if let Some(hook) = self.hook.take() {
+            hook.unhook();
+        }
+    }
+}
+
+/// Returns a future that completes after `duration` has passed. Hexchat's
+/// timers have millisecond resolution, and longer durations than a timer
+/// supports are shortened to the longest one. The timer starts when the
+/// future is first awaited.
+///
+pub fn sleep(duration: Duration) -> HookFuture<()> {
+    HookFuture::new(Box::new(move |slot| {
+        let hc = unsafe { &*PHEXCHAT };
+        hc.hook_timer(timer_millis(duration) as i64,
+                      move |_hc, _ud| {
+                          fill_slot(&slot, ());
+                          0
+                      },
+                      NoData)
+    }))
+}
+
+/// Returns a future that resolves to the arguments of the next occurrence of
+/// the named print event, such as "Channel Message". The event isn't eaten.
+///
+pub fn next_print(event_name: &str) -> HookFuture<Vec<String>> {
+    let event_name = event_name.to_string();
+    HookFuture::new(Box::new(move |slot| {
+        let hc = unsafe { &*PHEXCHAT };
+        hc.hook_print(&event_name,
+                      Priority::Norm,
+                      move |_hc, word, _ud| {
+                          fill_slot(&slot, word.to_vec());
+                          Eat::None
+                      },
+                      NoData)
+    }))
+}
+
+/// Returns a future that resolves to the next occurrence of the given text
+/// event. The event isn't eaten.
+///
+pub fn next_text_event(kind: TextEventKind) -> HookFuture<TextEvent> {
+    HookFuture::new(Box::new(move |slot| {
+        let hc = unsafe { &*PHEXCHAT };
+        hc.hook_text_event(kind,
+                           Priority::Norm,
+                           move |_hc, event, _ud| {
+                               fill_slot(&slot, event.clone());
+                               Eat::None
+                           },
+                           NoData)
+    }))
+}
+
+/// Returns a future that resolves to the next server message of the named
+/// type, such as "PRIVMSG", or any type for "RAW LINE". The message isn't
+/// eaten.
+///
+pub fn next_server_msg(name: &str) -> HookFuture<IrcMessage> {
+    let name = name.to_string();
+    HookFuture::new(Box::new(move |slot| {
+        let hc = unsafe { &*PHEXCHAT };
+        hc.hook_server_msg(&name,
+                           Priority::Norm,
+                           move |_hc, msg, _ud| {
+                               fill_slot(&slot, msg.clone());
+                               Eat::None
+                           },
+                           NoData)
+    }))
+}
+
+#[cfg(all(test, feature = "testing"))]
+mod tests {
+    use super::*;
+    use crate::hook::HookKind;
+    use crate::testing::MockHexchat;
+
+    fn timers() -> usize {
+        Hook::active().iter().filter(|h| h.kind() == HookKind::Timer).count()
+    }
+
+    #[test]
+    fn waiting_tasks_leave_no_timer() {
+        let mock   = MockHexchat::new();
+        let hc     = mock.hexchat();
+        let before = timers();
+        spawn_local(async move {
+            let args = next_print("Channel Message").await;
+            hc.print(&format!("got {}", args[1]));
+        });
+        assert_eq!(timers(), before + 1);
+        mock.advance(Duration::from_millis(100));
+        assert_eq!(timers(), before);
+
+        mock.inject_print("Channel Message", &["bob", "hi"]);
+        assert_eq!(timers(), before + 1);
+        mock.advance(Duration::from_millis(100));
+        assert_eq!(mock.printed_text(), vec!["got hi"]);
+        assert_eq!(timers(), before);
+    }
+
+    #[test]
+    fn sleep_resumes_the_task() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        spawn_local(async move {
+            sleep(Duration::from_millis(50)).await;
+            hc.print("awake");
+        });
+        mock.advance(Duration::from_millis(40));
+        assert!(mock.printed_text().is_empty());
+        mock.advance(Duration::from_millis(20));
+        assert_eq!(mock.printed_text(), vec!["awake"]);
+    }
+
+    #[test]
+    fn next_text_event_keeps_empty_args() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        spawn_local(async move {
+            let event = next_text_event(TextEventKind::ChannelMessage).await;
+            if let TextEvent::ChannelMessage { identified, .. } = event {
+                hc.print(&format!("identified {}", identified));
+            }
+        });
+        mock.advance(Duration::from_millis(100));
+        mock.inject_print("Channel Message", &["bob", "hi", "", "+"]);
+        mock.advance(Duration::from_millis(100));
+        assert_eq!(mock.printed_text(), vec!["identified +"]);
+    }
+
+    #[test]
+    fn long_sleeps_dont_wrap_around() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        spawn_local(async move {
+            sleep(Duration::from_millis((1 << 32) + 10)).await;
+            hc.print("awake");
+        });
+        mock.advance(Duration::from_secs(60));
+        assert!(mock.printed_text().is_empty());
+    }
+
+    #[test]
+    fn panicking_task_spares_the_others() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        spawn_local(async {
+            sleep(Duration::from_millis(10)).await;
+            panic!("task panicked");
+        });
+        spawn_local(async move {
+            sleep(Duration::from_millis(20)).await;
+            hc.print("second");
+        });
+        mock.advance(Duration::from_millis(100));
+        assert!(mock.printed_text().contains(&"second".to_string()));
+
+        spawn_local(async move { hc.print("third") });
+        mock.advance(Duration::from_millis(10));
+        assert!(mock.printed_text().contains(&"third".to_string()));
+    }
+}
@@ -0,0 +1,117 @@
+/*
+ * Hexchat's variadic functions for `MockHexchat` (see testing.rs). Variadic
+ * functions can't be defined in stable Rust, so these gather their arguments
+ * and hand them on to the mock's Rust functions. Only built with the
+ * "testing" feature.
+ */
+
+#include <stdarg.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <time.h>
+
+/* The most arguments `Hexchat::emit_print()` passes for a text event. */
+#define MAX_EVENT_ARGS  6
+
+typedef struct {
+    time_t  server_time_utc;
+} hexchat_event_attrs;
+
+/* Implemented in testing.rs. */
+extern void hexchat_api_mock_print(const void *hp, const char *text);
+extern void hexchat_api_mock_command(const void *hp, const char *command);
+extern int  hexchat_api_mock_emit(const char  *event,
+                                  const char **args,
+                                  int          count,
+                                  time_t       time);
+
+/* Formats a `printf()` style string into a buffer the caller frees. */
+static char *format_va(const char *format, va_list args)
+{
+    va_list copy;
+    int     len;
+    char   *text;
+
+    va_copy(copy, args);
+    len = vsnprintf(NULL, 0, format, copy);
+    va_end(copy);
+    if (len < 0) {
+        return NULL;
+    }
+    text = malloc((size_t)len + 1);
+    if (text) {
+        vsnprintf(text, (size_t)len + 1, format, args);
+    }
+    return text;
+}
+
+void hexchat_api_mock_printf(const void *hp, const char *format, ...)
+{
+    va_list args;
+    char   *text;
+
+    va_start(args, format);
+    text = format_va(format, args);
+    va_end(args);
+    if (text) {
+        hexchat_api_mock_print(hp, text);
+        free(text);
+    }
+}
+
+void hexchat_api_mock_commandf(const void *hp, const char *format, ...)
+{
+    va_list args;
+    char   *command;
+
+    va_start(args, format);
+    command = format_va(format, args);
+    va_end(args);
+    if (command) {
+        hexchat_api_mock_command(hp, command);
+        free(command);
+    }
+}
+
+/* Reads the event's arguments, which end at the first NULL. */
+static int emit_va(const char *event, time_t time, va_list args)
+{
+    const char *argv[MAX_EVENT_ARGS];
+    int         count = 0;
+
+    while (count < MAX_EVENT_ARGS) {
+        const char *arg = va_arg(args, const char *);
+        if (!arg) {
+            break;
+        }
+        argv[count++] = arg;
+    }
+    return hexchat_api_mock_emit(event, argv, count, time);
+}
+
+int hexchat_api_mock_emit_print(const void *hp, const char *event, ...)
+{
+    va_list args;
+    int     result;
+
+    (void)hp;
+    va_start(args, event);
+    result = emit_va(event, 0, args);
+    va_end(args);
+    return result;
+}
+
+int hexchat_api_mock_emit_print_attrs(const void                *hp,
+                                      const hexchat_event_attrs *attrs,
+                                      const char                *event,
+                                      ...)
+{
+    va_list args;
+    int     result;
+
+    (void)hp;
+    va_start(args, event);
+    result = emit_va(event, attrs ? attrs->server_time_utc : 0, args);
+    va_end(args);
+    return result;
+}
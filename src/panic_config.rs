@@ -0,0 +1,387 @@
+//! The panic hook that reports panics in the plugin, and its configuration.
+//! By default reports are printed to the active Hexchat window, and the
+//! debug build includes a stack trace using
+//! [Backtrace](https://crates.io/crates/backtrace). A `PanicConfig` passed to
+//! `Hexchat::set_panic_config()`, typically from the plugin's init function,
+//! can send them to a tab of their own or to a file in Hexchat's config
+//! directory, add stack traces to release builds, and pass panics on to the
+//! panic hook that was set before the plugin loaded.
+//!
+//! ```no_test
+//! hc.set_panic_config(PanicConfig::new().tab("myplugin-panics")
+//!                                       .file("myplugin-panics.log")
+//!                                       .backtrace(true));
+//! ```
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::iter::Peekable;
+use std::panic::{self, PanicHookInfo};
+use std::path::PathBuf;
+use std::str::Chars;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+#[cfg(feature = "threadsafe")]
+use std::thread;
+
+use backtrace::Backtrace;
+
+use crate::context::open_tab;
+use crate::hexchat::Hexchat;
+use crate::hexchat_entry_points::{plugin_name, PHEXCHAT};
+use crate::panic_policy::current_hook;
+#[cfg(feature = "threadsafe")]
+use crate::thread_facilities::{main_thread, MAIN_THREAD_ID};
+
+type PanicHook = dyn Fn(&PanicHookInfo<'_>) + Sync + Send + 'static;
+
+/// The panic hook that was set before the plugin's, restored when the plugin
+/// is unloaded.
+///
+static PREVIOUS_HOOK: Mutex<Option<Arc<PanicHook>>> = Mutex::new(None);
+
+/// Where panic reports go and what they include.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PanicConfig {
+    chain_previous : bool,
+    tab            : Option<String>,
+    file           : Option<String>,
+    backtrace      : bool,
+}
+
+impl PanicConfig {
+    /// Creates the default configuration: reports are printed to the active
+    /// window, with a stack trace in debug builds only.
+    ///
+    pub fn new() -> Self {
+        PanicConfig {
+            chain_previous : false,
+            tab            : None,
+            file           : None,
+            backtrace      : cfg!(debug_assertions),
+        }
+    }
+
+    /// Whether to also pass panics to the panic hook that was set before the
+    /// plugin loaded, such as Rust's default hook, which writes to stderr.
+    /// # Arguments
+    /// * `chain` - `true` to call the previous hook after reporting.
+    ///
+    pub fn chain_previous(mut self, chain: bool) -> Self {
+        self.chain_previous = chain;
+        self
+    }
+
+    /// Prints reports to a tab of their own, which is opened if needed,
+    /// instead of the active window.
+    /// # Arguments
+    /// * `name` - The name of the tab.
+    ///
+    pub fn tab(mut self, name: &str) -> Self {
+        self.tab = Some(name.to_string());
+        self
+    }
+
+    /// Appends reports to a file. Only a one line notice naming the file is
+    /// printed to the window.
+    /// # Arguments
+    /// * `path` - The file's path, relative to Hexchat's config directory.
+    ///
+    pub fn file(mut self, path: &str) -> Self {
+        self.file = Some(path.to_string());
+        self
+    }
+
+    /// Whether to include a stack trace. Defaults to `true` for debug builds
+    /// and `false` for release builds.
+    /// # Arguments
+    /// * `backtrace` - `true` to include a stack trace.
+    ///
+    pub fn backtrace(mut self, backtrace: bool) -> Self {
+        self.backtrace = backtrace;
+        self
+    }
+}
+
+impl Default for PanicConfig {
+    fn default() -> Self {
+        PanicConfig::new()
+    }
+}
+
+impl Hexchat {
+    /// Replaces the configuration of the plugin's panic hook.
+    /// # Arguments
+    /// * `config` - Where panic reports go and what they include.
+    ///
+    pub fn set_panic_config(&self, config: PanicConfig) {
+        install_panic_hook(config);
+    }
+}
+
+/// Sets the plugin's panic hook, replacing the one currently set. The report
+/// file's full path is worked out here, since Hexchat can't be asked for its
+/// config directory from the thread a panic happens on.
+///
+fn install_panic_hook(config: PanicConfig) {
+    let file = config.file.as_ref().map(|file| {
+        let hc  = unsafe { &*PHEXCHAT };
+        let dir = hc.get_info("configdir").unwrap_or_default();
+        PathBuf::from(dir).join(file)
+    });
+    panic::set_hook(Box::new(move |info| {
+        report_panic(info, &config, file.as_ref())
+    }));
+}
+
+/// Sets the panic hook with the default configuration, keeping the hook it
+/// replaces so it can be chained and later restored. Called when the plugin
+/// is loaded.
+///
+pub (crate) fn set_panic_hook() {
+    let previous = panic::take_hook();
+    *PREVIOUS_HOOK.lock().unwrap() = Some(Arc::from(previous));
+    install_panic_hook(PanicConfig::new());
+}
+
+/// Restores the panic hook that was set before the plugin loaded. Called
+/// when the plugin is unloaded.
+///
+pub (crate) fn remove_panic_hook() {
+    let _ = panic::take_hook();
+    if let Some(previous) = PREVIOUS_HOOK.lock().unwrap().take() {
+        panic::set_hook(Box::new(move |info| previous(info)));
+    }
+}
+
+/// The plugin's panic hook.
+///
+fn report_panic(panic_info : &PanicHookInfo<'_>,
+                config     : &PanicConfig,
+                file       : Option<&PathBuf>)
+{
+    let lines = build_report(panic_info, config);
+
+    if let Some(file) = file {
+        match write_report(file, &lines) {
+            Ok(()) => {
+                deliver(config, vec![
+                    lines[0].clone(),
+                    format!("\x0313Panic report written to {}.",
+                            file.display())]);
+            },
+            Err(err) => {
+                let mut lines = lines;
+                lines.push(format!("\x0304Couldn't write to {}: {}",
+                                   file.display(), err));
+                deliver(config, lines);
+            },
+        }
+    } else {
+        deliver(config, lines);
+    }
+    if config.chain_previous {
+        let previous = PREVIOUS_HOOK.lock().unwrap().clone();
+        if let Some(previous) = previous {
+            previous(panic_info);
+        }
+    }
+}
+
+/// Builds the lines of a panic report, with Hexchat color codes.
+///
+fn build_report(panic_info: &PanicHookInfo<'_>, config: &PanicConfig)
+    -> Vec<String>
+{
+    let mut lines   = vec![];
+    let mut loc     = String::new();
+    let plugin_name = plugin_name().unwrap_or("a Rust plugin".to_string());
+
+    if let Some(s) = panic_info.payload().downcast_ref::<&str>() {
+        lines.push(format!("\x0304<<Panicked!>>\t{:?}", s));
+    } else if let Some(s) = panic_info.payload().downcast_ref::<String>() {
+        lines.push(format!("\x0304<<Panicked!>>\t{:?}", s));
+    } else {
+        lines.push(format!("\x0304<<Panicked!>>\t{:?}", panic_info));
+    }
+    if let Some(hook) = current_hook() {
+        lines.push(format!("\x0313Panic occured in {}'s {}.",
+                           plugin_name, hook));
+    }
+    if let Some(location) = panic_info.location() {
+        lines.push(
+            format!("\x0313Panic occured in {} in file '{}' at line {:?}.",
+                    plugin_name,
+                    location.file(),
+                    location.line()));
+        loc = format!("{}:{:?}", location.file(), location.line());
+    }
+    if config.backtrace {
+        let mut trace = vec![];
+        let mut begin = 0;
+        let mut end   = 0;
+        let     bt    = Backtrace::new();
+        let     btstr = format!("{:?}", bt);
+
+        for line in btstr.lines() {
+            let line  = String::from(line);
+            if begin == 0 && !loc.is_empty() && line.contains(&loc) {
+                // Underlined and magenta.
+                trace.push(format!("\x1F\x0313{}", line));
+                begin = end;
+            } else {
+                trace.push(format!("\x0304{}", line));
+            }
+            end += 1;
+        }
+        // Start the trace where the panic actually occurred.
+        begin = if begin == 0 { 0 } else { begin - 1 };
+        lines.push(trace[begin..end].join("\n"));
+    }
+    lines
+}
+
+/// Prints the report to the configured tab, or the active window. Hexchat
+/// can only be used from its main thread, so reports of panics on other
+/// threads are handed to it with `main_thread()`.
+///
+fn deliver(config: &PanicConfig, lines: Vec<String>) {
+    let tab = config.tab.clone();
+
+    #[cfg(feature = "threadsafe")]
+    if Some(thread::current().id()) != unsafe { MAIN_THREAD_ID } {
+        main_thread(move |hc| print_report(hc, tab.as_deref(), &lines));
+        return;
+    }
+    print_report(unsafe { &*PHEXCHAT }, tab.as_deref(), &lines);
+}
+
+/// Prints the lines of a report to the named tab, or the active window.
+///
+fn print_report(hc: &Hexchat, tab: Option<&str>, lines: &[String]) {
+    match tab.and_then(open_tab) {
+        Some(ctx) => {
+            for line in lines {
+                let _ = ctx.print(line);
+            }
+        },
+        None => {
+            for line in lines {
+                hc.print(line);
+            }
+        },
+    }
+}
+
+/// Appends the report, without color codes, to the file at `path`.
+///
+fn write_report(path: &PathBuf, lines: &[String]) -> std::io::Result<()> {
+    let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+    let secs     = SystemTime::now().duration_since(UNIX_EPOCH)
+                                    .map(|d| d.as_secs())
+                                    .unwrap_or(0);
+    writeln!(file, "--- Panic at {} (seconds since the epoch) ---", secs)?;
+    for line in lines {
+        writeln!(file, "{}", strip_formatting(line))?;
+    }
+    Ok(())
+}
+
+/// Removes Hexchat's color and formatting codes from a line.
+///
+fn strip_formatting(line: &str) -> String {
+    let mut text  = String::with_capacity(line.len());
+    let mut chars = line.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x03' => {
+                // Skip the foreground and optional background color numbers.
+                if skip_digits(&mut chars) > 0 && chars.peek() == Some(&',') {
+                    let mut ahead = chars.clone();
+                    ahead.next();
+                    if ahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        chars.next();
+                        skip_digits(&mut chars);
+                    }
+                }
+            },
+            '\x02' | '\x0F' | '\x16' | '\x1D' | '\x1F' => {},
+            '\t' => text.push(' '),
+            _ => text.push(c),
+        }
+    }
+    text
+}
+
+/// Skips up to the two digits of a color number, returning how many there
+/// were.
+///
+fn skip_digits(chars: &mut Peekable<Chars<'_>>) -> usize {
+    let mut count = 0;
+    while count < 2 && chars.peek().is_some_and(|c| c.is_ascii_digit()) {
+        chars.next();
+        count += 1;
+    }
+    count
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn builder_sets_each_option() {
+        let config = PanicConfig::new().chain_previous(true)
+                                       .tab("panics")
+                                       .file("panics.log")
+                                       .backtrace(false);
+        assert!(config.chain_previous && !config.backtrace);
+        assert_eq!(config.tab.as_deref(), Some("panics"));
+        assert_eq!(config.file.as_deref(), Some("panics.log"));
+        assert_eq!(PanicConfig::default().backtrace, cfg!(debug_assertions));
+    }
+
+    #[test]
+    fn strips_colors_and_formatting() {
+        assert_eq!(strip_formatting("\x0304<<Panicked!>>\t\"boom\""),
+                   "<<Panicked!>> \"boom\"");
+        assert_eq!(strip_formatting("\x1F\x0313,01under\x0f \x035,x"),
+                   "under ,x");
+        assert_eq!(strip_formatting("\x03123"), "3");
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn reports_go_to_the_tab_and_file() {
+        use std::fs;
+        use crate::testing::MockHexchat;
+
+        let mock = MockHexchat::new();
+        let dir  = std::env::temp_dir().join(
+                        format!("hexchat-api-panic-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        mock.set_info("configdir", &dir.to_string_lossy())
+            .add_context("MockNet", "panics");
+
+        let default = panic::take_hook();
+        mock.hexchat().set_panic_config(PanicConfig::new().tab("panics")
+                                                          .file("panics.log")
+                                                          .backtrace(false));
+        let _ = panic::catch_unwind(|| panic!("boom"));
+        panic::set_hook(default);
+
+        let printed: Vec<_> = mock.printed().into_iter()
+                                            .filter(|p| p.channel == "panics")
+                                            .map(|p| p.text)
+                                            .collect();
+        let report = fs::read_to_string(dir.join("panics.log")).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+
+        assert_eq!(printed[0], "\x0304<<Panicked!>>\t\"boom\"");
+        assert!(printed[1].contains("Panic report written to"));
+        assert!(report.starts_with("--- Panic at "));
+        assert!(report.contains("<<Panicked!>> \"boom\"\n"));
+        assert!(report.contains("panic_config.rs"));
+    }
+}
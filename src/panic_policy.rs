@@ -0,0 +1,269 @@
+//! Panic handling for hook callbacks. Panics in callbacks are caught before
+//! they can unwind into Hexchat, and the hook's `PanicPolicy` decides what
+//! happens next: the `Eat` value returned to Hexchat in the callback's place,
+//! whether the hook is unhooked after it has panicked some number of times,
+//! and whether the whole plugin is disabled. The panic report printed by the
+//! panic hook names the hook that panicked.
+//!
+//! ```no_test
+//! hc.set_panic_policy(PanicPolicy::new().disable_plugin_after(10));
+//!
+//! hc.hook_print("Channel Message", Priority::Norm, on_message, NoData)
+//!   .set_panic_policy(PanicPolicy::new().fallback(Eat::Hexchat)
+//!                                       .unhook_after(3));
+//! ```
+
+use std::cell::{Cell, RefCell};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::ptr::null;
+use std::sync::{Arc, RwLock};
+
+use crate::hexchat::{Eat, Hexchat, hexchat_context};
+use crate::hexchat_entry_points::PHEXCHAT;
+use crate::hook::{Hook, HookInfo};
+use crate::local_executor::local_executor_deinit;
+#[cfg(feature = "threadsafe")]
+use crate::thread_facilities::main_thread_deinit;
+
+/// The policy for hooks that haven't been given their own.
+///
+static DEFAULT_POLICY: RwLock<PanicPolicy> = RwLock::new(PanicPolicy::new());
+
+thread_local! {
+    /// The hook whose callback is running, for panic reports.
+    static CURRENT_HOOK: RefCell<Option<Arc<HookInfo>>>
+        = const { RefCell::new(None) };
+
+    /// The context the running callback was invoked in, for error reports.
+    static CURRENT_CONTEXT: Cell<*const hexchat_context>
+        = const { Cell::new(null()) };
+}
+
+/// What to do when a hook's callback panics. Panics are counted per hook.
+/// The fallback `Eat` value doesn't apply to timers, which keep running
+/// until `unhook_after` is reached, and one-shot callbacks, such as those of
+/// `Hexchat::run_after()`, are spent whether or not they panic. A panic in a
+/// task passed to `main_thread()` is given to the task's `AsyncResult` as
+/// an error rather than counted against a hook.
+///
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct PanicPolicy {
+    fallback             : Eat,
+    unhook_after         : Option<u32>,
+    disable_plugin_after : Option<u32>,
+}
+
+impl PanicPolicy {
+    /// Creates the default policy: `Eat::None` is returned in place of the
+    /// callback's value, and the hook is left in place.
+    ///
+    pub const fn new() -> Self {
+        PanicPolicy {
+            fallback             : Eat::None,
+            unhook_after         : None,
+            disable_plugin_after : None,
+        }
+    }
+
+    /// Sets the `Eat` value returned to Hexchat when the callback panics.
+    /// # Arguments
+    /// * `fallback` - The value to return in place of the callback's.
+    ///
+    pub fn fallback(mut self, fallback: Eat) -> Self {
+        self.fallback = fallback;
+        self
+    }
+
+    /// Unhooks the hook once its callback has panicked `panics` times.
+    /// # Arguments
+    /// * `panics` - The number of panics the hook is allowed.
+    ///
+    pub fn unhook_after(mut self, panics: u32) -> Self {
+        self.unhook_after = Some(panics);
+        self
+    }
+
+    /// Disables the whole plugin, by unhooking all its hooks, once the
+    /// hook's callback has panicked `panics` times.
+    /// # Arguments
+    /// * `panics` - The number of panics the hook is allowed.
+    ///
+    pub fn disable_plugin_after(mut self, panics: u32) -> Self {
+        self.disable_plugin_after = Some(panics);
+        self
+    }
+}
+
+impl Default for PanicPolicy {
+    fn default() -> Self {
+        PanicPolicy::new()
+    }
+}
+
+impl Hexchat {
+    /// Sets the panic policy for hooks that haven't been given their own with
+    /// `Hook::set_panic_policy()`.
+    /// # Arguments
+    /// * `policy` - The default panic policy.
+    ///
+    pub fn set_panic_policy(&self, policy: PanicPolicy) {
+        *DEFAULT_POLICY.write().unwrap() = policy;
+    }
+}
+
+/// Marks a hook's callback as running until the guard is dropped, so a panic
+/// in it can be reported with the hook's identity, and an error it returns
+/// can be printed in the context it was invoked in. Callbacks can run inside
+/// other callbacks, so the previously running hook is restored on drop.
+///
+pub (crate) struct RunningHook {
+    previous         : Option<Arc<HookInfo>>,
+    previous_context : *const hexchat_context,
+}
+
+impl RunningHook {
+    pub (crate) fn enter(hook: &Hook) -> Self {
+        let info     = Some(hook.info().clone());
+        let previous = CURRENT_HOOK.with(|h| h.replace(info));
+        let context  = unsafe {
+            let hc = &*PHEXCHAT;
+            (hc.c_get_context)(hc)
+        };
+        let previous_context = CURRENT_CONTEXT.with(|c| c.replace(context));
+        RunningHook { previous, previous_context }
+    }
+}
+
+impl Drop for RunningHook {
+    fn drop(&mut self) {
+        CURRENT_HOOK.with(|h| *h.borrow_mut() = self.previous.take());
+        CURRENT_CONTEXT.with(|c| c.set(self.previous_context));
+    }
+}
+
+/// Returns a description of the hook whose callback is running on this
+/// thread, if any.
+///
+pub (crate) fn current_hook() -> Option<String> {
+    CURRENT_HOOK.with(|h| h.borrow().as_ref().map(|info| info.to_string()))
+}
+
+/// Returns the context the running callback was invoked in, or null if no
+/// callback is running on this thread. The context may have been closed
+/// since, which Hexchat's `set_context()` detects.
+///
+pub (crate) fn current_context() -> *const hexchat_context {
+    CURRENT_CONTEXT.with(Cell::get)
+}
+
+/// Applies the panic policy after a hook's callback has panicked.
+/// # Arguments
+/// * `hook` - The hook whose callback panicked.
+/// # Returns
+/// * The `Eat` value to return to Hexchat in place of the callback's.
+///
+pub (crate) fn hook_panicked(hook: &Hook) -> Eat {
+    // This runs outside the trampolines' `catch_unwind()`, where a panic
+    // would unwind into Hexchat and abort it.
+    catch_unwind(AssertUnwindSafe(|| apply_policy(hook))).unwrap_or(Eat::None)
+}
+
+/// Counts the panic against the hook and carries out its policy.
+///
+fn apply_policy(hook: &Hook) -> Eat {
+    let (policy, count) = hook.count_panic();
+    let policy = policy.unwrap_or_else(|| *DEFAULT_POLICY.read().unwrap());
+    let hc     = unsafe { &*PHEXCHAT };
+
+    if policy.disable_plugin_after.is_some_and(|n| count >= n) {
+        hc.print(&format!("\x0304Disabling the plugin after {} panics in \
+                           its {}.", count, hook.info()));
+        disable_plugin();
+    } else if policy.unhook_after.is_some_and(|n| count >= n) {
+        hc.print(&format!("\x0304Unhooked the {} after {} panics.",
+                          hook.info(), count));
+        hook.unhook();
+    }
+    policy.fallback
+}
+
+/// Stops the plugin from responding to anything without unloading it: its
+/// hooks are all unhooked, and its pending tasks are dropped.
+///
+fn disable_plugin() {
+    #[cfg(feature = "threadsafe")]
+    main_thread_deinit();
+    local_executor_deinit();
+    Hook::unhook_all();
+}
+
+#[cfg(all(test, feature = "testing", feature = "threadsafe"))]
+mod tests {
+    use std::cell::Cell;
+    use std::rc::Rc;
+    use std::thread;
+    use std::time::Duration;
+
+    use super::*;
+    use crate::hexchat::Priority;
+    use crate::testing::MockHexchat;
+    use crate::thread_facilities::main_thread;
+    use crate::user_data::UserData::NoData;
+
+    #[test]
+    fn panicking_task_leaves_the_queue_running() {
+        let mock  = MockHexchat::new();
+        let hooks = mock.hook_count();
+
+        let (bad, good) = thread::spawn(|| {
+            (main_thread(|_| -> i32 { panic!("task panicked") }),
+             main_thread(|_| 2))
+        }).join().unwrap();
+        mock.advance(Duration::from_millis(10));
+
+        let error = bad.get().unwrap_err();
+        assert!(format!("{:?}", error).contains("task panicked"));
+        assert_eq!(good.get().unwrap(), 2);
+        assert_eq!(mock.hook_count(), hooks);
+
+        let late = thread::spawn(|| main_thread(|_| 3)).join().unwrap();
+        mock.advance(Duration::from_millis(10));
+        assert_eq!(late.get().unwrap(), 3);
+    }
+
+    #[test]
+    fn timers_are_unhooked_by_policy() {
+        let mock  = MockHexchat::new();
+        let ticks = Rc::new(Cell::new(0));
+        let t     = ticks.clone();
+        let hook  = mock.hexchat().hook_timer(10, move |_, _| -> i32 {
+                                                  t.set(t.get() + 1);
+                                                  panic!("timer panicked")
+                                              }, NoData);
+        hook.set_panic_policy(PanicPolicy::new().unhook_after(3));
+
+        mock.advance(Duration::from_millis(20));
+        assert_eq!(ticks.get(), 2);
+        assert!(hook.is_hooked());
+        mock.advance(Duration::from_millis(100));
+        assert_eq!(ticks.get(), 3);
+        assert!(!hook.is_hooked());
+    }
+
+    #[test]
+    fn panicking_hook_disables_plugin() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        hc.set_panic_policy(PanicPolicy::new().disable_plugin_after(1));
+
+        let _hook = hc.hook_command("BAD", Priority::Norm,
+                                    |_, _, _, _| -> Eat { panic!("bad") },
+                                    "", NoData);
+        mock.inject_command("BAD");
+        hc.set_panic_policy(PanicPolicy::new());
+
+        assert_eq!(mock.hook_count(), 0);
+        let late = thread::spawn(|| main_thread(|_| 1).get()).join().unwrap();
+        assert!(late.is_err());
+    }
+}
@@ -0,0 +1,248 @@
+#![cfg(feature = "serde")]
+
+//! Structured plugin preferences. Any value that implements `Serialize` can be
+//! written to the plugin's config file as JSON. Values too large to fit in a
+//! single pref are split across several keys and put back together when read.
+
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+
+use crate::errors::HexchatError;
+use crate::errors::HexchatError::*;
+use crate::hexchat::{Hexchat, MAX_PREF_VALUE_SIZE};
+
+/// Tag for a value stored whole under its own key.
+const WHOLE_TAG: char = 'j';
+
+/// Tag for the header of a value that was split into chunks. It's followed by
+/// the number of chunks.
+const CHUNKED_TAG: char = 'c';
+
+/// Prepended to each chunk. Hexchat skips leading spaces and `=` characters
+/// when reading a value back, which could otherwise eat part of the JSON.
+const CHUNK_MARK: char = '-';
+
+/// Room for the JSON in each chunk - less the marker and terminating null.
+const CHUNK_SIZE: usize = MAX_PREF_VALUE_SIZE - 2;
+
+impl Hexchat {
+    /// Serializes `value` to JSON and writes it to the plugin's config file.
+    /// Unlike `pluginpref_set()`, there's no limit on the size of the value;
+    /// if it won't fit under one key it's split across several keys named
+    /// `<name>.0`, `<name>.1`, and so on, with `name` recording how many
+    /// there are. Chunks left over from a previous, larger value are deleted.
+    /// # Arguments
+    /// * `name`    - The name of the pref to set.
+    /// * `value`   - The value to serialize and store.
+    /// # Returns
+    /// * `Ok(())` if the value was written, or a `HexchatError` if it couldn't
+    ///   be serialized or Hexchat failed to store it.
+    ///
+    pub fn pluginpref_set_value<T>(&self,
+                                   name  : &str,
+                                   value : &T)
+        -> Result<(), HexchatError>
+    where
+        T: Serialize + ?Sized
+    {
+        let json = serde_json::to_string(value)
+                             .map_err(|e| SerializationFailed(e.to_string()))?;
+        let old_count = self.pluginpref_chunk_count(name);
+
+        let new_count = if json.len() < CHUNK_SIZE {
+            self.pluginpref_write(name, &format!("{}{}", WHOLE_TAG, json))?;
+            0
+        } else {
+            let chunks = split_chunks(&json);
+            for (i, chunk) in chunks.iter().enumerate() {
+                self.pluginpref_write(&chunk_name(name, i),
+                                      &format!("{}{}", CHUNK_MARK, chunk))?;
+            }
+            // The header goes last so a partial write isn't read back as
+            // a whole value.
+            self.pluginpref_write(name,
+                                  &format!("{}{}", CHUNKED_TAG, chunks.len()))?;
+            chunks.len()
+        };
+        for i in new_count..old_count {
+            self.pluginpref_delete(&chunk_name(name, i));
+        }
+        Ok(())
+    }
+
+    /// Reads back and deserializes a value written by
+    /// `pluginpref_set_value()`, reassembling it if it was split into chunks.
+    /// # Arguments
+    /// * `name` - The name of the pref to load.
+    /// # Returns
+    /// * `Ok(Some(value))` if the pref exists, `Ok(None)` if it doesn't, or a
+    ///   `HexchatError` if the stored data is incomplete, wasn't written by
+    ///   `pluginpref_set_value()`, or doesn't deserialize to `T`.
+    ///
+    pub fn pluginpref_get_value<T>(&self, name: &str)
+        -> Result<Option<T>, HexchatError>
+    where
+        T: DeserializeOwned
+    {
+        let header = match self.pluginpref_get_raw(name) {
+            Some(header) => header,
+            None => return Ok(None),
+        };
+        let json = if let Some(json) = header.strip_prefix(WHOLE_TAG) {
+            json.to_string()
+        } else if let Some(count) = parse_chunk_count(&header) {
+            let mut json = String::new();
+            for i in 0..count {
+                let chunk = self.pluginpref_get_raw(&chunk_name(name, i))
+                                .ok_or_else(|| {
+                    PluginPrefFailed(format!("{}: chunk {} of {} is missing.",
+                                             name, i, count))
+                })?;
+                json.push_str(chunk.strip_prefix(CHUNK_MARK)
+                                   .unwrap_or(&chunk));
+            }
+            json
+        } else {
+            return Err(PluginPrefFailed(
+                format!("{}: not written by `pluginpref_set_value()`.",
+                        name)));
+        };
+        serde_json::from_str(&json)
+                   .map(Some)
+                   .map_err(|e| SerializationFailed(e.to_string()))
+    }
+
+    /// Deletes a pref written by `pluginpref_set_value()` along with any
+    /// chunks it was split into.
+    /// # Arguments
+    /// * `name` - The name of the pref to delete.
+    /// # Returns
+    /// * `true` if the operation succeeds, `false` otherwise.
+    ///
+    pub fn pluginpref_delete_value(&self, name: &str) -> bool {
+        let count = self.pluginpref_chunk_count(name);
+        let mut ok = self.pluginpref_delete(name);
+        for i in 0..count {
+            ok &= self.pluginpref_delete(&chunk_name(name, i));
+        }
+        ok
+    }
+
+    /// Returns how many chunks the value currently stored under `name` was
+    /// split into; 0 if it wasn't chunked or doesn't exist.
+    ///
+    fn pluginpref_chunk_count(&self, name: &str) -> usize {
+        self.pluginpref_get_raw(name)
+            .and_then(|header| parse_chunk_count(&header))
+            .unwrap_or(0)
+    }
+
+    /// Writes a single, already encoded, string pref.
+    ///
+    fn pluginpref_write(&self, name: &str, value: &str)
+        -> Result<(), HexchatError>
+    {
+        if self.pluginpref_set_raw(name, value) {
+            Ok(())
+        } else {
+            Err(PluginPrefFailed(format!("{}: Hexchat failed to store the \
+                                          value.", name)))
+        }
+    }
+}
+
+/// Builds the key for the `i`th chunk of the pref `name`.
+///
+fn chunk_name(name: &str, i: usize) -> String {
+    format!("{}.{}", name, i)
+}
+
+/// Parses a chunked value's header into its chunk count.
+///
+fn parse_chunk_count(header: &str) -> Option<usize> {
+    header.strip_prefix(CHUNKED_TAG)?.parse().ok()
+}
+
+/// Splits `s` into pieces of at most `CHUNK_SIZE` bytes without breaking up
+/// any multibyte characters.
+///
+fn split_chunks(s: &str) -> Vec<&str> {
+    let mut chunks = vec![];
+    let mut rest   = s;
+    while !rest.is_empty() {
+        let mut end = rest.len().min(CHUNK_SIZE);
+        while !rest.is_char_boundary(end) {
+            end -= 1;
+        }
+        let (chunk, tail) = rest.split_at(end);
+        chunks.push(chunk);
+        rest = tail;
+    }
+    chunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn chunks_respect_char_boundaries() {
+        let s      = "é".repeat(CHUNK_SIZE);
+        let chunks = split_chunks(&s);
+        assert_eq!(chunks.len(), 2);
+        assert!(chunks.iter().all(|c| c.len() <= CHUNK_SIZE));
+        assert_eq!(chunks.concat(), s);
+        assert!(split_chunks("").is_empty());
+    }
+
+    #[test]
+    fn chunk_headers() {
+        assert_eq!(parse_chunk_count("c3"), Some(3));
+        assert_eq!(parse_chunk_count("j3"), None);
+        assert_eq!(parse_chunk_count("c"), None);
+        assert_eq!(chunk_name("pref", 2), "pref.2");
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn large_values_round_trip() {
+        let mock  = crate::testing::MockHexchat::new();
+        let hc    = mock.hexchat();
+        let large = vec!["line\nwith \"quotes\"".to_string(); 200];
+
+        hc.pluginpref_set_value("list", &large).unwrap();
+        assert_eq!(hc.pluginpref_get_value::<Vec<String>>("list").unwrap(),
+                   Some(large));
+        assert!(mock.pluginprefs().iter().any(|(n, _)| n == "list.0"));
+
+        // Shrinking the value drops the chunks it no longer needs.
+        hc.pluginpref_set_value("list", &vec![1, 2, 3]).unwrap();
+        assert_eq!(hc.pluginpref_get_value::<Vec<i32>>("list").unwrap(),
+                   Some(vec![1, 2, 3]));
+        assert_eq!(mock.pluginprefs().len(), 1);
+
+        assert!(hc.pluginpref_delete_value("list"));
+        assert!(mock.pluginprefs().is_empty());
+        assert_eq!(hc.pluginpref_get_value::<i32>("list").unwrap(), None);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn incomplete_or_foreign_values_are_errors() {
+        let mock = crate::testing::MockHexchat::new();
+        let hc   = mock.hexchat();
+
+        hc.pluginpref_set_value("big", &"x".repeat(CHUNK_SIZE * 2)).unwrap();
+        hc.pluginpref_delete("big.1");
+        assert!(matches!(hc.pluginpref_get_value::<String>("big"),
+                         Err(PluginPrefFailed(_))));
+
+        hc.pluginpref_set("plain", crate::PrefValue::StringVal("hi".into()));
+        assert!(matches!(hc.pluginpref_get_value::<String>("plain"),
+                         Err(PluginPrefFailed(_))));
+
+        hc.pluginpref_set_value("num", &5).unwrap();
+        assert!(matches!(hc.pluginpref_get_value::<String>("num"),
+                         Err(SerializationFailed(_))));
+    }
+}
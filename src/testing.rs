@@ -0,0 +1,1515 @@
+#![cfg(feature = "testing")]
+
+//! An in-process stand-in for the Hexchat client that plugin code can be
+//! unit tested against with `cargo test`. Hexchat hands plugins a struct of
+//! C function pointers when they're loaded; this module provides a `Hexchat`
+//! struct whose function pointers are implemented in Rust and backed by a
+//! scriptable in-memory state instead of a running client.
+//!
+//! A test creates a `MockHexchat`, which installs the fake host in place of
+//! the real one and initializes the crate as if the plugin had just been
+//! loaded. Contexts, lists, prefs, and info strings can be set up on it, and
+//! events can be injected to invoke the callbacks registered with
+//! `hook_command()`, `hook_print()`, `hook_server()`, `hook_timer()`, etc.
+//! Everything the plugin prints, the commands it issues, and the text events
+//! it emits are recorded and can be asserted on.
+//!
+//! ``` no_test
+//! let mock = MockHexchat::new();
+//! let hc   = mock.hexchat();
+//!
+//! hc.hook_command("greet", Priority::Norm, |hc, word, _, _| {
+//!     hc.print(&format!("Hello, {}!", word[1]));
+//!     Eat::All
+//! }, "", NoData);
+//!
+//! mock.inject_command("greet world");
+//! assert_eq!(mock.printed_text(), vec!["Hello, world!"]);
+//! ```
+//!
+//! Only one `MockHexchat` can be alive at a time since the crate keeps its
+//! Hexchat pointer and hook list in globals. `MockHexchat::new()` blocks until
+//! any other instance has been dropped, which serializes tests that run on
+//! parallel threads. The thread that creates the mock is treated as the
+//! Hexchat main thread.
+//!
+//! Hexchat's `printf()`, `commandf()`, and `emit_print()` functions are
+//! variadic, which can't be defined in stable Rust. The mock's versions of
+//! them are written in C, in `mock_variadic.c`, and pass their arguments on
+//! to the Rust functions here.
+
+use libc::{c_char, c_int, c_void, time_t};
+use std::collections::HashMap;
+use std::ffi::{CStr, CString};
+use std::ptr::null;
+use std::sync::{Mutex, MutexGuard, PoisonError};
+use std::time::Duration;
+
+use enumflags2::BitFlags;
+
+use crate::hexchat::*;
+use crate::hexchat_entry_points::PHEXCHAT;
+use crate::hook::Hook;
+use crate::local_executor::local_executor_deinit;
+#[cfg(feature = "threadsafe")]
+use crate::thread_facilities::{main_thread_init, main_thread_deinit};
+
+/// Held by the live `MockHexchat` so only one can exist at a time.
+static MOCK_LOCK: Mutex<()> = Mutex::new(());
+
+/// The state of the fake client, shared by the fake C functions below.
+static MOCK_STATE: Mutex<Option<MockState>> = Mutex::new(None);
+
+/// The size Hexchat allocates for the `word` and `word_eol` arrays.
+const WORD_ARRAY_SIZE: usize = 32;
+
+/// The size of the buffer Hexchat plugins pass to `pluginpref_get_str()`.
+const PREF_VALUE_SIZE: usize = 512;
+
+/// The size of the buffer Hexchat plugins pass to `pluginpref_list()`.
+const PREF_LIST_SIZE: usize = 4096;
+
+/// The lists Hexchat provides. Other names get no list.
+const HEXCHAT_LISTS: [&str; 5] = ["channels", "dcc", "ignore", "notify",
+                                  "users"];
+
+/// The default context every `MockHexchat` starts with.
+const DEFAULT_NETWORK: &str = "MockNet";
+const DEFAULT_CHANNEL: &str = "#mock";
+
+/// Text printed to a context by the plugin, or a command it issued in one.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MockOutput {
+    pub network : String,
+    pub channel : String,
+    pub text    : String,
+}
+
+/// A text event emitted by the plugin using `emit_print()` or
+/// `emit_print_attrs()`.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MockEmit {
+    pub network : String,
+    pub channel : String,
+    pub event   : String,
+    pub args    : Vec<String>,
+}
+
+/// The value of a field of a mock list item. The variants correspond to the
+/// 's', 'i' and 't' field types of Hexchat's lists.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MockField {
+    Str     (String),
+    Int     (i32),
+    Time    (time_t),
+}
+
+impl MockField {
+    /// The type character Hexchat prepends to field names in
+    /// `hexchat_list_fields()`.
+    fn type_char(&self) -> char {
+        match self {
+            MockField::Str(_)  => 's',
+            MockField::Int(_)  => 'i',
+            MockField::Time(_) => 't',
+        }
+    }
+}
+
+impl From<&str> for MockField {
+    fn from(s: &str) -> Self { MockField::Str(s.to_string()) }
+}
+
+impl From<String> for MockField {
+    fn from(s: String) -> Self { MockField::Str(s) }
+}
+
+impl From<i32> for MockField {
+    fn from(i: i32) -> Self { MockField::Int(i) }
+}
+
+/// A Hexchat setting as returned by `get_prefs()`.
+///
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MockPref {
+    Str     (String),
+    Int     (i32),
+    Bool    (bool),
+}
+
+struct MockContext {
+    id      : usize,
+    network : String,
+    channel : String,
+    info    : HashMap<String, String>,
+}
+
+#[derive(Clone, Copy)]
+enum MockCallback {
+    Command     (C_Callback),
+    Server      (C_Callback),
+    ServerAttrs (C_ServerAttrCallback),
+    Print       (C_PrintCallback),
+    PrintAttrs  (C_AttrCallback),
+    Timer       (C_TimerCallback),
+    Fd          (C_FDCallback),
+}
+
+struct MockHook {
+    id          : usize,
+    name        : String,
+    pri         : i32,
+    callback    : MockCallback,
+    udata       : usize,
+    interval    : u64,
+    due         : u64,
+    fd          : c_int,
+    fd_flags    : c_int,
+}
+
+struct MockCursor {
+    items   : Vec<HashMap<String, MockField>>,
+    pos     : Option<usize>,
+    strings : Vec<CString>,
+}
+
+struct MockState {
+    next_id     : usize,
+    contexts    : Vec<MockContext>,
+    active      : usize,
+    info        : HashMap<String, String>,
+    prefs       : HashMap<String, MockPref>,
+    pluginprefs : Vec<(String, String)>,
+    lists       : HashMap<String, Vec<HashMap<String, MockField>>>,
+    hooks       : Vec<MockHook>,
+    plugins     : Vec<(usize, String)>,
+    clock_ms    : u64,
+    printed     : Vec<MockOutput>,
+    commands    : Vec<MockOutput>,
+    emitted     : Vec<MockEmit>,
+    sent        : Vec<String>,
+    // Strings handed out to the plugin that need to outlive the call.
+    strings     : Vec<CString>,
+    field_lists : HashMap<String, (Vec<CString>, Vec<*const c_char>)>,
+}
+
+// The raw pointers held in `field_lists` only ever point into the `CString`s
+// kept alongside them.
+unsafe impl Send for MockState {}
+
+impl MockState {
+    fn new() -> Self {
+        let mut info = HashMap::new();
+        info.insert("version".to_string(), "2.16.2".to_string());
+        info.insert("configdir".to_string(),
+                    std::env::temp_dir().to_string_lossy().into_owned());
+        info.insert("nick".to_string(), "mockuser".to_string());
+        MockState {
+            next_id     : 2,
+            contexts    : vec![MockContext {
+                              id      : 1,
+                              network : DEFAULT_NETWORK.to_string(),
+                              channel : DEFAULT_CHANNEL.to_string(),
+                              info    : HashMap::new(),
+                          }],
+            active      : 1,
+            info,
+            prefs       : HashMap::new(),
+            pluginprefs : vec![],
+            lists       : HashMap::new(),
+            hooks       : vec![],
+            plugins     : vec![],
+            clock_ms    : 0,
+            printed     : vec![],
+            commands    : vec![],
+            emitted     : vec![],
+            sent        : vec![],
+            strings     : vec![],
+            field_lists : HashMap::new(),
+        }
+    }
+
+    fn next_id(&mut self) -> usize {
+        let id = self.next_id;
+        self.next_id += 1;
+        id
+    }
+
+    fn active(&self) -> &MockContext {
+        self.contexts.iter().find(|c| c.id == self.active)
+            .unwrap_or(&self.contexts[0])
+    }
+
+    fn find(&self, network: &str, channel: &str) -> Option<&MockContext> {
+        self.contexts.iter().find(|c| {
+            c.network.eq_ignore_ascii_case(network)
+                && c.channel.eq_ignore_ascii_case(channel)
+        })
+    }
+
+    fn find_mut(&mut self, network: &str, channel: &str)
+        -> Option<&mut MockContext>
+    {
+        self.contexts.iter_mut().find(|c| {
+            c.network.eq_ignore_ascii_case(network)
+                && c.channel.eq_ignore_ascii_case(channel)
+        })
+    }
+
+    fn output(&self, text: &str) -> MockOutput {
+        let ctx = self.active();
+        MockOutput {
+            network : ctx.network.clone(),
+            channel : ctx.channel.clone(),
+            text    : text.to_string(),
+        }
+    }
+
+    /// Keeps `s` alive until the mock is dropped and returns a pointer to it.
+    fn keep(&mut self, s: &str) -> *const c_char {
+        let cs = CString::new(s.replace('\0', "")).unwrap();
+        let p  = cs.as_ptr();
+        self.strings.push(cs);
+        p
+    }
+
+    fn add_hook(&mut self,
+                name     : &str,
+                pri      : c_int,
+                callback : MockCallback,
+                udata    : *const c_void)
+        -> *const hexchat_hook
+    {
+        let id = self.next_id();
+        self.hooks.push(MockHook {
+            id,
+            name     : name.to_string(),
+            pri,
+            callback,
+            udata    : udata as usize,
+            interval : 0,
+            due      : 0,
+            fd       : 0,
+            fd_flags : 0,
+        });
+        // Hexchat keeps hooks ordered by priority; the sort is stable, so
+        // hooks of equal priority stay in registration order.
+        self.hooks.sort_by_key(|h| std::cmp::Reverse(h.pri));
+        id as *const hexchat_hook
+    }
+
+    fn has_hook(&self, id: usize) -> bool {
+        self.hooks.iter().any(|h| h.id == id)
+    }
+}
+
+/// Locks the mock's state and hands it to `f`. The lock must never be held
+/// while a plugin callback is running, since the callback will likely call
+/// back into one of the fake functions.
+fn with_state<F, R>(f: F) -> R
+where
+    F: FnOnce(&mut MockState) -> R
+{
+    let mut guard = MOCK_STATE.lock().unwrap_or_else(PoisonError::into_inner);
+    f(guard.as_mut().expect("No `MockHexchat` is alive."))
+}
+
+/// Holds the `word` or `word_eol` array passed to a callback. Hexchat always
+/// provides at least 32 entries padded with empty strings, and entry 0 is
+/// reserved.
+struct WordArray {
+    _strings : Vec<CString>,
+    pointers : Vec<*const c_char>,
+}
+
+impl WordArray {
+    fn new<S: AsRef<str>>(words: &[S]) -> Self {
+        let len = WORD_ARRAY_SIZE.max(words.len() + 2);
+        let mut strings = vec![CString::default()];
+        for w in words {
+            strings.push(CString::new(w.as_ref().replace('\0', "")).unwrap());
+        }
+        strings.resize(len, CString::default());
+        let pointers = strings.iter().map(|s| s.as_ptr()).collect();
+        WordArray { _strings: strings, pointers }
+    }
+
+    fn as_ptr(&self) -> *const *const c_char {
+        self.pointers.as_ptr()
+    }
+}
+
+/// Splits a line into Hexchat's `word` and `word_eol` lists.
+fn split_line(line: &str) -> (Vec<String>, Vec<String>) {
+    let mut word     = vec![];
+    let mut word_eol = vec![];
+    let mut rest     = line.trim_start_matches(' ');
+    while !rest.is_empty() {
+        word_eol.push(rest.to_string());
+        let end = rest.find(' ').unwrap_or(rest.len());
+        word.push(rest[..end].to_string());
+        rest = rest[end..].trim_start_matches(' ');
+    }
+    (word, word_eol)
+}
+
+/// Converts the raw return value of a callback, or the combination of several,
+/// to an `Eat` value.
+fn to_eat(value: c_int) -> Eat {
+    match value & (Eat::All as c_int) {
+        0 => Eat::None,
+        1 => Eat::Hexchat,
+        2 => Eat::Plugin,
+        _ => Eat::All,
+    }
+}
+
+/// Lowercases a nickname using the RFC 1459 case mapping.
+fn irc_lower(c: u8) -> u8 {
+    match c {
+        b'[' => b'{', b']' => b'}', b'\\' => b'|', b'~' => b'^',
+        _ => c.to_ascii_lowercase(),
+    }
+}
+
+/// Strips mIRC color codes and/or text attributes the same way Hexchat's
+/// `strip()` does.
+fn strip_text(text: &str, flags: c_int) -> String {
+    let colors = flags & StripFlags::StripMIrcColors as c_int != 0;
+    let attrs  = flags & StripFlags::StripTextAttributes as c_int != 0;
+    let mut out   = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x03' if colors => {
+                for _ in 0..2 {
+                    chars.next_if(|c| c.is_ascii_digit());
+                }
+                if chars.peek() == Some(&',') {
+                    let mut ahead = chars.clone();
+                    ahead.next();
+                    if ahead.peek().is_some_and(|c| c.is_ascii_digit()) {
+                        chars.next();
+                        for _ in 0..2 {
+                            chars.next_if(|c| c.is_ascii_digit());
+                        }
+                    }
+                }
+            },
+            '\x02' | '\x07' | '\x0F' | '\x11' | '\x16' | '\x1D' | '\x1E'
+            | '\x1F' if attrs => {},
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Invokes the callbacks of the hooks selected by `select` in priority order
+/// until one eats the event for the other plugins. The callbacks are invoked
+/// without the state lock held, and any hook unhooked by a previous callback
+/// is skipped.
+fn dispatch<S, C>(select: S, mut call: C) -> Eat
+where
+    S: Fn(&MockHook) -> bool,
+    C: FnMut(MockCallback, *mut c_void) -> c_int,
+{
+    let hooks: Vec<(usize, MockCallback, usize)> = with_state(|st| {
+        st.hooks.iter()
+                .filter(|h| select(h))
+                .map(|h| (h.id, h.callback, h.udata))
+                .collect()
+    });
+    let mut eat = 0;
+    for (id, callback, udata) in hooks {
+        if !with_state(|st| st.has_hook(id)) {
+            continue;
+        }
+        eat |= call(callback, udata as *mut c_void);
+        if eat & Eat::Plugin as c_int != 0 {
+            break;
+        }
+    }
+    to_eat(eat)
+}
+
+/// Runs the command hooks for `line`, which is a command without the leading
+/// slash.
+fn dispatch_command(line: &str) -> Eat {
+    let (word, word_eol) = split_line(line);
+    let Some(name) = word.first().cloned() else { return Eat::None };
+    let cword     = WordArray::new(&word);
+    let cword_eol = WordArray::new(&word_eol);
+    dispatch(|h| matches!(h.callback, MockCallback::Command(_))
+                 && h.name.eq_ignore_ascii_case(&name),
+             |cb, ud| match cb {
+                 MockCallback::Command(cb) => {
+                     cb(cword.as_ptr(), cword_eol.as_ptr(), ud)
+                 },
+                 _ => 0,
+             })
+}
+
+/// Runs the print hooks registered for `event`.
+fn dispatch_print(event: &str, args: &[String], time: time_t) -> Eat {
+    let cword = WordArray::new(args);
+    let attrs = EventAttrs { server_time_utc: time };
+    dispatch(|h| matches!(h.callback, MockCallback::Print(_)
+                                    | MockCallback::PrintAttrs(_))
+                 && h.name.eq_ignore_ascii_case(event),
+             |cb, ud| match cb {
+                 MockCallback::Print(cb)      => cb(cword.as_ptr(), ud),
+                 MockCallback::PrintAttrs(cb) => cb(cword.as_ptr(), &attrs, ud),
+                 _ => 0,
+             })
+}
+
+/// Runs the server hooks registered for the command of `line` and those
+/// registered for "RAW LINE".
+fn dispatch_server(line: &str, time: time_t) -> Eat {
+    // Hexchat consumes the IRCv3 message tags before the hooks see the line.
+    let line = if line.starts_with('@') {
+        line.find(' ').map_or("", |i| line[i..].trim_start_matches(' '))
+    } else {
+        line
+    };
+    let (word, word_eol) = split_line(line);
+    let command = if line.starts_with(':') { word.get(1) }
+                  else                     { word.first() };
+    let command = command.cloned().unwrap_or_default();
+    let cword     = WordArray::new(&word);
+    let cword_eol = WordArray::new(&word_eol);
+    let attrs     = EventAttrs { server_time_utc: time };
+    dispatch(|h| matches!(h.callback, MockCallback::Server(_)
+                                    | MockCallback::ServerAttrs(_))
+                 && (h.name.eq_ignore_ascii_case(&command)
+                     || h.name.eq_ignore_ascii_case("RAW LINE")),
+             |cb, ud| match cb {
+                 MockCallback::Server(cb) => {
+                     cb(cword.as_ptr(), cword_eol.as_ptr(), ud)
+                 },
+                 MockCallback::ServerAttrs(cb) => {
+                     cb(cword.as_ptr(), cword_eol.as_ptr(), &attrs, ud)
+                 },
+                 _ => 0,
+             })
+}
+
+/// A fake Hexchat client for unit tests. Creating one installs it as the host
+/// of the crate, and dropping it unloads the "plugin" again: all hooks are
+/// unhooked and their callback data dropped.
+///
+pub struct MockHexchat {
+    _guard: MutexGuard<'static, ()>,
+}
+
+impl MockHexchat {
+    /// Creates the fake client with one context, network "MockNet" and channel
+    /// "#mock", which is the active context. The crate is initialized the same
+    /// way it is when Hexchat loads a plugin, with the current thread as the
+    /// main thread.
+    ///
+    #[allow(clippy::new_without_default)]
+    pub fn new() -> Self {
+        let guard = MOCK_LOCK.lock().unwrap_or_else(PoisonError::into_inner);
+        *MOCK_STATE.lock().unwrap_or_else(PoisonError::into_inner)
+            = Some(MockState::new());
+        unsafe { PHEXCHAT = &MOCK_HEXCHAT; }
+        Hook::init();
+        #[cfg(feature = "threadsafe")]
+        main_thread_init();
+        MockHexchat { _guard: guard }
+    }
+
+    /// Returns the fake `Hexchat` to pass to the plugin's init function or
+    /// to register hooks with.
+    ///
+    pub fn hexchat(&self) -> &'static Hexchat {
+        &MOCK_HEXCHAT
+    }
+
+    /// Adds a context (a server tab, channel, or dialog) to the client.
+    /// Adding one that already exists does nothing.
+    ///
+    pub fn add_context(&self, network: &str, channel: &str) -> &Self {
+        with_state(|st| {
+            if st.find(network, channel).is_none() {
+                let id = st.next_id();
+                st.contexts.push(MockContext {
+                    id,
+                    network : network.to_string(),
+                    channel : channel.to_string(),
+                    info    : HashMap::new(),
+                });
+            }
+        });
+        self
+    }
+
+    /// Makes the given context the active one, as if the user had switched
+    /// to its tab. Returns `false` if the context doesn't exist.
+    ///
+    pub fn set_active_context(&self, network: &str, channel: &str) -> bool {
+        with_state(|st| {
+            let id = st.find(network, channel).map(|c| c.id);
+            if let Some(id) = id {
+                st.active = id;
+            }
+            id.is_some()
+        })
+    }
+
+    /// Sets the value `get_info()` returns for `id` while the given context
+    /// is active. The "network" and "channel" ids are provided by the context
+    /// itself. Returns `false` if the context doesn't exist.
+    ///
+    pub fn set_context_info(&self,
+                            network : &str,
+                            channel : &str,
+                            id      : &str,
+                            value   : &str)
+        -> bool
+    {
+        with_state(|st| {
+            if let Some(ctx) = st.find_mut(network, channel) {
+                ctx.info.insert(id.to_string(), value.to_string());
+                true
+            } else {
+                false
+            }
+        })
+    }
+
+    /// Sets the value `get_info()` returns for `id` in any context that
+    /// doesn't have its own value for it. "version", "configdir" and "nick"
+    /// have default values.
+    ///
+    pub fn set_info(&self, id: &str, value: &str) -> &Self {
+        with_state(|st| st.info.insert(id.to_string(), value.to_string()));
+        self
+    }
+
+    /// Sets the value of a client setting returned by `get_prefs()`.
+    ///
+    pub fn set_pref(&self, name: &str, value: MockPref) -> &Self {
+        with_state(|st| st.prefs.insert(name.to_string(), value));
+        self
+    }
+
+    /// Appends an item to the named list ("channels", "users", "dcc", etc.).
+    /// The list's fields are the union of the fields of its items; an item
+    /// missing a field returns an empty value for it.
+    ///
+    pub fn add_list_item<N, V>(&self, list: &str, fields: &[(N, V)]) -> &Self
+    where
+        N: AsRef<str>,
+        V: Into<MockField> + Clone,
+    {
+        let item = fields.iter()
+                         .map(|(n, v)| (n.as_ref().to_string(),
+                                        v.clone().into()))
+                         .collect();
+        with_state(|st| {
+            st.lists.entry(list.to_string()).or_default().push(item);
+        });
+        self
+    }
+
+    /// Removes all the items of the named list. Hexchat's own lists are then
+    /// empty, like a fresh client's; other names no longer exist.
+    ///
+    pub fn clear_list(&self, list: &str) -> &Self {
+        with_state(|st| st.lists.remove(list));
+        self
+    }
+
+    /// Returns the plugin prefs saved by the plugin, in the order they were
+    /// first written. The values are the raw strings Hexchat would write to
+    /// the plugin's config file.
+    ///
+    pub fn pluginprefs(&self) -> Vec<(String, String)> {
+        with_state(|st| st.pluginprefs.clone())
+    }
+
+    /// Runs the command hooks for `line` as if the user had typed it into the
+    /// input box. The leading slash is optional. Returns the combined `Eat`
+    /// value of the callbacks.
+    ///
+    pub fn inject_command(&self, line: &str) -> Eat {
+        dispatch_command(line.strip_prefix('/').unwrap_or(line))
+    }
+
+    /// Runs the print hooks for a text event, as if Hexchat were about to
+    /// display it. `args` are the event's arguments, e.g. the nick and text
+    /// of a "Channel Message".
+    ///
+    pub fn inject_print(&self, event: &str, args: &[&str]) -> Eat {
+        self.inject_print_attrs(event, args, 0)
+    }
+
+    /// Like `inject_print()`, but with a server time for the callbacks
+    /// registered with `hook_print_attrs()`.
+    ///
+    pub fn inject_print_attrs(&self,
+                              event       : &str,
+                              args        : &[&str],
+                              server_time : time_t)
+        -> Eat
+    {
+        let args: Vec<String> = args.iter().map(|s| s.to_string()).collect();
+        dispatch_print(event, &args, server_time)
+    }
+
+    /// Runs the server hooks for a raw line received from the server, like
+    /// `":nick!user@host PRIVMSG #mock :hello"`. Any IRCv3 message tags are
+    /// removed from the line before the callbacks see it, as Hexchat does.
+    ///
+    pub fn inject_server(&self, line: &str) -> Eat {
+        dispatch_server(line, 0)
+    }
+
+    /// Like `inject_server()`, but with a server time for the callbacks
+    /// registered with `hook_server_attrs()`.
+    ///
+    pub fn inject_server_attrs(&self, line: &str, server_time: time_t) -> Eat {
+        dispatch_server(line, server_time)
+    }
+
+    /// Runs the callbacks registered with `hook_fd()` for `fd` whose flags
+    /// intersect `flags`.
+    ///
+    pub fn inject_fd(&self, fd: i32, flags: BitFlags<FD>) -> Eat {
+        let bits = flags.bits() as c_int;
+        dispatch(|h| matches!(h.callback, MockCallback::Fd(_))
+                     && h.fd == fd
+                     && h.fd_flags & bits != 0,
+                 |cb, ud| match cb {
+                     MockCallback::Fd(cb) => cb(fd, bits, ud),
+                     _ => 0,
+                 })
+    }
+
+    /// Advances the mock's clock, invoking each timer callback as it comes
+    /// due, in the order they come due. Timers that return 0 are removed.
+    /// With the `threadsafe` feature, this is also what runs the tasks
+    /// queued by `main_thread()` from other threads.
+    ///
+    pub fn advance(&self, time: Duration) {
+        let end = with_state(|st| st.clock_ms + time.as_millis() as u64);
+        loop {
+            let next = with_state(|st| {
+                let hook = st.hooks.iter()
+                                   .filter(|h| matches!(h.callback,
+                                                        MockCallback::Timer(_))
+                                               && h.due <= end)
+                                   .min_by_key(|h| h.due)?;
+                let next = (hook.id, hook.callback, hook.udata, hook.due);
+                st.clock_ms = st.clock_ms.max(next.3);
+                Some(next)
+            });
+            let Some((id, MockCallback::Timer(cb), udata, _)) = next else {
+                break;
+            };
+            let keep_going = cb(udata as *mut c_void);
+            with_state(|st| {
+                if keep_going == 0 {
+                    st.hooks.retain(|h| h.id != id);
+                } else if let Some(h) = st.hooks.iter_mut()
+                                               .find(|h| h.id == id) {
+                    h.due += h.interval;
+                }
+            });
+        }
+        with_state(|st| st.clock_ms = end);
+    }
+
+    /// Returns the number of hooks currently registered.
+    ///
+    pub fn hook_count(&self) -> usize {
+        with_state(|st| st.hooks.len())
+    }
+
+    /// Returns everything printed so far along with the context it was
+    /// printed in.
+    ///
+    pub fn printed(&self) -> Vec<MockOutput> {
+        with_state(|st| st.printed.clone())
+    }
+
+    /// Returns the text of everything printed so far.
+    ///
+    pub fn printed_text(&self) -> Vec<String> {
+        with_state(|st| st.printed.iter().map(|o| o.text.clone()).collect())
+    }
+
+    /// Returns the commands issued so far along with the context they were
+    /// issued in.
+    ///
+    pub fn commands(&self) -> Vec<MockOutput> {
+        with_state(|st| st.commands.clone())
+    }
+
+    /// Returns the text events emitted so far.
+    ///
+    pub fn emitted(&self) -> Vec<MockEmit> {
+        with_state(|st| st.emitted.clone())
+    }
+
+    /// Returns the lines sent directly to the server, such as the MODE lines
+    /// produced by `send_modes()`.
+    ///
+    pub fn sent(&self) -> Vec<String> {
+        with_state(|st| st.sent.clone())
+    }
+
+    /// Clears the recorded prints, commands, emitted events and sent lines.
+    ///
+    pub fn clear_output(&self) {
+        with_state(|st| {
+            st.printed.clear();
+            st.commands.clear();
+            st.emitted.clear();
+            st.sent.clear();
+        });
+    }
+}
+
+impl Drop for MockHexchat {
+    /// Unloads the "plugin", unhooking its hooks, and removes the mock's
+    /// state.
+    fn drop(&mut self) {
+        #[cfg(feature = "threadsafe")]
+        main_thread_deinit();
+        local_executor_deinit();
+        Hook::deinit();
+        MOCK_STATE.lock().unwrap_or_else(PoisonError::into_inner).take();
+    }
+}
+
+/// Converts a string from the plugin, which may be null.
+fn cstr(p: *const c_char) -> Option<String> {
+    if p.is_null() {
+        None
+    } else {
+        Some(unsafe { CStr::from_ptr(p) }.to_string_lossy().into_owned())
+    }
+}
+
+extern "C" fn mock_hook_command(_hp   : *const Hexchat,
+                                name  : *const c_char,
+                                pri   : c_int,
+                                cb    : C_Callback,
+                                _help : *const c_char,
+                                udata : *mut c_void)
+    -> *const hexchat_hook
+{
+    let name = cstr(name).unwrap_or_default();
+    with_state(|st| st.add_hook(&name, pri, MockCallback::Command(cb), udata))
+}
+
+extern "C" fn mock_hook_server(_hp   : *const Hexchat,
+                               name  : *const c_char,
+                               pri   : c_int,
+                               cb    : C_Callback,
+                               udata : *mut c_void)
+    -> *const hexchat_hook
+{
+    let name = cstr(name).unwrap_or_default();
+    with_state(|st| st.add_hook(&name, pri, MockCallback::Server(cb), udata))
+}
+
+extern "C" fn mock_hook_print(_hp   : *const Hexchat,
+                              name  : *const c_char,
+                              pri   : c_int,
+                              cb    : C_PrintCallback,
+                              udata : *mut c_void)
+    -> *const hexchat_hook
+{
+    let name = cstr(name).unwrap_or_default();
+    with_state(|st| st.add_hook(&name, pri, MockCallback::Print(cb), udata))
+}
+
+extern "C" fn mock_hook_timer(_hp     : *const Hexchat,
+                              timeout : c_int,
+                              cb      : C_TimerCallback,
+                              udata   : *mut c_void)
+    -> *const hexchat_hook
+{
+    with_state(|st| {
+        let hook = st.add_hook("", 0, MockCallback::Timer(cb), udata);
+        let now  = st.clock_ms;
+        let h    = st.hooks.iter_mut().find(|h| h.id == hook as usize).unwrap();
+        // A zero timeout would fire endlessly within one `advance()`.
+        h.interval = (timeout.max(1)) as u64;
+        h.due      = now + h.interval;
+        hook
+    })
+}
+
+extern "C" fn mock_hook_fd(_hp   : *const Hexchat,
+                           fd    : c_int,
+                           flags : c_int,
+                           cb    : C_FDCallback,
+                           udata : *mut c_void)
+    -> *const hexchat_hook
+{
+    with_state(|st| {
+        let hook = st.add_hook("", 0, MockCallback::Fd(cb), udata);
+        let h    = st.hooks.iter_mut().find(|h| h.id == hook as usize).unwrap();
+        h.fd       = fd;
+        h.fd_flags = flags;
+        hook
+    })
+}
+
+extern "C" fn mock_unhook(_hp: *const Hexchat, hook: *const hexchat_hook)
+    -> *const c_void
+{
+    with_state(|st| {
+        let id = hook as usize;
+        let udata = st.hooks.iter().find(|h| h.id == id).map(|h| h.udata);
+        st.hooks.retain(|h| h.id != id);
+        udata.unwrap_or(0) as *const c_void
+    })
+}
+
+#[export_name = "hexchat_api_mock_print"]
+extern "C" fn mock_print(_hp: *const Hexchat, text: *const c_char) {
+    let text = cstr(text).unwrap_or_default();
+    with_state(|st| {
+        let out = st.output(&text);
+        st.printed.push(out);
+    });
+}
+
+#[export_name = "hexchat_api_mock_command"]
+extern "C" fn mock_command(_hp: *const Hexchat, command: *const c_char) {
+    let command = cstr(command).unwrap_or_default();
+    with_state(|st| {
+        let out = st.output(&command);
+        st.commands.push(out);
+    });
+    // Hexchat lets the plugins' command hooks handle the command first.
+    dispatch_command(&command);
+}
+
+extern "C" fn mock_nickcmp(_hp: *const Hexchat,
+                           s1  : *const c_char,
+                           s2  : *const c_char)
+    -> c_int
+{
+    let (s1, s2) = unsafe { (CStr::from_ptr(s1), CStr::from_ptr(s2)) };
+    let s1 = s1.to_bytes().iter().map(|&c| irc_lower(c));
+    let s2 = s2.to_bytes().iter().map(|&c| irc_lower(c));
+    s1.cmp(s2) as c_int
+}
+
+extern "C" fn mock_set_context(_hp: *const Hexchat, ctx: *const hexchat_context)
+    -> c_int
+{
+    with_state(|st| {
+        let id = ctx as usize;
+        if st.contexts.iter().any(|c| c.id == id) {
+            st.active = id;
+            1
+        } else {
+            0
+        }
+    })
+}
+
+extern "C" fn mock_find_context(_hp     : *const Hexchat,
+                                srvname : *const c_char,
+                                channel : *const c_char)
+    -> *const hexchat_context
+{
+    let srvname = cstr(srvname);
+    let channel = cstr(channel);
+    with_state(|st| {
+        let network = srvname.unwrap_or_else(|| st.active().network.clone());
+        st.contexts.iter()
+                   .filter(|c| c.network.eq_ignore_ascii_case(&network))
+                   .find(|c| channel.as_ref().is_none_or(|ch| {
+                       c.channel.eq_ignore_ascii_case(ch)
+                   }))
+                   .map_or(null(), |c| c.id as *const hexchat_context)
+    })
+}
+
+extern "C" fn mock_get_context(_hp: *const Hexchat) -> *const hexchat_context {
+    with_state(|st| st.active().id as *const hexchat_context)
+}
+
+extern "C" fn mock_get_info(_hp: *const Hexchat, id: *const c_char)
+    -> *const c_char
+{
+    let id = cstr(id).unwrap_or_default();
+    with_state(|st| {
+        let ctx   = st.active();
+        let value = match id.as_str() {
+            "network" => Some(ctx.network.clone()),
+            "channel" => Some(ctx.channel.clone()),
+            _ => ctx.info.get(&id).or_else(|| st.info.get(&id)).cloned(),
+        };
+        value.map_or(null(), |v| st.keep(&v))
+    })
+}
+
+extern "C" fn mock_get_prefs(_hp    : *const Hexchat,
+                             name   : *const c_char,
+                             string : *mut *const c_char,
+                             integer: *mut c_int)
+    -> c_int
+{
+    let name = cstr(name).unwrap_or_default();
+    with_state(|st| unsafe {
+        match st.prefs.get(&name).cloned() {
+            Some(MockPref::Str(s))  => { *string  = st.keep(&s); 1 },
+            Some(MockPref::Int(i))  => { *integer = i; 2 },
+            Some(MockPref::Bool(b)) => { *integer = b as c_int; 3 },
+            None => 0,
+        }
+    })
+}
+
+extern "C" fn mock_list_get(_hp: *const Hexchat, name: *const c_char)
+    -> *const hexchat_list
+{
+    let name = cstr(name).unwrap_or_default();
+    with_state(|st| {
+        // Hexchat's own lists exist even when they're empty.
+        let items = match st.lists.get(&name) {
+            Some(items) => items.clone(),
+            None if HEXCHAT_LISTS.contains(&name.as_str()) => vec![],
+            None => return null(),
+        };
+        let cursor = MockCursor {
+            items,
+            pos     : None,
+            strings : vec![],
+        };
+        Box::into_raw(Box::new(cursor)) as *const hexchat_list
+    })
+}
+
+extern "C" fn mock_list_free(_hp: *const Hexchat, hclist: *const hexchat_list) {
+    if !hclist.is_null() {
+        drop(unsafe { Box::from_raw(hclist as *mut MockCursor) });
+    }
+}
+
+extern "C" fn mock_list_fields(_hp: *const Hexchat, name: *const c_char)
+    -> *const *const c_char
+{
+    let name = cstr(name).unwrap_or_default();
+    with_state(|st| {
+        let mut fields: Vec<(String, char)> = vec![];
+        for item in st.lists.get(&name).into_iter().flatten() {
+            for (field, value) in item {
+                if !fields.iter().any(|(f, _)| f == field) {
+                    fields.push((field.clone(), value.type_char()));
+                }
+            }
+        }
+        let strings: Vec<CString> = fields.iter()
+            .map(|(f, t)| CString::new(format!("{}{}", t, f)).unwrap())
+            .collect();
+        let mut pointers: Vec<*const c_char>
+            = strings.iter().map(|s| s.as_ptr()).collect();
+        pointers.push(null());
+        let p = pointers.as_ptr();
+        st.field_lists.insert(name, (strings, pointers));
+        p
+    })
+}
+
+extern "C" fn mock_list_next(_hp: *const Hexchat, hclist: *const hexchat_list)
+    -> c_int
+{
+    let cursor = unsafe { &mut *(hclist as *mut MockCursor) };
+    let next   = cursor.pos.map_or(0, |p| p + 1);
+    cursor.pos = Some(next.min(cursor.items.len()));
+    (next < cursor.items.len()) as c_int
+}
+
+/// Returns the current item's value for `field`, if there is a current item.
+fn current_field(cursor: &MockCursor, field: *const c_char)
+    -> Option<MockField>
+{
+    let field = cstr(field).unwrap_or_default();
+    cursor.pos.and_then(|p| cursor.items.get(p))
+              .and_then(|item| item.get(&field).cloned())
+}
+
+extern "C" fn mock_list_str(_hp    : *const Hexchat,
+                            hclist : *const hexchat_list,
+                            field  : *const c_char)
+    -> *const c_char
+{
+    let cursor = unsafe { &mut *(hclist as *mut MockCursor) };
+    let s = match current_field(cursor, field) {
+        Some(MockField::Str(s)) => s,
+        _ => return null(),
+    };
+    let cs = CString::new(s.replace('\0', "")).unwrap();
+    let p  = cs.as_ptr();
+    cursor.strings.push(cs);
+    p
+}
+
+extern "C" fn mock_list_int(_hp    : *const Hexchat,
+                            hclist : *const hexchat_list,
+                            field  : *const c_char)
+    -> c_int
+{
+    let cursor = unsafe { &*(hclist as *const MockCursor) };
+    match current_field(cursor, field) {
+        Some(MockField::Int(i)) => i,
+        _ => -1,
+    }
+}
+
+extern "C" fn mock_list_time(_hp    : *const Hexchat,
+                             hclist : *const hexchat_list,
+                             field  : *const c_char)
+    -> time_t
+{
+    let cursor = unsafe { &*(hclist as *const MockCursor) };
+    match current_field(cursor, field) {
+        Some(MockField::Time(t)) => t,
+        _ => -1,
+    }
+}
+
+extern "C" fn mock_plugingui_add(_hp       : *const Hexchat,
+                                 _filename : *const c_char,
+                                 name      : *const c_char,
+                                 _desc     : *const c_char,
+                                 _version  : *const c_char,
+                                 _reserved : *const c_char)
+    -> *const c_void
+{
+    let name = cstr(name).unwrap_or_default();
+    with_state(|st| {
+        let id = st.next_id();
+        st.plugins.push((id, name));
+        id as *const c_void
+    })
+}
+
+extern "C" fn mock_plugingui_remove(_hp    : *const Hexchat,
+                                    handle : *const c_void)
+{
+    with_state(|st| st.plugins.retain(|(id, _)| *id != handle as usize));
+}
+
+/// Records an emitted text event and runs the print hooks for it. Called by
+/// the C versions of `emit_print()` and `emit_print_attrs()` with the
+/// event's arguments.
+///
+#[export_name = "hexchat_api_mock_emit"]
+extern "C" fn mock_emit(event : *const c_char,
+                        args  : *const *const c_char,
+                        count : c_int,
+                        time  : time_t)
+    -> c_int
+{
+    let event = cstr(event).unwrap_or_default();
+    let mut args: Vec<String> = (0..count.max(0) as usize)
+        .map(|i| cstr(unsafe { *args.add(i) }).unwrap_or_default())
+        .collect();
+    while args.last().is_some_and(|a| a.is_empty()) {
+        args.pop();
+    }
+    with_state(|st| {
+        let ctx = st.active();
+        let emit = MockEmit {
+            network : ctx.network.clone(),
+            channel : ctx.channel.clone(),
+            event   : event.clone(),
+            args    : args.clone(),
+        };
+        st.emitted.push(emit);
+    });
+    dispatch_print(&event, &args, time);
+    1
+}
+
+extern "C" fn mock_read_fd(_hp  : *const Hexchat,
+                           _src : *const c_void,
+                           _buf : *mut c_char,
+                           _len : *mut c_int)
+    -> c_int
+{
+    0
+}
+
+extern "C" fn mock_gettext(_hp: *const Hexchat, msgid: *const c_char)
+    -> *const c_char
+{
+    msgid
+}
+
+extern "C" fn mock_send_modes(_hp            : *const Hexchat,
+                              targets        : *const *const c_char,
+                              n_targets      : c_int,
+                              modes_per_line : c_int,
+                              sign           : c_char,
+                              mode           : c_char)
+{
+    let targets: Vec<String> = (0..n_targets.max(0) as usize)
+        .map(|i| cstr(unsafe { *targets.add(i) }).unwrap_or_default())
+        .collect();
+    let per_line = if modes_per_line > 0 { modes_per_line as usize } else { 3 };
+    with_state(|st| {
+        let channel = st.active().channel.clone();
+        for chunk in targets.chunks(per_line) {
+            let modes = (mode as u8 as char).to_string().repeat(chunk.len());
+            st.sent.push(format!("MODE {} {}{} {}", channel,
+                                 sign as u8 as char, modes, chunk.join(" ")));
+        }
+    });
+}
+
+extern "C" fn mock_strip(_hp    : *const Hexchat,
+                         string : *const c_char,
+                         len    : c_int,
+                         flags  : c_int)
+    -> *const c_char
+{
+    let bytes = unsafe { CStr::from_ptr(string) }.to_bytes();
+    let bytes = if len >= 0 { &bytes[..(len as usize).min(bytes.len())] }
+                else        { bytes };
+    let text  = String::from_utf8_lossy(bytes);
+    CString::new(strip_text(&text, flags)).unwrap().into_raw()
+}
+
+extern "C" fn mock_free(_hp: *const Hexchat, ptr: *const c_void) {
+    // `strip()` is the only function whose result the crate frees.
+    if !ptr.is_null() {
+        drop(unsafe { CString::from_raw(ptr as *mut c_char) });
+    }
+}
+
+/// Returns the index of a plugin pref, if it's been set.
+fn pref_index(st: &MockState, var: &str) -> Option<usize> {
+    st.pluginprefs.iter().position(|(n, _)| n == var)
+}
+
+fn set_pluginpref(var: *const c_char, value: String) -> c_int {
+    let var = cstr(var).unwrap_or_default();
+    with_state(|st| {
+        match pref_index(st, &var) {
+            Some(i) => st.pluginprefs[i].1 = value,
+            None    => st.pluginprefs.push((var, value)),
+        }
+    });
+    1
+}
+
+extern "C" fn mock_pluginpref_set_str(_hp   : *const Hexchat,
+                                      var   : *const c_char,
+                                      value : *const c_char)
+    -> c_int
+{
+    // Hexchat's config file has a pref per line, so a value read back ends
+    // at the first line break.
+    let value = cstr(value).unwrap_or_default();
+    let value = value.split(['\n', '\r']).next().unwrap_or_default();
+    set_pluginpref(var, value.to_string())
+}
+
+extern "C" fn mock_pluginpref_get_str(_hp  : *const Hexchat,
+                                      var  : *const c_char,
+                                      dest : *mut c_char)
+    -> c_int
+{
+    let var = cstr(var).unwrap_or_default();
+    with_state(|st| {
+        let Some(i) = pref_index(st, &var) else { return 0 };
+        let value = st.pluginprefs[i].1.as_bytes();
+        let len   = value.len().min(PREF_VALUE_SIZE - 1);
+        unsafe {
+            std::ptr::copy_nonoverlapping(value.as_ptr() as *const c_char,
+                                          dest, len);
+            *dest.add(len) = 0;
+        }
+        1
+    })
+}
+
+extern "C" fn mock_pluginpref_set_int(_hp   : *const Hexchat,
+                                      var   : *const c_char,
+                                      value : c_int)
+    -> c_int
+{
+    set_pluginpref(var, value.to_string())
+}
+
+extern "C" fn mock_pluginpref_get_int(_hp: *const Hexchat, var: *const c_char)
+    -> c_int
+{
+    let var = cstr(var).unwrap_or_default();
+    with_state(|st| {
+        pref_index(st, &var).map_or(-1, |i| {
+            // Hexchat uses `atoi()`, which yields 0 for non-numbers.
+            st.pluginprefs[i].1.trim().parse().unwrap_or(0)
+        })
+    })
+}
+
+extern "C" fn mock_pluginpref_delete(_hp: *const Hexchat, var: *const c_char)
+    -> c_int
+{
+    let var = cstr(var).unwrap_or_default();
+    with_state(|st| st.pluginprefs.retain(|(n, _)| *n != var));
+    1
+}
+
+extern "C" fn mock_pluginpref_list(_hp: *const Hexchat, dest: *mut c_char)
+    -> c_int
+{
+    with_state(|st| {
+        let mut list = String::new();
+        for (name, _) in &st.pluginprefs {
+            list.push_str(name);
+            list.push(',');
+        }
+        let len = list.len().min(PREF_LIST_SIZE - 1);
+        unsafe {
+            std::ptr::copy_nonoverlapping(list.as_ptr() as *const c_char,
+                                          dest, len);
+            *dest.add(len) = 0;
+        }
+        1
+    })
+}
+
+extern "C" fn mock_hook_server_attrs(_hp   : *const Hexchat,
+                                     name  : *const c_char,
+                                     pri   : c_int,
+                                     cb    : C_ServerAttrCallback,
+                                     udata : *const c_void)
+    -> *const hexchat_hook
+{
+    let name = cstr(name).unwrap_or_default();
+    with_state(|st| {
+        st.add_hook(&name, pri, MockCallback::ServerAttrs(cb), udata)
+    })
+}
+
+extern "C" fn mock_hook_print_attrs(_hp   : *const Hexchat,
+                                    name  : *const c_char,
+                                    pri   : c_int,
+                                    cb    : C_AttrCallback,
+                                    udata : *const c_void)
+    -> *const hexchat_hook
+{
+    let name = cstr(name).unwrap_or_default();
+    with_state(|st| {
+        st.add_hook(&name, pri, MockCallback::PrintAttrs(cb), udata)
+    })
+}
+
+extern "C" fn mock_event_attrs_create(_hp: *const Hexchat) -> *mut EventAttrs {
+    Box::into_raw(Box::new(EventAttrs { server_time_utc: 0 }))
+}
+
+extern "C" fn mock_event_attrs_free(_hp   : *const Hexchat,
+                                    attrs : *mut EventAttrs)
+{
+    if !attrs.is_null() {
+        drop(unsafe { Box::from_raw(attrs) });
+    }
+}
+
+// Hexchat's variadic functions, from `mock_variadic.c`.
+extern "C" {
+    fn hexchat_api_mock_printf(hp: *const Hexchat, format: *const c_char, ...);
+    fn hexchat_api_mock_commandf(hp     : *const Hexchat,
+                                 format : *const c_char, ...);
+    fn hexchat_api_mock_emit_print(hp    : *const Hexchat,
+                                   event : *const c_char, ...) -> c_int;
+    fn hexchat_api_mock_emit_print_attrs(hp    : *const Hexchat,
+                                         attrs : *const EventAttrs,
+                                         event : *const c_char, ...)
+        -> c_int;
+}
+
+/// The fake host installed as `PHEXCHAT` while a `MockHexchat` is alive.
+static MOCK_HEXCHAT: Hexchat = Hexchat {
+    c_hook_command       : mock_hook_command,
+    c_hook_server        : mock_hook_server,
+    c_hook_print         : mock_hook_print,
+    c_hook_timer         : mock_hook_timer,
+    c_hook_fd            : mock_hook_fd,
+    c_unhook             : mock_unhook,
+    c_print              : mock_print,
+    c_printf             : hexchat_api_mock_printf,
+    c_command            : mock_command,
+    c_commandf           : hexchat_api_mock_commandf,
+    c_nickcmp            : mock_nickcmp,
+    c_set_context        : mock_set_context,
+    c_find_context       : mock_find_context,
+    c_get_context        : mock_get_context,
+    c_get_info           : mock_get_info,
+    c_get_prefs          : mock_get_prefs,
+    c_list_get           : mock_list_get,
+    c_list_free          : mock_list_free,
+    c_list_fields        : mock_list_fields,
+    c_list_next          : mock_list_next,
+    c_list_str           : mock_list_str,
+    c_list_int           : mock_list_int,
+    c_plugingui_add      : mock_plugingui_add,
+    c_plugingui_remove   : mock_plugingui_remove,
+    c_emit_print         : hexchat_api_mock_emit_print,
+    c_read_fd            : mock_read_fd,
+    c_list_time          : mock_list_time,
+    c_gettext            : mock_gettext,
+    c_send_modes         : mock_send_modes,
+    c_strip              : mock_strip,
+    c_free               : mock_free,
+    c_pluginpref_set_str : mock_pluginpref_set_str,
+    c_pluginpref_get_str : mock_pluginpref_get_str,
+    c_pluginpref_set_int : mock_pluginpref_set_int,
+    c_pluginpref_get_int : mock_pluginpref_get_int,
+    c_pluginpref_delete  : mock_pluginpref_delete,
+    c_pluginpref_list    : mock_pluginpref_list,
+    c_hook_server_attrs  : mock_hook_server_attrs,
+    c_hook_print_attrs   : mock_hook_print_attrs,
+    c_emit_print_attrs   : hexchat_api_mock_emit_print_attrs,
+    c_event_attrs_create : mock_event_attrs_create,
+    c_event_attrs_free   : mock_event_attrs_free,
+};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::user_data::UserData::NoData;
+
+    #[test]
+    fn inject_command() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        hc.hook_command("greet", Priority::Norm, |hc, word, _, _| {
+            hc.print(&format!("Hello, {}!", word[1]));
+            Eat::All
+        }, "", NoData);
+
+        assert_eq!(mock.inject_command("/GREET world"), Eat::All);
+        assert_eq!(mock.printed_text(), vec!["Hello, world!"]);
+        assert_eq!(mock.inject_command("other"), Eat::None);
+    }
+
+    #[test]
+    fn inject_print_and_emit_print() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        hc.hook_print("Channel Message", Priority::Norm, |hc, word, _| {
+            hc.print(&format!("<{}> {}", word[0], word[1]));
+            Eat::Hexchat
+        }, NoData);
+
+        assert_eq!(mock.inject_print("Channel Message", &["bob", "hi"]),
+                   Eat::Hexchat);
+        assert!(hc.emit_print("Channel Message", &["ann", "hey", ""]).is_ok());
+
+        assert_eq!(mock.printed_text(), vec!["<bob> hi", "<ann> hey"]);
+        let emitted = mock.emitted();
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(emitted[0].event, "Channel Message");
+        assert_eq!(emitted[0].args, vec!["ann", "hey"]);
+        assert_eq!(emitted[0].channel, "#mock");
+    }
+
+    #[test]
+    fn inject_server_attrs() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        hc.hook_server_attrs("PRIVMSG", Priority::Norm,
+                             |hc, word, _, attrs, _| {
+            if attrs.server_time_utc == 0 {
+                return Err("no server time");
+            }
+            hc.print(&format!("{} at {}", word[3], attrs.server_time_utc));
+            Ok(Eat::Hexchat)
+        }, NoData);
+
+        let line = ":bob!b@host PRIVMSG #mock :hi";
+        assert_eq!(mock.inject_server_attrs(line, 1_700_000_000), Eat::Hexchat);
+        assert_eq!(mock.printed_text(), vec![":hi at 1700000000"]);
+
+        mock.clear_output();
+        mock.inject_server(line);
+        assert!(mock.printed_text()[0].contains("no server time"));
+    }
+
+    #[test]
+    fn advance_runs_due_timers() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        let mut count = 0;
+        hc.hook_timer(100, move |hc, _| {
+            count += 1;
+            hc.print(&format!("tick {}", count));
+            if count < 3 { 1 } else { 0 }
+        }, NoData);
+        let hooks = mock.hook_count();
+
+        mock.advance(Duration::from_millis(99));
+        assert!(mock.printed_text().is_empty());
+        mock.advance(Duration::from_millis(1));
+        assert_eq!(mock.printed_text(), vec!["tick 1"]);
+        mock.advance(Duration::from_millis(500));
+        assert_eq!(mock.printed_text(), vec!["tick 1", "tick 2", "tick 3"]);
+        assert_eq!(mock.hook_count(), hooks - 1);
+    }
+
+    #[test]
+    fn prefs() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        mock.set_pref("irc_nick1", MockPref::Str("mocker".to_string()));
+
+        assert_eq!(hc.get_prefs("irc_nick1").map(|p| p.str()),
+                   Some("mocker".to_string()));
+        assert!(hc.pluginpref_set("greeting",
+                                  PrefValue::StringVal("hi".to_string())));
+        assert!(hc.pluginpref_set_int("count", 3));
+        assert_eq!(hc.pluginpref_get("greeting").map(|p| p.str()),
+                   Some("hi".to_string()));
+        assert_eq!(hc.pluginpref_get_int("count"), Some(3));
+        assert_eq!(mock.pluginprefs().len(), 2);
+
+        assert!(hc.pluginpref_delete("count"));
+        assert_eq!(hc.pluginpref_get_int("count"), None);
+        assert_eq!(hc.pluginpref_list(), Some(vec!["greeting".to_string()]));
+    }
+
+    #[test]
+    fn variadic_functions_format_their_args() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+        unsafe {
+            (hc.c_printf)(hc, c"%s has %d ops".as_ptr(),
+                          c"#mock".as_ptr(), 2 as c_int);
+            (hc.c_commandf)(hc, c"JOIN %s".as_ptr(), c"#rust".as_ptr());
+        }
+        assert_eq!(mock.printed_text(), vec!["#mock has 2 ops"]);
+        assert_eq!(mock.commands()[0].text, "JOIN #rust");
+    }
+
+    #[test]
+    fn hexchat_lists_start_empty() {
+        let mock = MockHexchat::new();
+        let hc   = mock.hexchat();
+
+        assert_eq!(hc.list_get("users").map(|list| list.count()), Some(0));
+        assert!(hc.list_get("not a list").is_none());
+
+        mock.add_list_item("users", &[("nick", "bob")]);
+        assert_eq!(hc.list_get("users").map(|list| list.count()), Some(1));
+    }
+}
@@ -0,0 +1,833 @@
+//! Typed Hexchat text events. Each event listed in Hexchat's Settings > Text
+//! Events dialog has a variant in `TextEvent` with a named field for each of
+//! its arguments, and a matching variant in `TextEventKind` that identifies
+//! the event without its arguments. These are used with
+//! `Hexchat::hook_text_event()` and `Hexchat::emit_text_event()` in place of
+//! event name strings and positional argument slices.
+
+/// Generates `TextEvent`, `TextEventKind`, and the conversions between them
+/// and Hexchat's event names and argument lists from the table below. Field
+/// docs are the argument descriptions shown in Hexchat's Text Events dialog.
+///
+macro_rules! text_events {
+    ($( $variant:ident = $name:literal {
+            $( $field:ident : $desc:literal ),* $(,)?
+        } )*
+    ) => {
+        /// A Hexchat text event along with its arguments.
+        ///
+        #[derive(Clone, Debug, PartialEq, Eq)]
+        pub enum TextEvent {
+            $(
+                #[doc = concat!("The \"", $name, "\" text event.")]
+                $variant { $( #[doc = $desc] $field : String ),* },
+            )*
+        }
+
+        /// Identifies a Hexchat text event. Used to hook an event with
+        /// `Hexchat::hook_text_event()`.
+        ///
+        #[derive(Copy, Clone, Debug, PartialEq, Eq, Hash)]
+        pub enum TextEventKind {
+            $(
+                #[doc = concat!("The \"", $name, "\" text event.")]
+                $variant,
+            )*
+        }
+
+        impl TextEventKind {
+            /// Every text event kind.
+            pub const ALL: &'static [TextEventKind] = &[
+                $( TextEventKind::$variant, )*
+            ];
+
+            /// Returns the name Hexchat uses for the event.
+            ///
+            pub fn name(&self) -> &'static str {
+                match self {
+                    $( TextEventKind::$variant => $name, )*
+                }
+            }
+
+            /// Returns the names of the event's arguments in the order
+            /// Hexchat passes them.
+            ///
+            pub fn arg_names(&self) -> &'static [&'static str] {
+                match self {
+                    $( TextEventKind::$variant => &[$( stringify!($field) ),*],
+                    )*
+                }
+            }
+
+            /// Looks up an event kind by the name Hexchat uses for it. The
+            /// comparison is case insensitive, as it is in Hexchat.
+            /// # Arguments
+            /// * `name` - The name of the text event, e.g. "Channel Message".
+            /// # Returns
+            /// * `Some(TextEventKind)` if the name is a known text event,
+            ///   `None` otherwise.
+            ///
+            pub fn from_name(name: &str) -> Option<Self> {
+                Self::ALL.iter()
+                         .find(|k| k.name().eq_ignore_ascii_case(name))
+                         .copied()
+            }
+        }
+
+        impl TextEvent {
+            /// Returns the kind of the event.
+            ///
+            pub fn kind(&self) -> TextEventKind {
+                match self {
+                    $( TextEvent::$variant { .. } => TextEventKind::$variant,
+                    )*
+                }
+            }
+
+            /// Returns the event's arguments in the order Hexchat expects
+            /// them.
+            ///
+            #[allow(unused_variables)]
+            pub fn args(&self) -> Vec<&str> {
+                match self {
+                    $( TextEvent::$variant { $( $field ),* } => {
+                        vec![$( $field.as_str() ),*]
+                    }, )*
+                }
+            }
+
+            /// Builds an event of the given kind from the argument list
+            /// passed to a print hook callback. Arguments are assigned by
+            /// position, so an empty argument needs to stay in the list to
+            /// keep the ones after it in place. Missing arguments are left
+            /// empty, and extra ones are ignored.
+            /// # Arguments
+            /// * `kind` - The kind of event to build.
+            /// * `args` - The event's arguments as positional strings.
+            /// # Returns
+            /// * The typed event.
+            ///
+            #[allow(unused_variables, unused_mut)]
+            pub fn from_args(kind: TextEventKind, args: &[String]) -> Self {
+                let mut args = args.iter().cloned();
+                match kind {
+                    $( TextEventKind::$variant => TextEvent::$variant {
+                        $( $field : args.next().unwrap_or_default() ),*
+                    }, )*
+                }
+            }
+        }
+    };
+}
+
+text_events! {
+    AddNotify = "Add Notify" {
+        nick    : "Nickname",
+        network : "Network",
+    }
+    BanList = "Ban List" {
+        channel : "Channel",
+        mask    : "Banmask",
+        setter  : "Who set the ban",
+        time    : "Ban time",
+    }
+    Banned = "Banned" {
+        channel : "Channel Name",
+    }
+    Beep = "Beep" {}
+    CapabilityAcknowledgement = "Capability Acknowledgement" {
+        server       : "Server Name",
+        capabilities : "Acknowledged Capabilities",
+    }
+    CapabilityDeleted = "Capability Deleted" {
+        server       : "Server Name",
+        capabilities : "Removed Capabilities",
+    }
+    CapabilityList = "Capability List" {
+        server       : "Server Name",
+        capabilities : "Capabilities",
+    }
+    CapabilityRequest = "Capability Request" {
+        capabilities : "Capabilities",
+    }
+    ChangeNick = "Change Nick" {
+        old_nick : "Old nickname",
+        new_nick : "New nickname",
+    }
+    ChannelAction = "Channel Action" {
+        nick       : "Nickname",
+        text       : "The action",
+        mode       : "Mode char",
+        identified : "Identified text",
+    }
+    ChannelActionHilight = "Channel Action Hilight" {
+        nick       : "Nickname",
+        text       : "The action",
+        mode       : "Mode char",
+        identified : "Identified text",
+    }
+    ChannelBan = "Channel Ban" {
+        nick : "The nick of the person who did the banning",
+        mask : "The ban mask",
+    }
+    ChannelCreation = "Channel Creation" {
+        channel : "The channel",
+        time    : "The time",
+    }
+    ChannelDeHalfOp = "Channel DeHalfOp" {
+        nick   : "The nick of the person who removed halfop status",
+        target : "The nick of the person who has been dehalfop'ed",
+    }
+    ChannelDeOp = "Channel DeOp" {
+        nick   : "The nick of the person who did the deop'ing",
+        target : "The nick of the person who has been deop'ed",
+    }
+    ChannelDeVoice = "Channel DeVoice" {
+        nick   : "The nick of the person who did the devoice'ing",
+        target : "The nick of the person who has been devoice'ed",
+    }
+    ChannelExempt = "Channel Exempt" {
+        nick : "The nick of the person who did the exempt",
+        mask : "The exempt mask",
+    }
+    ChannelHalfOperator = "Channel Half-Operator" {
+        nick   : "The nick of the person who gave halfop status",
+        target : "The nick of the person who has been halfop'ed",
+    }
+    ChannelInvite = "Channel INVITE" {
+        nick : "The nick of the person who did the invite",
+        mask : "The invite mask",
+    }
+    ChannelList = "Channel List" {}
+    ChannelMessage = "Channel Message" {
+        nick       : "Nickname",
+        text       : "The text",
+        mode       : "Mode char",
+        identified : "Identified text",
+    }
+    ChannelModeGeneric = "Channel Mode Generic" {
+        nick    : "The nick of the person setting the mode",
+        sign    : "The mode's sign (+/-)",
+        mode    : "The mode letter",
+        channel : "The channel it's being set on",
+    }
+    ChannelModes = "Channel Modes" {
+        channel : "Channel Name",
+        modes   : "Modes string",
+    }
+    ChannelMsgHilight = "Channel Msg Hilight" {
+        nick       : "Nickname",
+        text       : "The text",
+        mode       : "Mode char",
+        identified : "Identified text",
+    }
+    ChannelNotice = "Channel Notice" {
+        nick    : "Who it's from",
+        channel : "The Channel it's going to",
+        text    : "The message",
+    }
+    ChannelOperator = "Channel Operator" {
+        nick   : "The nick of the person who did the op'ing",
+        target : "The nick of the person who has been op'ed",
+    }
+    ChannelQuiet = "Channel Quiet" {
+        nick : "The nick of the person who did the quiet",
+        mask : "The quiet mask",
+    }
+    ChannelRemoveExempt = "Channel Remove Exempt" {
+        nick : "The nick of the person removed the exempt",
+        mask : "The exempt mask",
+    }
+    ChannelRemoveInvite = "Channel Remove Invite" {
+        nick : "The nick of the person removed the invite",
+        mask : "The invite mask",
+    }
+    ChannelRemoveKeyword = "Channel Remove Keyword" {
+        nick : "The nick who removed the key",
+    }
+    ChannelRemoveLimit = "Channel Remove Limit" {
+        nick : "The nick who removed the limit",
+    }
+    ChannelSetKey = "Channel Set Key" {
+        nick : "The nick of the person who changed the key",
+        key  : "The key",
+    }
+    ChannelSetLimit = "Channel Set Limit" {
+        nick  : "The nick of the person who changed the limit",
+        limit : "The limit",
+    }
+    ChannelUnBan = "Channel UnBan" {
+        nick : "The nick of the person who did the unban'ing",
+        mask : "The ban mask",
+    }
+    ChannelUnQuiet = "Channel UnQuiet" {
+        nick : "The nick of the person who did the unquiet'ing",
+        mask : "The quiet mask",
+    }
+    ChannelUrl = "Channel Url" {
+        channel : "Channel Name",
+        url     : "URL",
+    }
+    ChannelVoice = "Channel Voice" {
+        nick   : "The nick of the person who did the voice'ing",
+        target : "The nick of the person who has been voice'ed",
+    }
+    Connected = "Connected" {}
+    Connecting = "Connecting" {
+        host : "Host",
+        ip   : "IP",
+        port : "Port",
+    }
+    ConnectionFailed = "Connection Failed" {
+        error : "Error",
+    }
+    CtcpGeneric = "CTCP Generic" {
+        event : "The CTCP event",
+        nick  : "The nick of the person",
+    }
+    CtcpGenericToChannel = "CTCP Generic to Channel" {
+        event   : "The CTCP event",
+        nick    : "The nick of the person",
+        channel : "The Channel it's going to",
+    }
+    CtcpSend = "CTCP Send" {
+        receiver : "Receiver",
+        text     : "Message",
+    }
+    CtcpSound = "CTCP Sound" {
+        sound   : "The sound",
+        nick    : "The nick of the person",
+        channel : "The channel",
+    }
+    CtcpSoundToChannel = "CTCP Sound to Channel" {
+        sound   : "The sound",
+        nick    : "The nick of the person",
+        channel : "The channel",
+    }
+    DccChatAbort = "DCC CHAT Abort" {
+        nick : "Nickname",
+    }
+    DccChatConnect = "DCC CHAT Connect" {
+        nick : "Nickname",
+        ip   : "IP address",
+    }
+    DccChatFailed = "DCC CHAT Failed" {
+        nick  : "Nickname",
+        ip    : "IP address",
+        port  : "Port",
+        error : "Error",
+    }
+    DccChatOffer = "DCC CHAT Offer" {
+        nick : "Nickname",
+    }
+    DccChatOffering = "DCC CHAT Offering" {
+        nick : "Nickname",
+    }
+    DccChatReoffer = "DCC CHAT Reoffer" {
+        nick : "Nickname",
+    }
+    DccConnectionFailed = "DCC Conection Failed" {
+        dcc_type : "DCC Type",
+        nick     : "Nickname",
+        error    : "Error",
+    }
+    DccGenericOffer = "DCC Generic Offer" {
+        offer : "DCC String",
+        nick  : "Nickname",
+    }
+    DccHeader = "DCC Header" {}
+    DccMalformed = "DCC Malformed" {
+        nick   : "Nickname",
+        packet : "The Packet",
+    }
+    DccOffer = "DCC Offer" {
+        file : "Filename",
+        nick : "Nickname",
+        path : "Pathname",
+    }
+    DccOfferNotValid = "DCC Offer Not Valid" {}
+    DccRecvAbort = "DCC RECV Abort" {
+        nick : "Nickname",
+        file : "Filename",
+    }
+    DccRecvComplete = "DCC RECV Complete" {
+        file        : "Filename",
+        destination : "Destination filename",
+        nick        : "Nickname",
+        cps         : "CPS",
+    }
+    DccRecvConnect = "DCC RECV Connect" {
+        nick : "Nickname",
+        ip   : "IP address",
+        file : "Filename",
+    }
+    DccRecvFailed = "DCC RECV Failed" {
+        file        : "Filename",
+        destination : "Destination filename",
+        nick        : "Nickname",
+        error       : "Error",
+    }
+    DccRecvFileOpenError = "DCC RECV File Open Error" {
+        file  : "Filename",
+        error : "Error",
+    }
+    DccRename = "DCC Rename" {
+        old_file : "Old Filename",
+        new_file : "New Filename",
+    }
+    DccResumeRequest = "DCC RESUME Request" {
+        nick     : "Nickname",
+        file     : "Filename",
+        position : "Position",
+    }
+    DccSendAbort = "DCC SEND Abort" {
+        nick : "Nickname",
+        file : "Filename",
+    }
+    DccSendComplete = "DCC SEND Complete" {
+        file : "Filename",
+        nick : "Nickname",
+        cps  : "CPS",
+    }
+    DccSendConnect = "DCC SEND Connect" {
+        nick : "Nickname",
+        ip   : "IP address",
+        file : "Filename",
+    }
+    DccSendFailed = "DCC SEND Failed" {
+        file  : "Filename",
+        nick  : "Nickname",
+        error : "Error",
+    }
+    DccSendOffer = "DCC SEND Offer" {
+        nick : "Nickname",
+        file : "Filename",
+        size : "Size",
+        ip   : "IP address",
+    }
+    DccStall = "DCC Stall" {
+        dcc_type : "DCC Type",
+        file     : "Filename",
+        nick     : "Nickname",
+    }
+    DccTimeout = "DCC Timeout" {
+        dcc_type : "DCC Type",
+        file     : "Filename",
+        nick     : "Nickname",
+    }
+    DeleteNotify = "Delete Notify" {
+        nick    : "Nickname",
+        network : "Network",
+    }
+    Disconnected = "Disconnected" {
+        error : "Error",
+    }
+    FoundIp = "Found IP" {
+        ip : "IP",
+    }
+    GenericMessage = "Generic Message" {
+        left  : "Left message",
+        right : "Right message",
+    }
+    IgnoreAdd = "Ignore Add" {
+        mask : "Hostmask",
+    }
+    IgnoreChanged = "Ignore Changed" {
+        mask : "Hostmask",
+    }
+    IgnoreFooter = "Ignore Footer" {}
+    IgnoreHeader = "Ignore Header" {}
+    IgnoreRemove = "Ignore Remove" {
+        mask : "Hostmask",
+    }
+    IgnorelistEmpty = "Ignorelist Empty" {}
+    Invite = "Invite" {
+        channel : "Channel Name",
+    }
+    Invited = "Invited" {
+        channel : "Channel Name",
+        nick    : "Nick of person who invited you",
+        server  : "Server Name",
+    }
+    Join = "Join" {
+        nick    : "The nick of the joining person",
+        channel : "The channel being joined",
+        host    : "The host of the person",
+        account : "The account of the person",
+    }
+    Keyword = "Keyword" {
+        keyword : "Keyword",
+    }
+    Kick = "Kick" {
+        nick    : "The nickname of the kicker",
+        target  : "The person being kicked",
+        channel : "The channel",
+        reason  : "The reason",
+    }
+    Killed = "Killed" {
+        nick   : "Nickname",
+        reason : "Reason",
+    }
+    MessageSend = "Message Send" {
+        receiver : "Receiver",
+        text     : "Message",
+    }
+    Motd = "Motd" {
+        text : "Text",
+    }
+    MotdSkipped = "MOTD Skipped" {}
+    NickClash = "Nick Clash" {
+        nick     : "Nickname in use",
+        new_nick : "Nick being tried",
+    }
+    NickErroneous = "Nick Erroneous" {
+        nick : "Nickname",
+    }
+    NickFailed = "Nick Failed" {}
+    NoDcc = "No DCC" {}
+    NoRunningProcess = "No Running Process" {}
+    Notice = "Notice" {
+        nick : "Who it's from",
+        text : "The message",
+    }
+    NoticeSend = "Notice Send" {
+        receiver : "Receiver",
+        text     : "Message",
+    }
+    NotifyAway = "Notify Away" {
+        nick   : "Nickname",
+        reason : "Away Reason",
+    }
+    NotifyBack = "Notify Back" {
+        nick : "Nickname",
+    }
+    NotifyEmpty = "Notify Empty" {}
+    NotifyHeader = "Notify Header" {}
+    NotifyNumber = "Notify Number" {
+        count : "Number of notify items",
+    }
+    NotifyOffline = "Notify Offline" {
+        nick    : "Nickname",
+        server  : "Server Name",
+        network : "Network",
+    }
+    NotifyOnline = "Notify Online" {
+        nick    : "Nickname",
+        server  : "Server Name",
+        network : "Network",
+    }
+    OpenDialog = "Open Dialog" {}
+    Part = "Part" {
+        nick    : "The nick of the person leaving",
+        host    : "The host of the person",
+        channel : "The channel",
+    }
+    PartWithReason = "Part with Reason" {
+        nick    : "The nick of the person leaving",
+        host    : "The host of the person",
+        channel : "The channel",
+        reason  : "The reason",
+    }
+    PingReply = "Ping Reply" {
+        nick : "Who it's from",
+        time : "The time in x.x format",
+    }
+    PingTimeout = "Ping Timeout" {
+        seconds : "Seconds",
+    }
+    PrivateAction = "Private Action" {
+        nick       : "Nickname",
+        text       : "The message",
+        identified : "Identified text",
+    }
+    PrivateActionToDialog = "Private Action to Dialog" {
+        nick       : "Nickname",
+        text       : "The message",
+        identified : "Identified text",
+    }
+    PrivateMessage = "Private Message" {
+        nick       : "Nickname",
+        text       : "The message",
+        identified : "Identified text",
+    }
+    PrivateMessageToDialog = "Private Message to Dialog" {
+        nick       : "Nickname",
+        text       : "The message",
+        identified : "Identified text",
+    }
+    ProcessAlreadyRunning = "Process Already Running" {}
+    Quit = "Quit" {
+        nick   : "Nick",
+        reason : "Reason",
+        host   : "Host",
+    }
+    RawModes = "Raw Modes" {
+        nick  : "Nickname",
+        modes : "Modes string",
+    }
+    ReceiveWallops = "Receive Wallops" {
+        nick : "Nickname",
+        text : "The message",
+    }
+    ResolvingUser = "Resolving User" {
+        nick : "Nickname",
+        host : "Hostname",
+    }
+    SaslAuthenticating = "SASL Authenticating" {
+        account   : "Account",
+        mechanism : "Mechanism",
+    }
+    SaslResponse = "SASL Response" {
+        server  : "Server Name",
+        numeric : "Raw Numeric",
+        user    : "Username",
+        text    : "Message",
+    }
+    ServerConnected = "Server Connected" {}
+    ServerError = "Server Error" {
+        text : "Text",
+    }
+    ServerLookup = "Server Lookup" {
+        server : "Server Name",
+    }
+    ServerNotice = "Server Notice" {
+        text   : "Text",
+        server : "Server Name",
+    }
+    ServerText = "Server Text" {
+        text    : "Text",
+        server  : "Server Name",
+        numeric : "Raw Numeric",
+    }
+    SslMessage = "SSL Message" {
+        text   : "Text",
+        server : "Server Name",
+    }
+    StopConnection = "Stop Connection" {
+        ip : "IP",
+    }
+    Topic = "Topic" {
+        channel : "Channel",
+        topic   : "Topic",
+    }
+    TopicChange = "Topic Change" {
+        nick    : "Nick of person who changed the topic",
+        topic   : "Topic",
+        channel : "Channel",
+    }
+    TopicCreation = "Topic Creation" {
+        channel : "The channel",
+        creator : "The creator",
+        time    : "The time",
+    }
+    UnknownHost = "Unknown Host" {}
+    UserLimit = "User Limit" {
+        channel : "Channel Name",
+    }
+    UsersOnChannel = "Users On Channel" {
+        channel : "Channel Name",
+        users   : "Users",
+    }
+    WhoIsAuthenticated = "WhoIs Authenticated" {
+        nick    : "Nickname",
+        text    : "Message",
+        account : "Account",
+    }
+    WhoIsAwayLine = "WhoIs Away Line" {
+        nick   : "Nickname",
+        reason : "Away reason",
+    }
+    WhoIsChannelOperLine = "WhoIs Channel/Oper Line" {
+        nick : "Nickname",
+        text : "Channel Membership/\"is an IRC operator\"",
+    }
+    WhoIsEnd = "WhoIs End" {
+        nick : "Nickname",
+    }
+    WhoIsIdentified = "WhoIs Identified" {
+        nick : "Nickname",
+        text : "Message",
+    }
+    WhoIsIdleLine = "WhoIs Idle Line" {
+        nick : "Nickname",
+        idle : "Idle time",
+    }
+    WhoIsIdleLineWithSignon = "WhoIs Idle Line with Signon" {
+        nick   : "Nickname",
+        idle   : "Idle time",
+        signon : "Signon time",
+    }
+    WhoIsNameLine = "WhoIs Name Line" {
+        nick      : "Nickname",
+        user      : "Username",
+        host      : "Host",
+        full_name : "Full name",
+    }
+    WhoIsRealHost = "WhoIs Real Host" {
+        nick      : "Nickname",
+        real_host : "Real user@host",
+        real_ip   : "Real IP",
+        text      : "Message",
+    }
+    WhoIsServerLine = "WhoIs Server Line" {
+        nick   : "Nickname",
+        server : "Server Information",
+    }
+    WhoIsSpecial = "WhoIs Special" {
+        nick    : "Nickname",
+        text    : "Message",
+        numeric : "Numeric",
+    }
+    YouJoin = "You Join" {
+        nick    : "The nick of the joining person",
+        channel : "The channel being joined",
+        host    : "The host of the person",
+        account : "The account of the person",
+    }
+    YouKicked = "You Kicked" {
+        target  : "The person being kicked",
+        channel : "The channel",
+        nick    : "The nickname of the kicker",
+        reason  : "The reason",
+    }
+    YouPart = "You Part" {
+        nick    : "The nick of the person leaving",
+        host    : "The host of the person",
+        channel : "The channel",
+    }
+    YouPartWithReason = "You Part with Reason" {
+        nick    : "The nick of the person leaving",
+        host    : "The host of the person",
+        channel : "The channel",
+        reason  : "The reason",
+    }
+    YourAction = "Your Action" {
+        nick : "Nickname",
+        text : "The action",
+        mode : "Mode char",
+    }
+    YourInvitation = "Your Invitation" {
+        nick    : "Nick of person who have been invited",
+        channel : "Channel Name",
+        server  : "Server Name",
+    }
+    YourMessage = "Your Message" {
+        nick       : "Nickname",
+        text       : "The text",
+        mode       : "Mode char",
+        identified : "Identified text",
+    }
+    YourNickChanging = "Your Nick Changing" {
+        old_nick : "Old nickname",
+        new_nick : "New nickname",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_and_args_agree() {
+        for &kind in TextEventKind::ALL {
+            assert_eq!(TextEventKind::from_name(kind.name()), Some(kind));
+            let args: Vec<String> = kind.arg_names()
+                                        .iter()
+                                        .map(|a| a.to_string())
+                                        .collect();
+            let event = TextEvent::from_args(kind, &args);
+            assert_eq!(event.kind(), kind);
+            assert_eq!(event.args(), kind.arg_names());
+        }
+        assert_eq!(TextEventKind::from_name("channel MESSAGE"),
+                   Some(TextEventKind::ChannelMessage));
+        assert_eq!(TextEventKind::from_name("No Such Event"), None);
+    }
+
+    #[test]
+    fn missing_args_are_empty() {
+        let event = TextEvent::from_args(TextEventKind::ChangeNick,
+                                         &["amy".to_string()]);
+        assert_eq!(event, TextEvent::ChangeNick {
+            old_nick : "amy".into(),
+            new_nick : String::new(),
+        });
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn hook_and_emit_text_events() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        use crate::{Eat, Priority, UserData::NoData};
+
+        let mock = crate::testing::MockHexchat::new();
+        let hc   = mock.hexchat();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let s    = seen.clone();
+        let _hook = hc.hook_text_event(TextEventKind::ChangeNick,
+                                       Priority::Norm,
+                                       move |_hc, event, _ud| {
+                                           s.borrow_mut().push(event.clone());
+                                           Eat::None
+                                       }, NoData);
+
+        mock.inject_print("Change Nick", &["amy", "amelia"]);
+        let event = TextEvent::ChangeNick { old_nick : "amy".into(),
+                                            new_nick : "amelia".into() };
+        assert_eq!(*seen.borrow(), vec![event.clone()]);
+
+        hc.emit_text_event(&event).unwrap();
+        let emitted = mock.emitted();
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(emitted[0].event, "Change Nick");
+        assert_eq!(emitted[0].args, vec!["amy", "amelia"]);
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn empty_args_keep_their_place() {
+        use std::cell::RefCell;
+        use std::rc::Rc;
+        use crate::{Eat, Priority, UserData::NoData};
+
+        let mock = crate::testing::MockHexchat::new();
+        let hc   = mock.hexchat();
+        let seen = Rc::new(RefCell::new(vec![]));
+        let s    = seen.clone();
+        let _hook = hc.hook_text_event(TextEventKind::ChannelMessage,
+                                       Priority::Norm,
+                                       move |_hc, event, _ud| {
+                                           s.borrow_mut().push(event.clone());
+                                           Eat::None
+                                       }, NoData);
+
+        mock.inject_print("Channel Message", &["bob", "hi", "", "+"]);
+        let event = TextEvent::ChannelMessage { nick       : "bob".into(),
+                                                text       : "hi".into(),
+                                                mode       : String::new(),
+                                                identified : "+".into() };
+        assert_eq!(*seen.borrow(), vec![event.clone()]);
+
+        hc.emit_text_event(&event).unwrap();
+        assert_eq!(mock.emitted()[0].args, vec!["bob", "hi", "", "+"]);
+    }
+
+    #[cfg(all(feature = "testing", feature = "threadsafe"))]
+    #[test]
+    fn emit_text_event_from_another_thread() {
+        use std::thread;
+        use std::time::Duration;
+        use crate::ThreadSafeContext;
+
+        let mock   = crate::testing::MockHexchat::new();
+        let ctx    = ThreadSafeContext::get().unwrap();
+        let event  = TextEvent::Beep {};
+        let handle = thread::spawn(move || ctx.emit_text_event(&event));
+        while !handle.is_finished() {
+            mock.advance(Duration::from_millis(10));
+        }
+        handle.join().unwrap().unwrap();
+        assert_eq!(mock.emitted()[0].event, "Beep");
+    }
+}
@@ -14,14 +14,26 @@
 //! thread via an `AsyncResult` object. This can either be ignored, and
 //! the thread can continue doing other work, or `AsyncResult.get()` can be
 //! invoked on the result object; this call will block until the main thread
-//! has finished executing the callback.
+//! has finished executing the callback. Async code can instead `.await` the
+//! `AsyncResult`.
+//!
+//! `main_thread_after()` does the same after a delay, and also returns a
+//! `CancelHandle` for calling the callback off before it runs.
 
+use std::any::Any;
 use std::collections::LinkedList;
-use std::sync::{Arc, Condvar, Mutex};
+use std::future::Future;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::pin::Pin;
+use std::sync::{Arc, Condvar, Mutex, MutexGuard, PoisonError};
+use std::task::{Context, Poll, Waker};
 use std::thread;
+use std::time::Duration;
 
 use crate::hexchat::Hexchat;
 use crate::hexchat_entry_points::PHEXCHAT;
+use crate::hook::Hook;
+use crate::hook_group::outside_groups;
 use crate::{user_data::*, HexchatError};
 
 use UserData::*;
@@ -38,6 +50,14 @@ type TaskQueue = LinkedList<Box<dyn Task>>;
 ///
 static TASK_QUEUE: Mutex<Option<TaskQueue>> = Mutex::new(None);
 
+/// Locks the task queue. The lock is recovered if it was poisoned, so a
+/// panic while it was held can't take the queue, and the plugin's shutdown,
+/// down with it.
+///
+fn task_queue() -> MutexGuard<'static, Option<TaskQueue>> {
+    TASK_QUEUE.lock().unwrap_or_else(PoisonError::into_inner)
+}
+
 /// The main thread's ID is captured and used by `main_thread()` to determine
 /// whether it is being called from the main thread or not. If not, the
 /// callback can be invoked right away. Otherwise, it gets scheduled.
@@ -93,6 +113,21 @@ where
     }
 }
 
+impl<F, R> Drop for ConcreteTask<F, R>
+where
+    F: FnMut(&Hexchat) -> R,
+    R: Clone + Send,
+{
+    /// Sets an error on the result of a task that was dropped without
+    /// running, so any thread waiting on it isn't left blocked.
+    ///
+    fn drop(&mut self) {
+        if !self.result.is_done() {
+            self.result.set_error("The task was dropped before it completed.");
+        }
+    }
+}
+
 unsafe impl<F, R> Send for ConcreteTask<F, R>
 where
     F: FnMut(&Hexchat) -> R,
@@ -105,10 +140,22 @@ where
 /// on the completion of a callback, thus providing synchronization between
 /// threads.
 ///
-#[allow(clippy::type_complexity)]
+/// `AsyncResult` is also a `Future`, so code running on an async executor can
+/// `.await` it instead of blocking its thread in `get()`. It shouldn't be
+/// awaited from an executor that blocks Hexchat's main thread, since the
+/// callback it's waiting on needs that thread to run.
+///
 #[derive(Clone)]
 pub struct AsyncResult<T: Clone + Send> {
-    data: Arc<(Mutex<(Option<Result<T, HexchatError>>, bool)>, Condvar)>,
+    data: Arc<(Mutex<ResultState<T>>, Condvar)>,
+}
+
+/// The state shared between an `AsyncResult` and its clones.
+///
+struct ResultState<T> {
+    result : Option<Result<T, HexchatError>>,
+    done   : bool,
+    wakers : Vec<Waker>,
 }
 
 unsafe impl<T: Clone + Send> Send for AsyncResult<T> {}
@@ -119,8 +166,9 @@ impl<T: Clone + Send> AsyncResult<T> {
     ///
     pub (crate)
     fn new() -> Self {
+        let state = ResultState { result: None, done: false, wakers: vec![] };
         AsyncResult {
-            data: Arc::new((Mutex::new((None, false)), Condvar::new()))
+            data: Arc::new((Mutex::new(state), Condvar::new()))
         }
     }
     /// Indicates whether the callback executing on another thread is done or
@@ -128,35 +176,67 @@ impl<T: Clone + Send> AsyncResult<T> {
     ///
     pub fn is_done(&self) -> bool {
         let (mtx, _) = &*self.data;
-        mtx.lock().unwrap().1
+        mtx.lock().unwrap().done
     }
     /// Blocking call to retrieve the return data from a callback on another
-    /// thread.
+    /// thread. Each clone of the result, and each call, gets its own copy of
+    /// the data.
     ///
     pub fn get(&self) -> Result<T, HexchatError> {
         let (mtx, cvar) = &*self.data;
         let mut guard   = mtx.lock().unwrap();
-        while !guard.1 {
+        while !guard.done {
             guard = cvar.wait(guard).unwrap();
         }
-        guard.0.take().unwrap()
+        guard.result.clone().unwrap()
     }
     /// Sets the return data for the async result. This will unblock the
-    /// receiver waiting on the result from `get()`.
+    /// receiver waiting on the result from `get()`, or wake the task awaiting
+    /// it.
     ///
     pub (crate)
     fn set(&self, result: T) {
-        let (mtx, cvar) = &*self.data;
-        let mut guard   = mtx.lock().unwrap();
-               *guard   = (Some(Ok(result)), true);
-        cvar.notify_all();
+        self.complete(Ok(result));
     }
     fn set_error(&self, error: &str) {
         use HexchatError::ThreadSafeOperationFailed as Error;
+        self.complete(Err(Error(error.into())));
+    }
+    /// Stores the result and notifies whoever is waiting on it.
+    ///
+    fn complete(&self, result: Result<T, HexchatError>) {
         let (mtx, cvar) = &*self.data;
-        let mut guard   = mtx.lock().unwrap();
-               *guard   = (Some(Err(Error(error.into()))), true);
+        let wakers = {
+            let mut guard = mtx.lock().unwrap();
+            guard.result  = Some(result);
+            guard.done    = true;
+            std::mem::take(&mut guard.wakers)
+        };
         cvar.notify_all();
+        for waker in wakers {
+            waker.wake();
+        }
+    }
+}
+
+impl<T: Clone + Send> Future for AsyncResult<T> {
+    type Output = Result<T, HexchatError>;
+
+    /// Resolves to the return value of the callback once it has run. Clones
+    /// of the result can be awaited by several tasks, which are all woken
+    /// and each get a copy of the value.
+    ///
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let (mtx, _)  = &*self.data;
+        let mut guard = mtx.lock().unwrap();
+        if guard.done {
+            Poll::Ready(guard.result.clone().unwrap())
+        } else {
+            if !guard.wakers.iter().any(|w| w.will_wake(cx.waker())) {
+                guard.wakers.push(cx.waker().clone());
+            }
+            Poll::Pending
+        }
     }
 }
 
@@ -181,7 +261,7 @@ where
     } else {
         let res = AsyncResult::new();
         let cln = res.clone();
-        if let Some(queue) = TASK_QUEUE.lock().unwrap().as_mut() {
+        if let Some(queue) = task_queue().as_mut() {
             let task = Box::new(ConcreteTask::new(callback, cln));
             queue.push_back(task);
         }
@@ -192,6 +272,200 @@ where
     }
 }
 
+/// Executes a closure on the Hexchat main thread after a delay. Like
+/// `main_thread()`, this can be called from any thread and returns
+/// immediately. The delay starts when the main thread picks up the task.
+///
+/// # Arguments
+/// * `delay`    - How long to wait before running the callback.
+/// * `callback` - The callback to execute on the main thread.
+///
+/// # Returns
+/// * A `CancelHandle` that can cancel the callback before it runs, and an
+///   `AsyncResult` that receives its return value. If the callback is
+///   cancelled, or never runs because the plugin is unloaded, the result is
+///   an error.
+///
+pub fn main_thread_after<F, R>(delay: Duration, callback: F)
+    -> (CancelHandle, AsyncResult<R>)
+where
+    F: FnOnce(&Hexchat) -> R + Send + 'static,
+    R: 'static + Clone + Send,
+{
+    let result = AsyncResult::new();
+    let handle = CancelHandle::new(result.clone());
+    let mut task = DelayedTask {
+        delay,
+        call: Some(DelayedCall {
+            callback : Some(callback),
+            result   : result.clone(),
+            state    : handle.state.clone(),
+        }),
+    };
+    if Some(thread::current().id()) == unsafe { MAIN_THREAD_ID } {
+        task.execute(unsafe { &*PHEXCHAT });
+    } else if let Some(queue) = task_queue().as_mut() {
+        queue.push_back(Box::new(task));
+    } else {
+        task.set_error("Task queue has been shut down.");
+    }
+    (handle, result)
+}
+
+/// A handle that cancels a callback scheduled with `main_thread_after()`.
+/// Clones share the same callback.
+///
+#[derive(Clone)]
+pub struct CancelHandle {
+    state: Arc<Mutex<DelayedState>>,
+}
+
+/// The state shared between a `CancelHandle` and the callback it cancels.
+///
+struct DelayedState {
+    status    : DelayedStatus,
+    hook      : Option<Hook>,
+    on_cancel : Option<Box<dyn FnOnce() + Send>>,
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum DelayedStatus {
+    Pending,
+    Started,
+    Cancelled,
+}
+
+impl CancelHandle {
+    fn new<R: 'static + Clone + Send>(result: AsyncResult<R>) -> Self {
+        let on_cancel = move || result.set_error("The task was cancelled.");
+        CancelHandle {
+            state: Arc::new(Mutex::new(DelayedState {
+                       status    : DelayedStatus::Pending,
+                       hook      : None,
+                       on_cancel : Some(Box::new(on_cancel)),
+                   })),
+        }
+    }
+
+    /// Cancels the callback, unless it has already started running. Its
+    /// `AsyncResult` is then set to an error.
+    /// # Returns
+    /// * `true` if the callback was cancelled by this call.
+    ///
+    pub fn cancel(&self) -> bool {
+        let (on_cancel, hook) = {
+            let mut state = self.state.lock().unwrap();
+            if state.status != DelayedStatus::Pending {
+                return false;
+            }
+            state.status = DelayedStatus::Cancelled;
+            (state.on_cancel.take(), state.hook.take())
+        };
+        if let Some(on_cancel) = on_cancel {
+            on_cancel();
+        }
+        if let Some(hook) = hook {
+            // The timer can only be unhooked from the main thread.
+            main_thread(move |_hc| { hook.unhook(); });
+        }
+        true
+    }
+
+    /// Returns `true` if the callback was cancelled.
+    ///
+    pub fn is_cancelled(&self) -> bool {
+        self.state.lock().unwrap().status == DelayedStatus::Cancelled
+    }
+}
+
+/// A task that sets the timer for a `main_thread_after()` callback once it
+/// reaches the main thread.
+///
+struct DelayedTask<F, R>
+where
+    F: FnOnce(&Hexchat) -> R,
+    R: 'static + Clone + Send,
+{
+    delay : Duration,
+    call  : Option<DelayedCall<F, R>>,
+}
+
+impl<F, R> Task for DelayedTask<F, R>
+where
+    F: FnOnce(&Hexchat) -> R + Send + 'static,
+    R: 'static + Clone + Send,
+{
+    /// Hooks the timer, unless the callback was cancelled while the task was
+    /// queued.
+    ///
+    fn execute(&mut self, hexchat: &Hexchat) {
+        if let Some(call) = self.call.take() {
+            let state = call.state.clone();
+            let mut state = state.lock().unwrap();
+            if state.status == DelayedStatus::Pending {
+                let delay  = self.delay;
+                state.hook = Some(outside_groups(|| {
+                    hexchat.run_after(delay, move |hc| call.run(hc))
+                }));
+            }
+        }
+    }
+
+    fn set_error(&mut self, error: &str) {
+        if let Some(call) = self.call.take() {
+            call.result.set_error(error);
+        }
+    }
+}
+
+/// A callback waiting on its timer. If it's dropped without having set its
+/// result, because it panicked or its timer was unhooked, the result is set
+/// to an error so nothing waits on it forever.
+///
+struct DelayedCall<F, R>
+where
+    F: FnOnce(&Hexchat) -> R,
+    R: 'static + Clone + Send,
+{
+    callback : Option<F>,
+    result   : AsyncResult<R>,
+    state    : Arc<Mutex<DelayedState>>,
+}
+
+impl<F, R> DelayedCall<F, R>
+where
+    F: FnOnce(&Hexchat) -> R,
+    R: 'static + Clone + Send,
+{
+    /// Runs the callback, unless it has been cancelled.
+    ///
+    fn run(mut self, hexchat: &Hexchat) {
+        {
+            let mut state = self.state.lock().unwrap();
+            if state.status != DelayedStatus::Pending {
+                return;
+            }
+            state.status = DelayedStatus::Started;
+            state.hook   = None;
+        }
+        if let Some(callback) = self.callback.take() {
+            self.result.set(callback(hexchat));
+        }
+    }
+}
+
+impl<F, R> Drop for DelayedCall<F, R>
+where
+    F: FnOnce(&Hexchat) -> R,
+    R: 'static + Clone + Send,
+{
+    fn drop(&mut self) {
+        if !self.result.is_done() {
+            self.result.set_error("The task was dropped before it completed.");
+        }
+    }
+}
+
 /// This initializes the fundamental thread-safe features of this library.
 /// A mutex guarded task queue is created, and a timer function is registered
 /// that handles the queue at intervals. If a thread requires fast response,
@@ -202,32 +476,50 @@ pub (crate)
 fn main_thread_init() {
     unsafe { MAIN_THREAD_ID = Some(thread::current().id()) }
 
-    if TASK_QUEUE.lock().unwrap().is_none() {
-        *TASK_QUEUE.lock().unwrap() = Some(LinkedList::new());
+    if task_queue().is_none() {
+        *task_queue() = Some(LinkedList::new());
         let hex = unsafe { &*PHEXCHAT };
 
         hex.hook_timer(
             TASK_REST_MSECS,
             move |_hc, _ud| {
-                if let Some(task_queue) = TASK_QUEUE.lock().unwrap().as_mut() {
-                    let mut count = 1;
-
-                    while let Some(mut task) = task_queue.pop_front() {
-                        task.execute(hex);
-                        count += 1;
-                        if count > TASK_SPURT_SIZE {
-                            break
-                        }
+                for _ in 0..TASK_SPURT_SIZE {
+                    // The queue isn't held while the task runs, so a task
+                    // that panics can't poison it.
+                    let task = match task_queue().as_mut() {
+                        Some(task_queue) => task_queue.pop_front(),
+                        None => return 0, // Task queue is gone, remove timer.
+                    };
+                    let Some(mut task) = task else { break };
+
+                    // A panic is contained to its task, so it can't stop the
+                    // timer the other tasks depend on.
+                    let run = catch_unwind(AssertUnwindSafe(|| {
+                        task.execute(hex)
+                    }));
+                    if let Err(payload) = run {
+                        task.set_error(&format!("The task panicked: {}",
+                                                panic_message(&*payload)));
                     }
-                    1 // Keep going.
-                } else {
-                    0 // Task queue is gone, remove timer callback.
                 }
+                1 // Keep going.
             },
             NoData);
     }
 }
 
+/// Returns the message a panic was raised with, if it was given one.
+///
+fn panic_message(payload: &(dyn Any + Send)) -> String {
+    if let Some(s) = payload.downcast_ref::<&str>() {
+        s.to_string()
+    } else if let Some(s) = payload.downcast_ref::<String>() {
+        s.clone()
+    } else {
+        "(no message)".to_string()
+    }
+}
+
 /// Called when the an addon is being unloaded. This eliminates the task queue.
 /// Any holders of `AsyncResult` objects that are blocked on `.get()` may be
 /// waiting forever. This can be called from addons if the thread-safe
@@ -236,7 +528,7 @@ fn main_thread_init() {
 ///
 pub (crate)
 fn main_thread_deinit() {
-    if let Some(mut queue) = TASK_QUEUE.lock().unwrap().take() {
+    if let Some(mut queue) = task_queue().take() {
         while let Some(mut task) = queue.pop_front() {
             task.set_error("Task queue is being shut down.");
         }
@@ -269,3 +561,103 @@ fn main_thread_deinit() {
 pub unsafe fn turn_off_threadsafe_features() {
     main_thread_deinit();
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::task::Wake;
+
+    use super::*;
+
+    struct CountingWaker(AtomicUsize);
+
+    impl Wake for CountingWaker {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::SeqCst);
+        }
+    }
+
+    fn poll<T: Clone + Send>(result: &mut AsyncResult<T>,
+                             waker: &Arc<CountingWaker>)
+        -> Poll<Result<T, HexchatError>>
+    {
+        let waker = Waker::from(waker.clone());
+        Pin::new(result).poll(&mut Context::from_waker(&waker))
+    }
+
+    #[test]
+    fn clones_all_get_the_result() {
+        let result    = AsyncResult::new();
+        let mut first = result.clone();
+        let mut other = result.clone();
+        let wakers    = [Arc::new(CountingWaker(AtomicUsize::new(0))),
+                         Arc::new(CountingWaker(AtomicUsize::new(0)))];
+
+        assert!(poll(&mut first, &wakers[0]).is_pending());
+        assert!(poll(&mut first, &wakers[0]).is_pending());
+        assert!(poll(&mut other, &wakers[1]).is_pending());
+        result.set(7);
+        assert_eq!(wakers[0].0.load(Ordering::SeqCst), 1);
+        assert_eq!(wakers[1].0.load(Ordering::SeqCst), 1);
+
+        assert!(matches!(poll(&mut first, &wakers[0]), Poll::Ready(Ok(7))));
+        assert!(matches!(poll(&mut other, &wakers[1]), Poll::Ready(Ok(7))));
+        assert_eq!(result.get().unwrap(), 7);
+        assert_eq!(result.get().unwrap(), 7);
+    }
+
+    #[test]
+    fn errors_reach_every_clone() {
+        let result = AsyncResult::<i32>::new();
+        let other  = result.clone();
+        let waiter = thread::spawn(move || other.get());
+        result.set_error("failed");
+        assert!(waiter.join().unwrap().is_err());
+        assert!(result.is_done());
+        assert!(result.get().is_err());
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn delayed_callbacks_run_once_after_the_delay() {
+        let mock = crate::testing::MockHexchat::new();
+        let (handle, result) = thread::spawn(|| {
+            main_thread_after(Duration::from_millis(50), |hc| {
+                hc.print("ran");
+                5
+            })
+        }).join().unwrap();
+
+        while !result.is_done() {
+            assert!(mock.printed_text().is_empty());
+            mock.advance(Duration::from_millis(10));
+        }
+        assert_eq!(result.get().unwrap(), 5);
+        assert_eq!(mock.printed_text(), vec!["ran"]);
+        assert!(!handle.cancel());
+        assert!(!handle.is_cancelled());
+    }
+
+    #[cfg(feature = "testing")]
+    #[test]
+    fn cancelled_callbacks_never_run() {
+        let mock   = crate::testing::MockHexchat::new();
+        let timers = mock.hook_count();
+        let (handle, result) = main_thread_after(Duration::from_millis(50),
+                                                 |hc| hc.print("ran"));
+        assert_eq!(mock.hook_count(), timers + 1);
+
+        let other = handle.clone();
+        assert!(handle.cancel());
+        assert!(!other.cancel());
+        assert!(other.is_cancelled());
+        assert!(result.get().is_err());
+        assert_eq!(mock.hook_count(), timers);
+
+        let hook = mock.hexchat().run_after(Duration::from_millis(50),
+                                            |hc| hc.print("ran too"));
+        hook.unhook();
+        mock.advance(Duration::from_millis(100));
+        assert!(mock.printed_text().is_empty());
+    }
+}
@@ -12,6 +12,8 @@ use send_wrapper::SendWrapper;
 
 use crate::HexchatError;
 use crate::context::*;
+use crate::hexchat::ModeSign;
+use crate::text_events::TextEvent;
 use crate::thread_facilities::*;
 use crate::threadsafe_list_iterator::*;
 
@@ -115,6 +117,31 @@ impl ThreadSafeContext {
         }).get().and_then(|r| r)
     }
 
+    /// Sends MODE lines for the channel held by the `ThreadSafeContext`
+    /// object, setting or unsetting `mode` on each of the targets. See
+    /// `Hexchat::send_modes()`.
+    ///
+    pub fn send_modes(&self,
+                      targets        : &[&str],
+                      modes_per_line : i32,
+                      sign           : ModeSign,
+                      mode           : char)
+        -> Result<(), HexchatError>
+    {
+        let targets: Vec<String> = targets.iter()
+                                          .map(|s| s.to_string())
+                                          .collect();
+        let me = self.clone();
+        main_thread(move |_| {
+            let targets: Vec<&str> = targets.iter()
+                                            .map(|s| s.as_str())
+                                            .collect();
+            me.ctx.read().unwrap().as_ref()
+                  .ok_or_else(|| ContextDropped(DROPPED_ERR.into()))?
+                  .send_modes(&targets, modes_per_line, sign, mode)
+        }).get().and_then(|r| r)
+    }
+
     /// Gets information from the channel/window that the `ThreadSafeContext`
     /// object holds an internal pointer to.
     ///
@@ -149,6 +176,20 @@ impl ThreadSafeContext {
         }).get().and_then(|r| r)
     }
 
+    /// Issues a text event in the context held by the `ThreadSafeContext`.
+    ///
+    pub fn emit_text_event(&self, event: &TextEvent)
+        -> Result<(), HexchatError>
+    {
+        let event = event.clone();
+        let me = self.clone();
+        main_thread(move |_| {
+            me.ctx.read().unwrap().as_ref()
+                  .ok_or_else(|| ContextDropped(DROPPED_ERR.into()))?
+                  .emit_text_event(&event)
+        }).get().and_then(|r| r)
+    }
+
     /// Gets a `ThreadSafeListIterator` from the context.  If the list doesn't
     /// exist, or a problem occurs, an error will be returned.
     ///
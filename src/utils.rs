@@ -169,6 +169,34 @@ fn argv2svec(pchar: *const *const c_char, start: usize) -> Vec<String>
     }
 }
 
+/// ```*const *const c_char -> Vec<String>```
+///
+/// Like `argv2svec()`, but reads up to `count` strings and keeps the empty
+/// ones. Hexchat pads the `word` array of print events with empty strings, so
+/// an event argument can be empty without ending the list of arguments.
+///
+/// # Arguments
+/// * `pchar`   - The C array of character pointers.
+/// * `start`   - Which offset in the pchar array to start at.
+/// * `count`   - The number of strings to read.
+///
+/// # Returns
+/// A String Vec of at most `count` items, shorter only if `pchar` ends first.
+///
+pub (crate)
+fn argv2svec_count(pchar: *const *const c_char, start: usize, count: usize)
+    -> Vec<String>
+{
+    unsafe {
+        (start..start + count).map(|i| *pchar.add(i))
+                              .take_while(|pval| !pval.is_null())
+                              .map(|pval| CStr::from_ptr(pval)
+                                                .to_string_lossy()
+                                                .into_owned())
+                              .collect()
+    }
+}
+
 /// ```&CString -> String``` creates a new String from a CString.
 /// Some strings coming from Hexchat my contain invalid characters. This
 /// function guards against them offecting the system by replacing those